@@ -0,0 +1,98 @@
+use bevy::audio::{Pitch, PitchBundle};
+use bevy::prelude::*;
+use bevy_crossterm::prelude::*;
+use bevy_crossterm::CrosstermKeyEventWrapper;
+
+use bevy::log::LogPlugin;
+use crossterm::event::{KeyCode, KeyEventKind};
+use std::default::Default;
+use std::time::Duration;
+
+// Play notes with the number keys, to check sounds start on time and play smoothly under the crossterm runner.
+// Needs the "audio" feature: `cargo run --example audio --features audio`
+
+// A major scale, starting at middle C
+const NOTES: [f32; 8] = [261.63, 293.66, 329.63, 349.23, 392.0, 440.0, 493.88, 523.25];
+
+pub fn main() {
+    // Window settings must happen before the crossterm Plugin
+    let mut settings = CrosstermWindowSettings::default();
+    settings.set_title("Audio example");
+    // Run slowly while nothing is happening. Playing sounds counts as something happening, so they aren't held up
+    settings.set_idle_pacing(IdlePacing {
+        wait: Duration::from_millis(500),
+        after: Duration::from_secs(1),
+    });
+
+    App::new()
+        .insert_resource(settings)
+        .add_plugins(bevy_app::ScheduleRunnerPlugin::run_loop(
+            Duration::from_millis(50),
+        ))
+        // DefaultPlugins includes bevy's AudioPlugin when the "bevy_audio" feature is on. One thread is plenty, sounds
+        // are played on a thread of their own
+        .add_plugins(
+            DefaultPlugins
+                .set(TaskPoolPlugin {
+                    task_pool_options: TaskPoolOptions::with_num_threads(1),
+                })
+                .set(LogPlugin {
+                    filter: "off".into(),
+                    level: bevy::log::Level::ERROR,
+                    ..default()
+                }),
+        )
+        .add_plugins(CrosstermPlugin)
+        .add_systems(Startup, startup_system)
+        .add_systems(Update, play_notes)
+        .run();
+}
+
+fn startup_system(
+    mut commands: Commands,
+    window: Query<&CrosstermWindow>,
+    mut cursor: ResMut<Cursor>,
+    mut sprites: ResMut<Assets<Sprite>>,
+    mut stylemaps: ResMut<Assets<StyleMap>>,
+) {
+    cursor.hidden = true;
+    let window = window.single();
+
+    let text = Sprite::new("Press 1 to 8 to play a note, and q to quit");
+    commands.spawn(SpriteBundle {
+        position: Position::with_xy(
+            window.x_center() as i32 - text.x_center() as i32,
+            window.y_center() as i32,
+        ),
+        sprite: sprites.add(text),
+        stylemap: stylemaps.add(StyleMap::default()),
+        ..Default::default()
+    });
+}
+
+fn play_notes(
+    mut commands: Commands,
+    mut keys: EventReader<CrosstermKeyEventWrapper>,
+    mut pitches: ResMut<Assets<Pitch>>,
+    mut app_exit: EventWriter<bevy::app::AppExit>,
+) {
+    for key in keys.read() {
+        if key.0.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.0.code {
+            KeyCode::Char('q') => {
+                app_exit.send(bevy::app::AppExit);
+            }
+            KeyCode::Char(c @ '1'..='8') => {
+                let note = NOTES[c as usize - '1' as usize];
+                // Each note is an entity of its own, which goes away once it's played
+                commands.spawn(PitchBundle {
+                    source: pitches.add(Pitch::new(note, Duration::from_millis(300))),
+                    settings: PlaybackSettings::DESPAWN,
+                });
+            }
+            _ => {}
+        }
+    }
+}
@@ -2,7 +2,6 @@ use bevy::prelude::*;
 use bevy_crossterm::prelude::*;
 
 use bevy_asset::LoadedFolder;
-use bevy_crossterm::CrosstermKeyEventWrapper;
 use std::default::Default;
 
 use bevy::log::LogPlugin;
@@ -65,7 +64,7 @@ pub fn main() {
             Update,
             check_for_loaded.run_if(in_state(GameState::Loading)),
         )
-        .add_systems(Update, just_wait_and_advance)
+        .add_systems(Update, just_wait_and_advance.run_if(on_any_key()))
         .add_systems(OnEnter(GameState::Title), title::setup)
         .add_systems(OnExit(GameState::Title), simple_teardown)
         .add_systems(OnEnter(GameState::Sprites), sprites::setup)
@@ -113,25 +112,16 @@ fn check_for_loaded(
     }
 }
 
-// Helper function to see if there was a key press this frame
-fn detect_keypress(mut keys: EventReader<CrosstermKeyEventWrapper>) -> bool {
-    keys.read()
-        .any(|k| k.0.kind == crossterm::event::KeyEventKind::Press)
-}
-
-// Simple update function that most screens will use
+// Simple update function that most screens will use, run when a key is pressed
 pub fn just_wait_and_advance(
     mut app_exit: ResMut<Events<bevy::app::AppExit>>,
-    keys: EventReader<CrosstermKeyEventWrapper>,
     state: Res<State<GameState>>,
     mut next_state: ResMut<NextState<GameState>>,
 ) {
-    if detect_keypress(keys) {
-        if let Some(state) = state.next_state() {
-            next_state.set(state);
-        } else {
-            app_exit.send(bevy::app::AppExit);
-        }
+    if let Some(state) = state.next_state() {
+        next_state.set(state);
+    } else {
+        app_exit.send(bevy::app::AppExit);
     }
 }
 
@@ -0,0 +1,157 @@
+use bevy::input::gamepad::{
+    Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, Gamepads,
+};
+use bevy::prelude::*;
+use bevy_crossterm::prelude::*;
+
+use crossterm::event::KeyCode;
+use std::default::Default;
+use std::time::Duration;
+
+// Move a player around with a gamepad's left stick or d-pad, to check gamepads work under the crossterm runner.
+// Needs the "gamepad" feature: `cargo run --example gamepad --features gamepad`
+
+// How far the stick has to be pushed to move
+const DEAD_ZONE: f32 = 0.5;
+
+#[derive(Component)]
+struct Player;
+
+#[derive(Component)]
+struct Status;
+
+pub fn main() {
+    // Window settings must happen before the crossterm Plugin
+    let mut settings = CrosstermWindowSettings::default();
+    settings.set_title("Gamepad example");
+    // Run slowly while nothing is happening. Using a gamepad counts as something happening, even with the terminal
+    // in the background
+    settings.set_idle_pacing(IdlePacing {
+        wait: Duration::from_millis(500),
+        after: Duration::from_secs(1),
+    });
+
+    App::new()
+        .insert_resource(settings)
+        // Includes bevy's GilrsPlugin with the "gamepad" feature on
+        .add_plugins(CrosstermDefaultPlugins)
+        .add_systems(Startup, startup_system)
+        .add_systems(
+            Update,
+            (
+                move_player,
+                show_gamepads,
+                quit.run_if(on_key_press(KeyCode::Char('q'))),
+            ),
+        )
+        .run();
+}
+
+fn startup_system(
+    mut commands: Commands,
+    window: Query<&CrosstermWindow>,
+    mut cursor: ResMut<Cursor>,
+    mut sprites: ResMut<Assets<Sprite>>,
+    mut stylemaps: ResMut<Assets<StyleMap>>,
+) {
+    cursor.hidden = true;
+    let window = window.single();
+
+    commands.spawn((
+        SpriteBundle {
+            position: Position::with_xy(window.x_center() as i32, window.y_center() as i32),
+            sprite: sprites.add(Sprite::new("@")),
+            stylemap: stylemaps.add(StyleMap::default()),
+            ..Default::default()
+        },
+        Player,
+    ));
+    commands.spawn((
+        SpriteBundle {
+            sprite: sprites.add(Sprite::new("No gamepads connected, press q to quit")),
+            stylemap: stylemaps.add(StyleMap::default()),
+            ..Default::default()
+        },
+        Status,
+    ));
+}
+
+fn move_player(
+    gamepads: Res<Gamepads>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    window: Query<&CrosstermWindow>,
+    mut player: Query<&mut Position, With<Player>>,
+) {
+    let window = window.single();
+    let mut position = player.single_mut();
+
+    for gamepad in gamepads.iter() {
+        let (dx, dy) = direction(gamepad, &buttons, &axes);
+        if dx == 0 && dy == 0 {
+            continue;
+        }
+        position.x = (position.x + dx).clamp(0, window.virtual_width() as i32 - 1);
+        position.y = (position.y + dy).clamp(0, window.virtual_height() as i32 - 1);
+    }
+}
+
+// The way the d-pad or, failing that, the left stick is pointing
+fn direction(
+    gamepad: Gamepad,
+    buttons: &ButtonInput<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+) -> (i32, i32) {
+    let pressed = |button_type| buttons.pressed(GamepadButton::new(gamepad, button_type));
+    let dpad = (
+        pressed(GamepadButtonType::DPadRight) as i32 - pressed(GamepadButtonType::DPadLeft) as i32,
+        pressed(GamepadButtonType::DPadDown) as i32 - pressed(GamepadButtonType::DPadUp) as i32,
+    );
+    if dpad != (0, 0) {
+        return dpad;
+    }
+
+    let axis = |axis_type| {
+        let value = axes
+            .get(GamepadAxis::new(gamepad, axis_type))
+            .unwrap_or_default();
+        if value > DEAD_ZONE {
+            1
+        } else if value < -DEAD_ZONE {
+            -1
+        } else {
+            0
+        }
+    };
+    // Up is positive on the stick, but down is positive in the terminal
+    (
+        axis(GamepadAxisType::LeftStickX),
+        -axis(GamepadAxisType::LeftStickY),
+    )
+}
+
+fn show_gamepads(
+    gamepads: Res<Gamepads>,
+    status: Query<&Handle<Sprite>, With<Status>>,
+    mut sprites: ResMut<Assets<Sprite>>,
+) {
+    if !gamepads.is_changed() {
+        return;
+    }
+    let names: Vec<_> = gamepads
+        .iter()
+        .filter_map(|gamepad| gamepads.name(gamepad))
+        .collect();
+    let text = if names.is_empty() {
+        "No gamepads connected, press q to quit".to_string()
+    } else {
+        format!("Connected: {}, press q to quit", names.join(", "))
+    };
+    if let Some(sprite) = sprites.get_mut(status.single()) {
+        sprite.update(text);
+    }
+}
+
+fn quit(mut app_exit: EventWriter<bevy::app::AppExit>) {
+    app_exit.send(bevy::app::AppExit);
+}
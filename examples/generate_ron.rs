@@ -3,7 +3,6 @@ use bevy_crossterm::prelude::*;
 // This doesn't really demonstrate anything, but it does show how to save a stylemap as an asset.
 
 fn main() {
-    let mut stylemap = StyleMap::default();
     let w = Style::with_colors(Colors::new(Color::White, Color::Black));
     let r = Style::with_colors(Colors::new(Color::White, Color::AnsiValue(160)));
     let o = Style::with_colors(Colors::new(Color::White, Color::AnsiValue(166)));
@@ -12,15 +11,19 @@ fn main() {
     let b = Style::with_colors(Colors::new(Color::White, Color::AnsiValue(27)));
     let i = Style::with_colors(Colors::new(Color::White, Color::AnsiValue(19)));
     let v = Style::with_colors(Colors::new(Color::White, Color::AnsiValue(91)));
-    stylemap
-        .map
-        .push(vec![w, w, w, w, w, w, w, w, w, w, w, w, w, w, w, w, w, w]);
-    stylemap
-        .map
-        .push(vec![w, w, r, o, y, g, b, i, v, r, o, y, g, b, i, v, w, w]);
-    stylemap
-        .map
-        .push(vec![w, w, w, w, w, w, w, w, w, w, w, w, w, w, w, w, w, w]);
+    let rainbow = [r, o, y, g, b, i, v, r, o, y, g, b, i, v];
+    let stylemap = StyleMap::builder()
+        .width(18)
+        .row(0, w)
+        .row(1, w)
+        .row(2, w)
+        .cells(
+            rainbow
+                .into_iter()
+                .enumerate()
+                .map(|(x, style)| ((x + 2, 1), style)),
+        )
+        .build();
 
     let file = std::fs::File::create("bounce.stylemap").unwrap();
     ron::ser::to_writer(&file, &stylemap).unwrap();
@@ -0,0 +1,714 @@
+//! Animating the look of entities.
+
+use std::time::Duration;
+
+use bevy::asset::LoadedFolder;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::color;
+use crate::components::{Color, Sprite, Style, StyleMap, StyleSpan};
+use crate::theme::{Theme, ThemeColors};
+
+/// How a [`ColorAnimation`] changes the color over each period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorEffect {
+    /// Fade smoothly to the color and back
+    Pulse(Color),
+    /// Switch to the color for the first half of each period
+    Flash(Color),
+    /// Go around the color wheel
+    Rainbow,
+}
+
+/// Which of an entity's colors a [`ColorAnimation`] changes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorTarget {
+    #[default]
+    Foreground,
+    Background,
+}
+
+/// Animates the colors of an entity's [`StyleMap`], e.g. `ColorAnimation::flash(Color::Red, period)` for a warning.
+/// The entity gets a copy of its stylemap to animate, so other entities sharing the stylemap aren't affected, and it
+/// goes back to the original once the animation is over or the component is removed
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct ColorAnimation {
+    pub effect: ColorEffect,
+    pub target: ColorTarget,
+    /// How long one cycle of the effect takes
+    pub period: Duration,
+    /// How long the animation runs for, or `None` to keep going until it's removed
+    pub duration: Option<Duration>,
+    elapsed: Duration,
+}
+
+impl ColorAnimation {
+    pub fn new(effect: ColorEffect, period: Duration) -> ColorAnimation {
+        ColorAnimation {
+            effect,
+            target: ColorTarget::default(),
+            period,
+            duration: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn pulse(color: Color, period: Duration) -> ColorAnimation {
+        ColorAnimation::new(ColorEffect::Pulse(color), period)
+    }
+
+    pub fn flash(color: Color, period: Duration) -> ColorAnimation {
+        ColorAnimation::new(ColorEffect::Flash(color), period)
+    }
+
+    pub fn rainbow(period: Duration) -> ColorAnimation {
+        ColorAnimation::new(ColorEffect::Rainbow, period)
+    }
+
+    #[must_use]
+    pub fn with_target(mut self, target: ColorTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    #[must_use]
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// How long the animation has been running
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Returns true once the animation has run for its duration
+    pub fn is_finished(&self) -> bool {
+        self.duration
+            .is_some_and(|duration| self.elapsed >= duration)
+    }
+
+    // The color `original` becomes at this point in the animation
+    fn color(&self, original: Option<Color>) -> Option<Color> {
+        let phase = if self.period.is_zero() {
+            0.0
+        } else {
+            (self.elapsed.as_secs_f32() / self.period.as_secs_f32()).fract()
+        };
+        match self.effect {
+            ColorEffect::Pulse(to) => {
+                let amount = (1.0 - (phase * std::f32::consts::TAU).cos()) / 2.0;
+                Some(color::mix(original.unwrap_or(Color::Reset), to, amount))
+            }
+            ColorEffect::Flash(to) if phase < 0.5 => Some(to),
+            ColorEffect::Flash(_) => original,
+            ColorEffect::Rainbow => Some(color::hue(phase)),
+        }
+    }
+
+    fn apply(&self, style: Style) -> Style {
+        let mut style = style;
+        match self.target {
+            ColorTarget::Foreground => {
+                style.colors.foreground = self.color(style.colors.foreground)
+            }
+            ColorTarget::Background => {
+                style.colors.background = self.color(style.colors.background)
+            }
+        }
+        style
+    }
+}
+
+/// Animates the stylemaps of entities with a [`ColorAnimation`], and puts their own back afterwards
+pub(crate) fn animate_colors(
+    mut originals: Local<HashMap<Entity, Handle<StyleMap>>>,
+    mut commands: Commands,
+    time: Res<Time>,
+    theme: Res<Theme>,
+    mut stylemaps: ResMut<Assets<StyleMap>>,
+    mut animated: Query<(Entity, Option<&mut ColorAnimation>, &mut Handle<StyleMap>)>,
+    mut removed: RemovedComponents<ColorAnimation>,
+) {
+    for entity in removed.read() {
+        if let (Some(original), Ok((_, _, mut handle))) =
+            (originals.remove(&entity), animated.get_mut(entity))
+        {
+            *handle = original;
+        }
+    }
+
+    for (entity, animation, mut handle) in &mut animated {
+        let Some(mut animation) = animation else {
+            continue;
+        };
+        // Timing isn't drawn, so it doesn't count as a change
+        animation.bypass_change_detection().elapsed += time.delta();
+        let original = originals.entry(entity).or_insert_with(|| handle.clone());
+        let Some(source) = stylemaps.get(&*original) else {
+            continue;
+        };
+
+        if animation.is_finished() {
+            *handle = originals.remove(&entity).unwrap();
+            commands.entity(entity).remove::<ColorAnimation>();
+            continue;
+        }
+
+        // The theme's colors are baked in, so they can be animated too
+        let source = source.resolve_pairs(&theme);
+        let new_stylemap = StyleMap {
+            style: animation.apply(source.base_style(&theme)),
+            map: source
+                .map
+                .iter()
+                .map(|row| row.iter().map(|style| animation.apply(*style)).collect())
+                .collect(),
+            theme: ThemeColors::default(),
+            spans: source
+                .spans
+                .iter()
+                .map(|span| StyleSpan::new(span.range.clone(), animation.apply(span.style)))
+                .collect(),
+            pairs: Vec::new(),
+        };
+
+        if *handle == *original {
+            *handle = stylemaps.add(new_stylemap);
+        } else if stylemaps
+            .get(&*handle)
+            .is_some_and(|old| *old != new_stylemap)
+        {
+            *stylemaps.get_mut(&*handle).unwrap() = new_stylemap;
+        }
+    }
+}
+
+// One set of colors that take each other's place in turn
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ColorCycle {
+    colors: Vec<Color>,
+    step: Duration,
+    elapsed: Duration,
+}
+
+impl ColorCycle {
+    // How many places along the colors have moved
+    fn offset(&self) -> usize {
+        if self.step.is_zero() || self.colors.is_empty() {
+            return 0;
+        }
+        (self.elapsed.as_nanos() / self.step.as_nanos()) as usize % self.colors.len()
+    }
+}
+
+/// Rotates sets of colors through every [`StyleMap`] that uses them, the classic trick for animating water, fire and
+/// conveyor belts without drawing more frames. Every `step`, each color in a set takes the place of the one before
+/// it, e.g. `palette_cycle.add(vec![Color::DarkBlue, Color::Blue, Color::Cyan], Duration::from_millis(150))`.
+/// Sprites aren't touched, and clearing the cycles puts the original colors back
+#[derive(Resource, Debug, Default, Clone, PartialEq, Eq)]
+pub struct PaletteCycle {
+    cycles: Vec<ColorCycle>,
+}
+
+impl PaletteCycle {
+    /// Cycle `colors`, moving them along one place every `step`. A color should only be in one set
+    pub fn add(&mut self, colors: Vec<Color>, step: Duration) -> &mut Self {
+        self.cycles.push(ColorCycle {
+            colors,
+            step,
+            elapsed: Duration::ZERO,
+        });
+        self
+    }
+
+    /// Stop cycling every color
+    pub fn clear(&mut self) -> &mut Self {
+        self.cycles.clear();
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cycles.is_empty()
+    }
+
+    // What each color becomes right now
+    fn mapping(&self) -> HashMap<Color, Color> {
+        let mut mapping = HashMap::default();
+        for cycle in &self.cycles {
+            let offset = cycle.offset();
+            for (i, color) in cycle.colors.iter().enumerate() {
+                mapping.insert(*color, cycle.colors[(i + offset) % cycle.colors.len()]);
+            }
+        }
+        mapping
+    }
+}
+
+fn recolor(style: Style, mapping: &HashMap<Color, Color>) -> Style {
+    let map = |color: Option<Color>| color.map(|color| *mapping.get(&color).unwrap_or(&color));
+    let mut style = style;
+    style.colors.foreground = map(style.colors.foreground);
+    style.colors.background = map(style.colors.background);
+    style.underline_color = map(style.underline_color);
+    style
+}
+
+/// Moves the palette cycles along and restyles the stylemaps using their colors
+pub(crate) fn cycle_palettes(
+    // Each stylemap's original, and what it was changed to
+    mut restyled: Local<HashMap<AssetId<StyleMap>, (StyleMap, StyleMap)>>,
+    time: Res<Time>,
+    mut palette: ResMut<PaletteCycle>,
+    mut stylemaps: ResMut<Assets<StyleMap>>,
+) {
+    // Time passing doesn't count as a change, only moving to the next colors does
+    let changed = palette.is_changed();
+    let mut stepped = false;
+    for cycle in &mut palette.bypass_change_detection().cycles {
+        let offset = cycle.offset();
+        cycle.elapsed += time.delta();
+        stepped |= cycle.offset() != offset;
+    }
+    if !changed && !stepped {
+        return;
+    }
+
+    let mapping = palette.mapping();
+    let ids: Vec<_> = stylemaps.ids().collect();
+    for id in ids {
+        let stylemap = stylemaps.get(id).unwrap();
+        // Start from the original, unless something else has changed the stylemap since it was last restyled
+        let original = match restyled.remove(&id) {
+            Some((original, last)) if last == *stylemap => original,
+            _ => stylemap.clone(),
+        };
+        let new_stylemap = StyleMap {
+            style: recolor(original.style, &mapping),
+            map: original
+                .map
+                .iter()
+                .map(|row| row.iter().map(|style| recolor(*style, &mapping)).collect())
+                .collect(),
+            theme: original.theme.clone(),
+            spans: original
+                .spans
+                .iter()
+                .map(|span| StyleSpan::new(span.range.clone(), recolor(span.style, &mapping)))
+                .collect(),
+            // Pairs keep the theme's colors
+            pairs: original.pairs.clone(),
+        };
+        if new_stylemap != *stylemap {
+            *stylemaps.get_mut(id).unwrap() = new_stylemap.clone();
+        }
+        // Stylemaps without any of the colors don't need remembering
+        if new_stylemap != original {
+            restyled.insert(id, (original, new_stylemap));
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Frames {
+    Sprites(Vec<Handle<Sprite>>),
+    // Turned into sprites once the folder has loaded
+    Folder(Handle<LoadedFolder>),
+}
+
+/// Flips an entity's `Handle<Sprite>` through a series of frames, like a flip-book. The frames can be sprites, or a
+/// folder of text files loaded with `asset_server.load_folder("explosion")`, one frame to a file. The files are shown
+/// in the order of the number in their names, so `frame2.txt` comes before `frame10.txt`
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct SpriteAnimation {
+    frames: Frames,
+    frame_duration: Duration,
+    looping: bool,
+    playing: bool,
+    frame: usize,
+    elapsed: Duration,
+    // Whether the frame showing has been sent as reached
+    reported: bool,
+}
+
+impl SpriteAnimation {
+    /// Show `frames` in order, `fps` frames a second
+    pub fn new(frames: Vec<Handle<Sprite>>, fps: f32) -> SpriteAnimation {
+        SpriteAnimation::with_frames(Frames::Sprites(frames), fps)
+    }
+
+    /// Show each text file in `folder` as a frame, `fps` frames a second. Nothing is shown until the folder has loaded
+    pub fn from_folder(folder: Handle<LoadedFolder>, fps: f32) -> SpriteAnimation {
+        SpriteAnimation::with_frames(Frames::Folder(folder), fps)
+    }
+
+    fn with_frames(frames: Frames, fps: f32) -> SpriteAnimation {
+        SpriteAnimation {
+            frames,
+            frame_duration: frame_duration(fps),
+            looping: true,
+            playing: true,
+            frame: 0,
+            elapsed: Duration::ZERO,
+            reported: false,
+        }
+    }
+
+    /// Whether the animation starts again after the last frame, rather than stopping on it. Animations loop by
+    /// default
+    #[must_use]
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    pub fn set_looping(&mut self, looping: bool) -> &mut Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn fps(&self) -> f32 {
+        1.0 / self.frame_duration.as_secs_f32()
+    }
+
+    pub fn set_fps(&mut self, fps: f32) -> &mut Self {
+        self.frame_duration = frame_duration(fps);
+        self
+    }
+
+    /// Which frame is showing, counting from 0
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Jump to `frame`, which is kept within the frames there are
+    pub fn set_frame(&mut self, frame: usize) -> &mut Self {
+        self.frame = frame.min(self.frame_count().saturating_sub(1));
+        self.elapsed = Duration::ZERO;
+        self.reported = false;
+        self
+    }
+
+    /// How many frames there are, which is 0 until a folder of frames has loaded
+    pub fn frame_count(&self) -> usize {
+        match &self.frames {
+            Frames::Sprites(frames) => frames.len(),
+            Frames::Folder(_) => 0,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn play(&mut self) -> &mut Self {
+        self.playing = true;
+        self
+    }
+
+    pub fn pause(&mut self) -> &mut Self {
+        self.playing = false;
+        self
+    }
+
+    /// Returns true once an animation that doesn't loop has shown its last frame
+    pub fn is_finished(&self) -> bool {
+        self.is_on_last_frame() && self.elapsed >= self.frame_duration
+    }
+
+    fn is_on_last_frame(&self) -> bool {
+        !self.looping && self.frame_count() > 0 && self.frame + 1 >= self.frame_count()
+    }
+
+    // The sprite for the frame that's showing
+    fn sprite(&self) -> Option<&Handle<Sprite>> {
+        match &self.frames {
+            Frames::Sprites(frames) => frames.get(self.frame),
+            Frames::Folder(_) => None,
+        }
+    }
+
+    // Move the animation along by `delta`, returning the frames it reached on the way
+    fn advance(&mut self, delta: Duration) -> Vec<usize> {
+        let count = self.frame_count();
+        let mut reached = Vec::new();
+        if count == 0 {
+            return reached;
+        }
+        if !self.reported {
+            self.reported = true;
+            reached.push(self.frame);
+        }
+        if !self.playing || self.frame_duration.is_zero() || self.is_finished() {
+            return reached;
+        }
+        self.elapsed += delta;
+        while self.elapsed >= self.frame_duration && !self.is_on_last_frame() {
+            self.elapsed -= self.frame_duration;
+            self.frame = (self.frame + 1) % count;
+            reached.push(self.frame);
+        }
+        reached
+    }
+}
+
+fn frame_duration(fps: f32) -> Duration {
+    if fps > 0.0 {
+        Duration::from_secs_f32(1.0 / fps)
+    } else {
+        Duration::ZERO
+    }
+}
+
+// The number in a frame's file name, to sort frames by
+fn frame_number(handle: &UntypedHandle) -> Option<u64> {
+    let path = handle.path()?;
+    let stem = path.path().file_stem()?.to_string_lossy();
+    let digits: String = stem
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+// A change of state an AnimationController allows
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Transition {
+    // `None` for any state
+    from: Option<String>,
+    to: String,
+    // Taken by itself once the animation of `from` finishes, rather than when asked for
+    on_finish: bool,
+}
+
+/// Switches an entity between named [`SpriteAnimation`]s, like "idle", "walk" and "attack", following the
+/// transitions it's given. The entity's `SpriteAnimation` is replaced with the state's animation, from its first
+/// frame, whenever the state changes, e.g.
+///
+/// ```ignore
+/// let idle = SpriteAnimation::from_folder(asset_server.load_folder("hero/idle"), 4.0);
+/// let attack = SpriteAnimation::from_folder(asset_server.load_folder("hero/attack"), 12.0).with_looping(false);
+/// AnimationController::new("idle")
+///     .with_state("idle", idle)
+///     .with_state("attack", attack)
+///     .with_transition("idle", "attack")
+///     .with_transition_on_finish("attack", "idle")
+/// ```
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct AnimationController {
+    states: HashMap<String, SpriteAnimation>,
+    transitions: Vec<Transition>,
+    state: String,
+    // The state the entity's SpriteAnimation was last set up for
+    applied: Option<String>,
+}
+
+impl AnimationController {
+    /// A controller that starts out in `state`
+    pub fn new(state: impl Into<String>) -> AnimationController {
+        AnimationController {
+            states: HashMap::default(),
+            transitions: Vec::new(),
+            state: state.into(),
+            applied: None,
+        }
+    }
+
+    /// Play `animation` while in the state called `name`
+    #[must_use]
+    pub fn with_state(mut self, name: impl Into<String>, animation: SpriteAnimation) -> Self {
+        self.states.insert(name.into(), animation);
+        self
+    }
+
+    /// Allow asking to go from `from` to `to` with [`request_state`](Self::request_state)
+    #[must_use]
+    pub fn with_transition(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.transitions.push(Transition {
+            from: Some(from.into()),
+            to: to.into(),
+            on_finish: false,
+        });
+        self
+    }
+
+    /// Allow asking to go to `to` from whatever state the controller is in
+    #[must_use]
+    pub fn with_transition_from_any(mut self, to: impl Into<String>) -> Self {
+        self.transitions.push(Transition {
+            from: None,
+            to: to.into(),
+            on_finish: false,
+        });
+        self
+    }
+
+    /// Go from `from` to `to` by itself once the animation of `from` finishes, like going back to "idle" after an
+    /// attack. Only animations that don't loop ever finish
+    #[must_use]
+    pub fn with_transition_on_finish(
+        mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+    ) -> Self {
+        self.transitions.push(Transition {
+            from: Some(from.into()),
+            to: to.into(),
+            on_finish: true,
+        });
+        self
+    }
+
+    /// The state the controller is in
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    /// The animation played in the state called `name`
+    pub fn animation(&self, name: &str) -> Option<&SpriteAnimation> {
+        self.states.get(name)
+    }
+
+    /// Whether there's a transition from the current state to the state called `to`
+    pub fn can_transition_to(&self, to: &str) -> bool {
+        self.states.contains_key(to)
+            && self.transitions.iter().any(|transition| {
+                !transition.on_finish
+                    && transition.to == to
+                    && transition
+                        .from
+                        .as_ref()
+                        .is_none_or(|from| *from == self.state)
+            })
+    }
+
+    /// Go to the state called `to` if there's a transition to it from the current state, and returns whether it did.
+    /// Asking for the state the controller is already in leaves its animation playing
+    pub fn request_state(&mut self, to: &str) -> bool {
+        if self.state == to {
+            return true;
+        }
+        if !self.can_transition_to(to) {
+            return false;
+        }
+        self.state = to.to_string();
+        true
+    }
+
+    // Where to go once the current state's animation finishes
+    fn finish_transition(&self) -> Option<&str> {
+        self.transitions
+            .iter()
+            .find(|transition| {
+                transition.on_finish && transition.from.as_deref() == Some(self.state.as_str())
+            })
+            .map(|transition| transition.to.as_str())
+    }
+}
+
+/// Takes the transitions of animations that have finished, and gives entities the animation of the state they're in
+pub(crate) fn update_animation_controllers(
+    mut commands: Commands,
+    mut controllers: Query<(
+        Entity,
+        &mut AnimationController,
+        Option<&mut SpriteAnimation>,
+    )>,
+) {
+    for (entity, mut controller, animation) in &mut controllers {
+        if controller.applied.as_ref() == Some(&controller.state) {
+            let finished = animation
+                .as_ref()
+                .is_some_and(|animation| animation.is_finished());
+            match controller.finish_transition() {
+                Some(to) if finished => {
+                    let to = to.to_string();
+                    controller.state = to;
+                }
+                _ => continue,
+            }
+        }
+
+        let state = controller.state.clone();
+        controller.bypass_change_detection().applied = Some(state.clone());
+        let Some(new_animation) = controller.states.get(&state) else {
+            warn!("The animation controller has no state called {state:?}");
+            continue;
+        };
+        match animation {
+            Some(mut animation) => *animation = new_animation.clone(),
+            None => {
+                commands.entity(entity).insert(new_animation.clone());
+            }
+        }
+    }
+}
+
+/// Sent when an entity's [`SpriteAnimation`] gets to a frame, including the one it starts on, so things can happen
+/// at a certain point in the animation
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationFrameReached {
+    pub entity: Entity,
+    pub frame: usize,
+}
+
+/// Sent when an entity's [`SpriteAnimation`] finishes, see [`SpriteAnimation::is_finished`]
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationFinished {
+    pub entity: Entity,
+}
+
+/// Loads the frames of folder animations, and shows the frame each animation is on
+pub(crate) fn animate_sprites(
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    folders: Res<Assets<LoadedFolder>>,
+    mut animated: Query<(Entity, &mut SpriteAnimation, &mut Handle<Sprite>)>,
+    mut frames_reached: EventWriter<AnimationFrameReached>,
+    mut finished: EventWriter<AnimationFinished>,
+) {
+    for (entity, mut animation, mut handle) in &mut animated {
+        if let Frames::Folder(folder) = &animation.frames {
+            let Some(folder) = folders.get(folder) else {
+                continue;
+            };
+            let mut files: Vec<_> = folder
+                .handles
+                .iter()
+                .filter(|handle| handle.path().is_some())
+                .collect();
+            files.sort_by_key(|handle| {
+                (frame_number(handle), handle.path().map(ToString::to_string))
+            });
+            // The folder may have loaded the files as colored sprites, so they're loaded again as plain ones
+            let frames = files
+                .into_iter()
+                .map(|file| asset_server.load::<Sprite>(file.path().unwrap().clone()))
+                .collect();
+            animation.frames = Frames::Sprites(frames);
+        }
+
+        // Timing isn't drawn, so it doesn't count as a change
+        let was_finished = animation.is_finished();
+        let reached = animation.bypass_change_detection().advance(time.delta());
+        frames_reached.send_batch(
+            reached
+                .into_iter()
+                .map(|frame| AnimationFrameReached { entity, frame }),
+        );
+        if animation.is_finished() && !was_finished {
+            finished.send(AnimationFinished { entity });
+        }
+        if let Some(sprite) = animation.sprite() {
+            if *handle != *sprite {
+                *handle = sprite.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! Reading and writing text that is colored with ANSI escape sequences, like the output of `ls --color` or art
+//! exported from an ANSI editor.
+
+use crossterm::style::Attribute;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::components::{Color, ColoredSprite, Style};
+use crate::render::Cell;
+
+const ESC: char = '\x1b';
+// DOS end of file marker. ANSI art files put their SAUCE metadata record after it
+const SUB: char = '\x1a';
+
+const UNDERLINES: [Attribute; 5] = [
+    Attribute::Underlined,
+    Attribute::DoubleUnderlined,
+    Attribute::Undercurled,
+    Attribute::Underdotted,
+    Attribute::Underdashed,
+];
+
+/// Turn text containing SGR escape sequences into a colored sprite. Other escape sequences are dropped, since there's
+/// nothing sensible to do with e.g. cursor movement in a sprite
+pub(crate) fn parse(text: &str) -> ColoredSprite {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut style = Style::default();
+    let mut chars = text.char_indices().peekable();
+    let mut run_start = 0;
+
+    while let Some((i, c)) = chars.next() {
+        if c != ESC && c != SUB {
+            continue;
+        }
+        push_graphemes(&text[run_start..i], style, &mut row, &mut rows);
+        if c == SUB {
+            run_start = text.len();
+            break;
+        }
+
+        match chars.next() {
+            // Control sequence: parameters, then a final byte between @ and ~
+            Some((_, '[')) => {
+                let start = i + 2;
+                let mut end = text.len();
+                let mut last = None;
+                for (j, c) in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        end = j;
+                        last = Some(c);
+                        break;
+                    }
+                }
+                if last == Some('m') {
+                    apply_sgr(&text[start..end], &mut style);
+                }
+            }
+            // Operating system command, ended by BEL or ST
+            Some((_, ']')) => {
+                while let Some((_, c)) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == ESC {
+                        chars.next_if(|(_, c)| *c == '\\');
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+        run_start = chars.peek().map_or(text.len(), |(j, _)| *j);
+    }
+    push_graphemes(&text[run_start..], style, &mut row, &mut rows);
+
+    if !row.is_empty() {
+        rows.push(row);
+    }
+    ColoredSprite::new(Style::default(), rows)
+}
+
+/// Write a colored sprite as text with SGR escape sequences, which [`parse`] reads back in
+pub(crate) fn write(sprite: &ColoredSprite) -> String {
+    let mut text = String::new();
+    for (y, row) in sprite.rows.iter().enumerate() {
+        if y > 0 {
+            text.push('\n');
+        }
+        let mut current = Style::default();
+        for cell in row {
+            if cell.style != current {
+                text.push_str(&sgr(&cell.style));
+                current = cell.style;
+            }
+            text.push_str(&cell.grapheme);
+        }
+        // Don't let the style bleed into whatever comes after the line
+        if current != Style::default() {
+            text.push_str("\x1b[0m");
+        }
+    }
+    text
+}
+
+// The escape sequence that switches to `style` from anything else
+fn sgr(style: &Style) -> String {
+    let mut params = vec!["0".to_string()];
+    params.extend(
+        Attribute::iterator()
+            .filter(|attribute| *attribute != Attribute::Reset && style.attributes.has(*attribute))
+            .map(Attribute::sgr),
+    );
+    if let Some(foreground) = style.colors.foreground {
+        params.push(color_sgr(foreground, 30));
+    }
+    if let Some(background) = style.colors.background {
+        params.push(color_sgr(background, 40));
+    }
+    if let Some(underline) = style.underline_color {
+        params.push(color_sgr(underline, 50));
+    }
+    format!("\x1b[{}m", params.join(";"))
+}
+
+// The parameters that set a color. `base` is 30 for the foreground, 40 for the background and 50 for underlines
+fn color_sgr(color: Color, base: u8) -> String {
+    let index = (0..16).find(|index| ansi_color(*index) == color);
+    match (color, index) {
+        // Underlines don't have their own codes for the 16 standard colors
+        (_, Some(index)) if base == 50 => format!("58;5;{index}"),
+        (_, Some(index)) if index < 8 => (base + index).to_string(),
+        (_, Some(index)) => (base + 60 + index - 8).to_string(),
+        (Color::AnsiValue(index), _) => format!("{};5;{index}", base + 8),
+        (Color::Rgb { r, g, b }, _) => format!("{};2;{r};{g};{b}", base + 8),
+        _ => (base + 9).to_string(),
+    }
+}
+
+// Add each grapheme of plain text to the current row, starting a new row at line breaks
+fn push_graphemes(text: &str, style: Style, row: &mut Vec<Cell>, rows: &mut Vec<Vec<Cell>>) {
+    for grapheme in text.graphemes(true) {
+        if grapheme == "\r" || grapheme == "\n" || grapheme == "\r\n" {
+            rows.push(std::mem::take(row));
+        } else {
+            row.push(Cell {
+                grapheme: grapheme.to_string(),
+                style,
+            });
+        }
+    }
+}
+
+// Apply the parameters of a single SGR sequence, e.g. "1;38;5;208" or "4:3"
+pub(crate) fn apply_sgr(params: &str, style: &mut Style) {
+    // Parameters are separated by ';', and some have sub-parameters separated by ':'
+    let mut params = params.split(';').map(|param| {
+        param
+            .split(':')
+            .map(|sub| sub.parse::<u8>().ok())
+            .collect::<Vec<_>>()
+    });
+
+    while let Some(param) = params.next() {
+        let attributes = &mut style.attributes;
+        match param[0].unwrap_or(0) {
+            0 => *style = Style::default(),
+            1 => attributes.set(Attribute::Bold),
+            2 => attributes.set(Attribute::Dim),
+            3 => attributes.set(Attribute::Italic),
+            4 => {
+                for underline in UNDERLINES {
+                    attributes.unset(underline);
+                }
+                match param.get(1).copied().flatten().unwrap_or(1) {
+                    0 => {}
+                    2 => attributes.set(Attribute::DoubleUnderlined),
+                    3 => attributes.set(Attribute::Undercurled),
+                    4 => attributes.set(Attribute::Underdotted),
+                    5 => attributes.set(Attribute::Underdashed),
+                    _ => attributes.set(Attribute::Underlined),
+                }
+            }
+            5 => attributes.set(Attribute::SlowBlink),
+            6 => attributes.set(Attribute::RapidBlink),
+            7 => attributes.set(Attribute::Reverse),
+            8 => attributes.set(Attribute::Hidden),
+            9 => attributes.set(Attribute::CrossedOut),
+            21 => {
+                for underline in UNDERLINES {
+                    attributes.unset(underline);
+                }
+                attributes.set(Attribute::DoubleUnderlined);
+            }
+            22 => {
+                attributes.unset(Attribute::Bold);
+                attributes.unset(Attribute::Dim);
+            }
+            23 => attributes.unset(Attribute::Italic),
+            24 => {
+                for underline in UNDERLINES {
+                    attributes.unset(underline);
+                }
+            }
+            25 => {
+                attributes.unset(Attribute::SlowBlink);
+                attributes.unset(Attribute::RapidBlink);
+            }
+            27 => attributes.unset(Attribute::Reverse),
+            28 => attributes.unset(Attribute::Hidden),
+            29 => attributes.unset(Attribute::CrossedOut),
+            n @ 30..=37 => style.colors.foreground = Some(ansi_color(n - 30)),
+            38 => style.colors.foreground = extended_color(&param, &mut params),
+            39 => style.colors.foreground = None,
+            n @ 40..=47 => style.colors.background = Some(ansi_color(n - 40)),
+            48 => style.colors.background = extended_color(&param, &mut params),
+            49 => style.colors.background = None,
+            58 => style.underline_color = extended_color(&param, &mut params),
+            59 => style.underline_color = None,
+            n @ 90..=97 => style.colors.foreground = Some(ansi_color(n - 90 + 8)),
+            n @ 100..=107 => style.colors.background = Some(ansi_color(n - 100 + 8)),
+            _ => {}
+        }
+    }
+}
+
+// The 16 standard colors, in SGR order
+fn ansi_color(index: u8) -> Color {
+    [
+        Color::Black,
+        Color::DarkRed,
+        Color::DarkGreen,
+        Color::DarkYellow,
+        Color::DarkBlue,
+        Color::DarkMagenta,
+        Color::DarkCyan,
+        Color::Grey,
+        Color::DarkGrey,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::White,
+    ][index as usize]
+}
+
+// A 256 color or RGB color after 38, 48 or 58. It's either in the sub-parameters (38:5:208, 38:2::255:128:0 with an
+// optional color space id) or the following parameters (38;5;208, 38;2;255;128;0)
+fn extended_color<I>(param: &[Option<u8>], params: &mut I) -> Option<Color>
+where
+    I: Iterator<Item = Vec<Option<u8>>>,
+{
+    let values: Vec<Option<u8>> = if param.len() > 1 {
+        let mut values = param[1..].to_vec();
+        if values.first() == Some(&Some(2)) && values.len() > 4 {
+            values.remove(1);
+        }
+        values
+    } else {
+        let mut values = vec![params.next()?[0]];
+        let count = match values[0] {
+            Some(5) => 1,
+            Some(2) => 3,
+            _ => 0,
+        };
+        values.extend(params.take(count).map(|param| param[0]));
+        values
+    };
+
+    match values.as_slice() {
+        [Some(5), Some(index)] => Some(Color::AnsiValue(*index)),
+        [Some(2), Some(r), Some(g), Some(b)] => Some(Color::Rgb {
+            r: *r,
+            g: *g,
+            b: *b,
+        }),
+        _ => None,
+    }
+}
@@ -1,10 +1,15 @@
+use std::path::PathBuf;
+
+use bevy::log::warn;
 use bevy::utils::BoxedFuture;
 use bevy_asset::io::Reader;
 use bevy_asset::AsyncReadExt;
-use bevy_asset::{AssetLoader, LoadContext};
+use bevy_asset::{AssetLoader, LoadContext, ReadAssetBytesError};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use unicode_segmentation::UnicodeSegmentation;
 
-use crate::components::{Sprite, StyleMap};
+use crate::components::{ColoredSprite, Sprite, StyleMap};
 
 #[derive(Error, Debug)]
 pub enum LoadSpriteError {
@@ -12,6 +17,165 @@ pub enum LoadSpriteError {
     InvalidUtf8(#[from] std::str::Utf8Error),
     #[error("io error")]
     Io(#[from] std::io::Error),
+    #[error("sprite data contains a tab on line {line}")]
+    Tab { line: usize },
+    #[error("sprite data contains the control character {character:?} on line {line}")]
+    ControlCharacter { character: char, line: usize },
+    #[error("could not read the sprite's mask")]
+    ReadMask(#[from] ReadAssetBytesError),
+}
+
+/// What to do with tabs in sprite files
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tabs {
+    /// Replace each tab with spaces up to the next multiple of this many columns
+    Expand(u8),
+    /// Fail to load the sprite
+    Reject,
+}
+
+impl Default for Tabs {
+    fn default() -> Self {
+        Tabs::Expand(8)
+    }
+}
+
+/// What to do with control characters other than line endings and tabs in sprite files, like carriage returns, bells
+/// and escapes. They'd be written straight to the terminal otherwise
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlCharacters {
+    /// Leave them out of the sprite
+    #[default]
+    Strip,
+    /// Fail to load the sprite
+    Reject,
+}
+
+/// Where lines shorter than the longest one go
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Settings for loading [`Sprite`]s, e.g.
+/// `asset_server.load_with_settings("map.txt", |settings: &mut SpriteLoaderSettings| settings.tabs = Tabs::Expand(4))`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpriteLoaderSettings {
+    /// A tab takes up one cell but moves the terminal's cursor further than that, so tabs would throw off the
+    /// sprite's columns and width if they were kept. Defaults to expanding them to 8 columns
+    pub tabs: Tabs,
+    /// Defaults to stripping them
+    pub control_characters: ControlCharacters,
+    /// Remove whitespace from the end of every line. This happens before aligning and padding
+    pub trim: bool,
+    /// Add spaces to the end of shorter lines, so every line is as wide as the sprite
+    pub pad: bool,
+    /// Line up shorter lines with the left edge, the middle, or the right edge of the longest line. Lines are moved
+    /// over with spaces
+    pub align: Align,
+    /// Give the sprite a mask from the file next to it with the same name and a `.mask` extension, see
+    /// [`Sprite::set_mask`]. In the mask file spaces are see-through and anything else is drawn. It has to line up
+    /// with the sprite as it's drawn, since the settings above don't change it
+    pub mask: bool,
+}
+
+// Replace tabs with spaces up to the next tab stop, or fail if tabs aren't allowed
+fn handle_tabs(text: &str, tabs: Tabs) -> Result<String, LoadSpriteError> {
+    match tabs {
+        Tabs::Expand(width) => Ok(expand_tabs(text, width)),
+        Tabs::Reject => match text.lines().position(|line| line.contains('\t')) {
+            Some(line) => Err(LoadSpriteError::Tab { line: line + 1 }),
+            None => Ok(text.to_string()),
+        },
+    }
+}
+
+// Replace tabs with spaces up to the next multiple of `width` columns, counting columns in graphemes
+fn expand_tabs(text: &str, width: u8) -> String {
+    if !text.contains('\t') {
+        return text.to_string();
+    }
+    let width = width.max(1) as usize;
+
+    let mut expanded = String::with_capacity(text.len());
+    let mut column = 0;
+    for grapheme in text.graphemes(true) {
+        match grapheme {
+            "\t" => {
+                let spaces = width - column % width;
+                expanded.push_str(&" ".repeat(spaces));
+                column += spaces;
+            }
+            "\n" | "\r\n" | "\r" => {
+                expanded.push_str(grapheme);
+                column = 0;
+            }
+            _ => {
+                expanded.push_str(grapheme);
+                column += 1;
+            }
+        }
+    }
+    expanded
+}
+
+// Strip control characters, or fail on the first one. Line endings are kept
+fn sanitize(text: &str, control_characters: ControlCharacters) -> Result<String, LoadSpriteError> {
+    let mut sanitized = String::with_capacity(text.len());
+    let mut line = 1;
+    for grapheme in text.graphemes(true) {
+        if grapheme == "\n" || grapheme == "\r\n" {
+            line += 1;
+        } else if let Some(character) = grapheme.chars().find(|c| c.is_control()) {
+            match control_characters {
+                ControlCharacters::Strip => continue,
+                ControlCharacters::Reject => {
+                    return Err(LoadSpriteError::ControlCharacter { character, line })
+                }
+            }
+        }
+        sanitized.push_str(grapheme);
+    }
+    Ok(sanitized)
+}
+
+// Trim, align and pad the lines as the settings ask. Line endings become "\n" if anything changes
+fn shape(text: &str, settings: &SpriteLoaderSettings) -> String {
+    if !settings.trim && !settings.pad && settings.align == Align::Left {
+        return text.to_string();
+    }
+
+    let lines: Vec<&str> = text
+        .lines()
+        .map(|line| if settings.trim { line.trim_end() } else { line })
+        .collect();
+    let widths: Vec<usize> = lines
+        .iter()
+        .map(|line| line.graphemes(true).count())
+        .collect();
+    let width = widths.iter().copied().max().unwrap_or(0);
+
+    let mut shaped = String::with_capacity(text.len());
+    for (i, (line, line_width)) in lines.iter().zip(widths).enumerate() {
+        if i > 0 {
+            shaped.push('\n');
+        }
+        let space = width - line_width;
+        let before = match settings.align {
+            Align::Left => 0,
+            Align::Center => space / 2,
+            Align::Right => space,
+        };
+        shaped.push_str(&" ".repeat(before));
+        shaped.push_str(line);
+        if settings.pad {
+            shaped.push_str(&" ".repeat(space - before));
+        }
+    }
+    shaped
 }
 
 #[derive(Default)]
@@ -19,6 +183,45 @@ pub struct SpriteLoader;
 
 impl AssetLoader for SpriteLoader {
     type Asset = Sprite;
+    type Settings = SpriteLoaderSettings;
+    type Error = LoadSpriteError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, LoadSpriteError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let string = std::str::from_utf8(&bytes)?;
+            let string = handle_tabs(string, settings.tabs)?;
+            let string = sanitize(&string, settings.control_characters)?;
+            let mut sprite = Sprite::new(shape(&string, settings));
+
+            if settings.mask {
+                let mask_path = load_context.path().with_extension("mask");
+                let mask_bytes = load_context.read_asset_bytes(mask_path).await?;
+                let mask = handle_tabs(std::str::from_utf8(&mask_bytes)?, settings.tabs)?;
+                sprite.set_mask(Some(Sprite::mask_from_text(&mask)));
+            }
+            Ok(sprite)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["txt"]
+    }
+}
+
+/// Loads text containing ANSI color escapes. Plain `.txt` files are loaded this way too when they're loaded as a
+/// [`ColoredSprite`] rather than a [`Sprite`]
+#[derive(Default)]
+pub struct ColoredSpriteLoader;
+
+impl AssetLoader for ColoredSpriteLoader {
+    type Asset = ColoredSprite;
     type Settings = ();
     type Error = LoadSpriteError;
 
@@ -32,22 +235,96 @@ impl AssetLoader for SpriteLoader {
             let mut bytes = Vec::new();
             reader.read_to_end(&mut bytes).await?;
             let string = std::str::from_utf8(&bytes)?;
-            let sprite = Sprite::new(string);
-            Ok(sprite)
+            Ok(ColoredSprite::from_ansi(string))
         })
     }
 
     fn extensions(&self) -> &[&str] {
-        &["txt"]
+        &["ans", "txt"]
+    }
+}
+
+/// Settings for loading Markdown with [`MarkdownLoader`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarkdownLoaderSettings {
+    /// How many columns text is wrapped at. Code blocks aren't wrapped. Defaults to 80
+    pub width: u16,
+}
+
+impl Default for MarkdownLoaderSettings {
+    fn default() -> Self {
+        MarkdownLoaderSettings { width: 80 }
+    }
+}
+
+/// Loads `.md` files as [`ColoredSprite`]s, with headings, emphasis, lists and code blocks styled and paragraphs
+/// wrapped to fit
+#[derive(Default)]
+pub struct MarkdownLoader;
+
+impl AssetLoader for MarkdownLoader {
+    type Asset = ColoredSprite;
+    type Settings = MarkdownLoaderSettings;
+    type Error = LoadSpriteError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, LoadSpriteError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let string = std::str::from_utf8(&bytes)?;
+            let string = sanitize(&expand_tabs(string, 4), ControlCharacters::Strip)?;
+            Ok(crate::markdown::render(&string, settings.width as usize))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["md", "markdown"]
     }
 }
 
 #[derive(Error, Debug)]
 pub enum LoadStyleMapError {
-    #[error("error deserializing style map from ron data")]
-    Deserialize(#[from] ron::de::SpannedError),
+    #[error("error deserializing style map from ron data at {}:{line}:{column}: {error}", path.display())]
+    Deserialize {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        error: ron::error::Error,
+    },
     #[error("io error")]
     Io(#[from] std::io::Error),
+    #[error("could not read the sprite to check the style map against")]
+    ReadSprite(#[from] ReadAssetBytesError),
+    #[error("the sprite to check the style map against is invalid")]
+    InvalidSprite(#[from] LoadSpriteError),
+    #[error(
+        "{} has a style for row {row}, column {column}, outside of the {width}x{height} sprite {}",
+        path.display(),
+        sprite.display()
+    )]
+    OutOfBounds {
+        path: PathBuf,
+        sprite: PathBuf,
+        row: usize,
+        column: usize,
+        width: usize,
+        height: usize,
+    },
+}
+
+/// Settings for loading [`StyleMap`]s
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StyleMapLoaderSettings {
+    /// The sprite the style map goes with, as an asset path. If it's set, styles for cells outside of the sprite are
+    /// reported, since they're likely a mistake
+    pub sprite: Option<String>,
+    /// Fail to load the style map if it has styles outside of `sprite`, rather than just warning about it
+    pub strict: bool,
 }
 
 #[derive(Default)]
@@ -55,19 +332,48 @@ pub struct StyleMapLoader;
 
 impl AssetLoader for StyleMapLoader {
     type Asset = StyleMap;
-    type Settings = ();
+    type Settings = StyleMapLoaderSettings;
     type Error = LoadStyleMapError;
 
     fn load<'a>(
         &'a self,
         reader: &'a mut Reader,
-        _settings: &'a Self::Settings,
-        _load_context: &'a mut LoadContext,
+        settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<Self::Asset, LoadStyleMapError>> {
         Box::pin(async move {
             let mut bytes = Vec::new();
             reader.read_to_end(&mut bytes).await?;
-            let stylemap = ron::de::from_bytes::<StyleMap>(&bytes)?;
+            let path = load_context.path().to_path_buf();
+            let stylemap = ron::de::from_bytes::<StyleMap>(&bytes).map_err(|err| {
+                LoadStyleMapError::Deserialize {
+                    path: path.clone(),
+                    line: err.position.line,
+                    column: err.position.col,
+                    error: err.code,
+                }
+            })?;
+
+            let Some(sprite_path) = &settings.sprite else {
+                return Ok(stylemap);
+            };
+            let sprite_bytes = load_context.read_asset_bytes(sprite_path.clone()).await?;
+            let sprite =
+                Sprite::new(std::str::from_utf8(&sprite_bytes).map_err(LoadSpriteError::from)?);
+            if let Some((column, row)) = stylemap.out_of_bounds(&sprite) {
+                let err = LoadStyleMapError::OutOfBounds {
+                    path,
+                    sprite: PathBuf::from(sprite_path),
+                    row,
+                    column,
+                    width: sprite.width(),
+                    height: sprite.height(),
+                };
+                if settings.strict {
+                    return Err(err);
+                }
+                warn!("{err}");
+            }
             Ok(stylemap)
         })
     }
@@ -76,3 +382,163 @@ impl AssetLoader for StyleMapLoader {
         &["stylemap"]
     }
 }
+
+#[cfg(feature = "syntect")]
+#[derive(Error, Debug)]
+pub enum LoadSyntaxError {
+    #[error("source code contains invalid utf8 data")]
+    InvalidUtf8(#[from] std::str::Utf8Error),
+    #[error("io error")]
+    Io(#[from] std::io::Error),
+    #[error("there's no highlighting theme called {0:?}")]
+    UnknownTheme(String),
+    #[error("error highlighting source code")]
+    Highlight(#[from] syntect::Error),
+}
+
+/// Settings for loading source code with [`SyntaxLoader`]
+#[cfg(feature = "syntect")]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyntaxLoaderSettings {
+    /// One of syntect's default themes. Defaults to `"base16-ocean.dark"`
+    pub theme: String,
+    /// Use the theme's background colors. Off by default, so the code sits on the terminal's own background
+    pub background: bool,
+    /// How many columns tabs are expanded to. Defaults to 4
+    pub tab_width: u8,
+}
+
+#[cfg(feature = "syntect")]
+impl Default for SyntaxLoaderSettings {
+    fn default() -> Self {
+        SyntaxLoaderSettings {
+            theme: "base16-ocean.dark".to_string(),
+            background: false,
+            tab_width: 4,
+        }
+    }
+}
+
+/// Loads source code files as [`ColoredSprite`]s, syntax highlighted by syntect. Like any colored sprite, they're
+/// split into a sprite and a stylemap when they're put on an entity. The language is picked by the file's extension
+#[cfg(feature = "syntect")]
+pub struct SyntaxLoader {
+    syntaxes: syntect::parsing::SyntaxSet,
+    themes: syntect::highlighting::ThemeSet,
+}
+
+#[cfg(feature = "syntect")]
+impl Default for SyntaxLoader {
+    fn default() -> Self {
+        SyntaxLoader {
+            syntaxes: syntect::parsing::SyntaxSet::load_defaults_newlines(),
+            themes: syntect::highlighting::ThemeSet::load_defaults(),
+        }
+    }
+}
+
+#[cfg(feature = "syntect")]
+impl SyntaxLoader {
+    fn highlight(
+        &self,
+        text: &str,
+        extension: &str,
+        settings: &SyntaxLoaderSettings,
+    ) -> Result<ColoredSprite, LoadSyntaxError> {
+        use crate::components::Style;
+        use crate::render::Cell;
+        use syntect::highlighting::FontStyle;
+
+        let theme = self
+            .themes
+            .themes
+            .get(&settings.theme)
+            .ok_or_else(|| LoadSyntaxError::UnknownTheme(settings.theme.clone()))?;
+        let syntax = self
+            .syntaxes
+            .find_syntax_by_extension(extension)
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        let mut highlighter = syntect::easy::HighlightLines::new(syntax, theme);
+
+        let color = |color: syntect::highlighting::Color| crossterm::style::Color::Rgb {
+            r: color.r,
+            g: color.g,
+            b: color.b,
+        };
+        let mut rows = Vec::new();
+        for line in syntect::util::LinesWithEndings::from(text) {
+            let mut row = Vec::new();
+            for (highlight, piece) in highlighter.highlight_line(line, &self.syntaxes)? {
+                let mut style = Style::with_fg(color(highlight.foreground));
+                if settings.background {
+                    style.colors.background = Some(color(highlight.background));
+                }
+                for (font_style, attribute) in [
+                    (FontStyle::BOLD, crossterm::style::Attribute::Bold),
+                    (FontStyle::ITALIC, crossterm::style::Attribute::Italic),
+                    (
+                        FontStyle::UNDERLINE,
+                        crossterm::style::Attribute::Underlined,
+                    ),
+                ] {
+                    if highlight.font_style.contains(font_style) {
+                        style.attributes.set(attribute);
+                    }
+                }
+                row.extend(
+                    piece
+                        .graphemes(true)
+                        .filter(|grapheme| !matches!(*grapheme, "\n" | "\r\n"))
+                        .map(|grapheme| Cell {
+                            grapheme: grapheme.to_string(),
+                            style,
+                        }),
+                );
+            }
+            rows.push(row);
+        }
+
+        let background = theme
+            .settings
+            .background
+            .filter(|_| settings.background)
+            .map_or_else(Style::default, |background| {
+                Style::with_bg(color(background))
+            });
+        Ok(ColoredSprite::new(background, rows))
+    }
+}
+
+#[cfg(feature = "syntect")]
+impl AssetLoader for SyntaxLoader {
+    type Asset = ColoredSprite;
+    type Settings = SyntaxLoaderSettings;
+    type Error = LoadSyntaxError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, LoadSyntaxError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let string = std::str::from_utf8(&bytes)?;
+            let string = expand_tabs(string, settings.tab_width);
+            let extension = load_context
+                .path()
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .unwrap_or_default();
+            self.highlight(&string, extension, settings)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[
+            "c", "cpp", "cs", "css", "go", "h", "hpp", "html", "java", "js", "json", "lua", "py",
+            "rb", "rs", "sh", "toml", "ts", "yaml", "yml",
+        ]
+    }
+}
@@ -0,0 +1,45 @@
+//! Keeping sounds from bevy_audio on time under the crossterm runner.
+//!
+//! Sounds are played on rodio's own thread, so once a sound has started, how fast the app runs doesn't matter. They
+//! only start on the next frame after they're spawned though, and the runner slows down while the app is idle or
+//! the terminal is in the background, see [`CrosstermWindowSettings::set_idle_pacing`]. So while any sound is waiting
+//! to start or playing, the app counts as busy and runs at its normal frame rate.
+//!
+//! Limiting the task pools to one thread, as the examples do, is fine for audio: loading and decoding sounds happens
+//! on the IO and async compute pools, which always get a thread of their own.
+//!
+//! [`CrosstermWindowSettings::set_idle_pacing`]: crate::CrosstermWindowSettings::set_idle_pacing
+
+use bevy::audio::{AudioSink, AudioSinkPlayback, PlaybackSettings, SpatialAudioSink};
+use bevy::prelude::*;
+
+/// Whether any sounds were waiting to start or playing at the end of the last frame
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AudioActivity {
+    pub(crate) busy: bool,
+}
+
+/// Checks whether any sounds are waiting to start or playing, for the runner to keep up with them
+#[allow(clippy::type_complexity)]
+pub(crate) fn track_audio(
+    mut activity: ResMut<AudioActivity>,
+    queued: Query<
+        (),
+        (
+            With<PlaybackSettings>,
+            Without<AudioSink>,
+            Without<SpatialAudioSink>,
+        ),
+    >,
+    sinks: Query<&AudioSink>,
+    spatial_sinks: Query<&SpatialAudioSink>,
+) {
+    let busy = !queued.is_empty()
+        || sinks.iter().any(|sink| !sink.is_paused() && !sink.empty())
+        || spatial_sinks
+            .iter()
+            .any(|sink| !sink.is_paused() && !sink.empty());
+    if activity.busy != busy {
+        activity.busy = busy;
+    }
+}
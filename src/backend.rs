@@ -0,0 +1,454 @@
+//! Where the plugin's terminal input and output actually go.
+//!
+//! The renderer produces standard ANSI escape sequences and the runner consumes crossterm events, but getting them to
+//! and from the terminal is left to a [`TerminalBackend`]. [`CrosstermBackend`] talks to the real terminal and is
+//! used unless another backend is picked with [`SelectedBackend`]. [`TestBackend`] runs without a terminal at all.
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use crossterm::event::Event;
+
+/// The terminal the plugin draws to and reads input from
+pub trait TerminalBackend: Send + Sync + 'static {
+    /// Stop the terminal from echoing and line buffering input
+    fn enable_raw_mode(&mut self) -> std::io::Result<()>;
+
+    fn disable_raw_mode(&mut self) -> std::io::Result<()>;
+
+    /// The size of the terminal as (width, height)
+    fn size(&self) -> std::io::Result<(u16, u16)>;
+
+    /// Write output, which may be buffered until [`TerminalBackend::flush`]
+    fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()>;
+
+    fn flush(&mut self) -> std::io::Result<()>;
+
+    /// Wait up to `timeout` for an event. Returns true if [`TerminalBackend::read`] won't block
+    fn poll(&mut self, timeout: Duration) -> std::io::Result<bool>;
+
+    /// Read the next event, waiting for one if needed
+    fn read(&mut self) -> std::io::Result<Event>;
+
+    /// Whether the terminal supports the kitty keyboard protocol
+    fn supports_keyboard_enhancement(&mut self) -> bool {
+        false
+    }
+
+    /// Ask the terminal for its background color, see
+    /// [`TerminalCapabilities::background`](crate::capabilities::TerminalCapabilities::background)
+    fn query_background(&mut self) -> Option<(u8, u8, u8)> {
+        None
+    }
+}
+
+/// Use this backend instead of the real terminal. Insert it before the app runs
+#[derive(Resource)]
+pub struct SelectedBackend(pub(crate) Box<dyn TerminalBackend>);
+
+impl SelectedBackend {
+    pub fn new(backend: impl TerminalBackend) -> SelectedBackend {
+        SelectedBackend(Box::new(backend))
+    }
+}
+
+/// The backend a window is using, which can be borrowed from systems that only have shared access to the window
+pub(crate) struct SharedBackend {
+    backend: Arc<Mutex<Box<dyn TerminalBackend>>>,
+    render_thread: Option<RenderThread>,
+    // How many frames are being worked out on other threads, see `begin_frame`
+    frames_in_flight: Arc<(Mutex<usize>, Condvar)>,
+    // Set once the terminal has gone away, after which nothing is written to it
+    hung_up: Arc<AtomicBool>,
+    // Set while something else has the terminal, see `RawTerminalGuard`
+    paused: Arc<AtomicBool>,
+    // Frames written since they were last taken, once something has asked for them, see `capture_frames`
+    captured: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl SharedBackend {
+    pub(crate) fn new(backend: Box<dyn TerminalBackend>) -> SharedBackend {
+        SharedBackend {
+            backend: Arc::new(Mutex::new(backend)),
+            render_thread: None,
+            frames_in_flight: Arc::default(),
+            hung_up: Arc::default(),
+            paused: Arc::default(),
+            captured: Arc::default(),
+        }
+    }
+
+    /// Another handle on the same terminal, for writing from another thread. Frames written with it go straight to
+    /// the terminal
+    pub(crate) fn detached(&self) -> SharedBackend {
+        SharedBackend {
+            backend: self.backend.clone(),
+            render_thread: None,
+            frames_in_flight: self.frames_in_flight.clone(),
+            hung_up: self.hung_up.clone(),
+            paused: self.paused.clone(),
+            captured: self.captured.clone(),
+        }
+    }
+
+    /// Note that a frame is being worked out on another thread, until the returned guard is dropped. Anything that
+    /// waits for frames with [`SharedBackend::finish_frames`] waits for it too
+    pub(crate) fn begin_frame(&self) -> FrameInFlight {
+        *self.frames_in_flight.0.lock().unwrap() += 1;
+        FrameInFlight(self.frames_in_flight.clone())
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Box<dyn TerminalBackend>> {
+        lock(&self.backend)
+    }
+
+    /// Write frames on a thread of their own from now on, see [`crate::CrosstermWindowSettings::set_render_thread`]
+    pub(crate) fn start_render_thread(&mut self) {
+        if self.render_thread.is_none() {
+            self.render_thread = Some(RenderThread::spawn(
+                self.backend.clone(),
+                self.hung_up.clone(),
+            ));
+        }
+    }
+
+    /// Write a frame's output and flush it. With a render thread this hands the output over and returns straight
+    /// away, and any error is from writing an earlier frame. Failing to write means the terminal is gone, so the
+    /// backend hangs up rather than returning the error, unless the write was only interrupted
+    pub(crate) fn write_frame(&self, bytes: &[u8]) -> std::io::Result<()> {
+        if self.is_hung_up() || self.is_paused() {
+            return Ok(());
+        }
+        if let Some(captured) = &mut *self.captured.lock().unwrap() {
+            captured.extend_from_slice(bytes);
+        }
+        let result = match &self.render_thread {
+            Some(thread) => thread.send(bytes),
+            None => {
+                let mut backend = self.lock();
+                backend.write_all(bytes).and_then(|_| backend.flush())
+            }
+        };
+        // Writing fails once the terminal is gone, possibly before the runner finds out about it on its own
+        match result {
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => Err(error),
+            Err(_) => {
+                self.hang_up();
+                Ok(())
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Keep a copy of every frame written from now on, to be taken with [`SharedBackend::take_captured`]
+    pub(crate) fn capture_frames(&self) {
+        self.captured.lock().unwrap().get_or_insert_with(Vec::new);
+    }
+
+    /// The frames written since this was last called, in the order they were written
+    pub(crate) fn take_captured(&self) -> Vec<u8> {
+        self.captured
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Note that the terminal has gone away, e.g. because it was closed. Frames are dropped from then on, and
+    /// restoring the terminal is skipped
+    pub(crate) fn hang_up(&self) {
+        self.hung_up.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_hung_up(&self) -> bool {
+        self.hung_up.load(Ordering::Relaxed)
+    }
+
+    /// Drop frames instead of writing them, or start writing them again. Frames already on their way to the terminal
+    /// still get there, so wait for them with [`SharedBackend::finish_frames`] first
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Wait for every frame that's on its way to the terminal to get there, so other output comes after them
+    pub(crate) fn finish_frames(&self) {
+        let (count, changed) = &*self.frames_in_flight;
+        let mut count = count.lock().unwrap();
+        while *count > 0 {
+            count = changed.wait(count).unwrap();
+        }
+        drop(count);
+        if let Some(thread) = &self.render_thread {
+            thread.finish();
+        }
+    }
+}
+
+/// A frame being worked out on another thread, see [`SharedBackend::begin_frame`]
+pub(crate) struct FrameInFlight(Arc<(Mutex<usize>, Condvar)>);
+
+impl Drop for FrameInFlight {
+    fn drop(&mut self) {
+        let (count, changed) = &*self.0;
+        *count
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) -= 1;
+        changed.notify_all();
+    }
+}
+
+fn lock(backend: &Mutex<Box<dyn TerminalBackend>>) -> MutexGuard<'_, Box<dyn TerminalBackend>> {
+    // A panic while writing doesn't leave the backend in a state we can't carry on from
+    backend
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Output waiting for the render thread
+#[derive(Default)]
+struct PendingOutput {
+    bytes: Vec<u8>,
+    // Whether the thread is in the middle of writing
+    writing: bool,
+    // Set when the thread should stop, once everything's written
+    closed: bool,
+    error: Option<std::io::Error>,
+}
+
+/// Writes frames to the terminal so the app doesn't have to wait for a slow terminal, like one over ssh. Frames that
+/// pile up while the terminal is busy are written together, in one go
+struct RenderThread {
+    pending: Arc<(Mutex<PendingOutput>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    fn spawn(
+        backend: Arc<Mutex<Box<dyn TerminalBackend>>>,
+        hung_up: Arc<AtomicBool>,
+    ) -> RenderThread {
+        let pending = Arc::new((Mutex::new(PendingOutput::default()), Condvar::new()));
+        let thread = {
+            let pending = pending.clone();
+            std::thread::Builder::new()
+                .name("terminal render".into())
+                .spawn(move || RenderThread::run(&backend, &pending, &hung_up))
+                .expect("Could not start the render thread")
+        };
+        RenderThread {
+            pending,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(
+        backend: &Mutex<Box<dyn TerminalBackend>>,
+        pending: &(Mutex<PendingOutput>, Condvar),
+        hung_up: &AtomicBool,
+    ) {
+        let (output, changed) = pending;
+        loop {
+            let bytes = {
+                let mut output = output.lock().unwrap();
+                while output.bytes.is_empty() && !output.closed {
+                    output = changed.wait(output).unwrap();
+                }
+                if output.bytes.is_empty() {
+                    return;
+                }
+                output.writing = true;
+                std::mem::take(&mut output.bytes)
+            };
+
+            // Frames for a terminal that's gone are thrown away
+            let result = if hung_up.load(Ordering::Relaxed) {
+                Ok(())
+            } else {
+                let mut backend = lock(backend);
+                backend.write_all(&bytes).and_then(|_| backend.flush())
+            };
+
+            let mut output = output.lock().unwrap();
+            output.writing = false;
+            match result {
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {
+                    output.error = Some(error);
+                }
+                // The terminal is gone, the same as in `SharedBackend::write_frame`
+                Err(_) => hung_up.store(true, Ordering::Relaxed),
+                Ok(()) => {}
+            }
+            changed.notify_all();
+        }
+    }
+
+    fn send(&self, bytes: &[u8]) -> std::io::Result<()> {
+        let (output, changed) = &*self.pending;
+        let mut output = output.lock().unwrap();
+        output.bytes.extend_from_slice(bytes);
+        changed.notify_all();
+        output.error.take().map_or(Ok(()), Err)
+    }
+
+    fn finish(&self) {
+        let (output, changed) = &*self.pending;
+        let mut output = output.lock().unwrap();
+        while !output.bytes.is_empty() || output.writing {
+            output = changed.wait(output).unwrap();
+        }
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        let (output, changed) = &*self.pending;
+        output.lock().unwrap().closed = true;
+        changed.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl std::fmt::Debug for SharedBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedBackend")
+    }
+}
+
+/// The real terminal, through crossterm and stdout
+#[derive(Debug, Default)]
+pub struct CrosstermBackend;
+
+impl TerminalBackend for CrosstermBackend {
+    fn enable_raw_mode(&mut self) -> std::io::Result<()> {
+        crossterm::terminal::enable_raw_mode()
+    }
+
+    fn disable_raw_mode(&mut self) -> std::io::Result<()> {
+        crossterm::terminal::disable_raw_mode()
+    }
+
+    fn size(&self) -> std::io::Result<(u16, u16)> {
+        crossterm::terminal::size()
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        std::io::stdout().lock().write_all(bytes)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stdout().flush()
+    }
+
+    fn poll(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        crossterm::event::poll(timeout)
+    }
+
+    fn read(&mut self) -> std::io::Result<Event> {
+        crossterm::event::read()
+    }
+
+    fn supports_keyboard_enhancement(&mut self) -> bool {
+        matches!(
+            crossterm::terminal::supports_keyboard_enhancement(),
+            Ok(true)
+        )
+    }
+
+    fn query_background(&mut self) -> Option<(u8, u8, u8)> {
+        crate::capabilities::query_background()
+    }
+}
+
+/// A pretend terminal for tests and headless runs. It keeps everything written to it, and hands out events that were
+/// pushed to it. Clones share the same output and events, so keep one around to inspect the app.
+#[derive(Debug, Clone)]
+pub struct TestBackend {
+    size: Arc<Mutex<(u16, u16)>>,
+    output: Arc<Mutex<Vec<u8>>>,
+    events: Arc<Mutex<VecDeque<Event>>>,
+}
+
+impl TestBackend {
+    pub fn new(width: u16, height: u16) -> TestBackend {
+        TestBackend {
+            size: Arc::new(Mutex::new((width, height))),
+            output: Arc::default(),
+            events: Arc::default(),
+        }
+    }
+
+    /// Everything written so far
+    pub fn output(&self) -> Vec<u8> {
+        self.output.lock().unwrap().clone()
+    }
+
+    /// Everything written since the last call
+    pub fn take_output(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.output.lock().unwrap())
+    }
+
+    /// Queue an event for the app to read
+    pub fn push_event(&self, event: Event) {
+        self.events.lock().unwrap().push_back(event);
+    }
+
+    /// Change the size, and let the app know about it
+    pub fn resize(&self, width: u16, height: u16) {
+        *self.size.lock().unwrap() = (width, height);
+        self.push_event(Event::Resize(width, height));
+    }
+}
+
+impl TerminalBackend for TestBackend {
+    fn enable_raw_mode(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn disable_raw_mode(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> std::io::Result<(u16, u16)> {
+        Ok(*self.size.lock().unwrap())
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.output.lock().unwrap().extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn poll(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        if self.events.lock().unwrap().is_empty() {
+            std::thread::sleep(timeout);
+        }
+        Ok(!self.events.lock().unwrap().is_empty())
+    }
+
+    fn read(&mut self) -> std::io::Result<Event> {
+        self.events
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::WouldBlock, "no events queued"))
+    }
+
+    fn supports_keyboard_enhancement(&mut self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,183 @@
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+
+use crate::components::{BlendMode, ColoredSprite, Position, Sprite, Style, StyleMap, Visible};
+use crate::render::{Cell, CellBuffer};
+use crate::render_graph;
+use crate::theme::Theme;
+
+/// Scrolls the scene. The camera's position is the world coordinate drawn in the top left corner of the viewport.
+///
+/// Only one camera is supported. Without one, the world is drawn as if there was a camera at 0,0. Moving the camera
+/// redraws the whole screen.
+#[derive(Component, Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct TerminalCamera {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl TerminalCamera {
+    pub fn new(x: i32, y: i32) -> TerminalCamera {
+        TerminalCamera { x, y }
+    }
+
+    /// Converts a position inside the viewport (see [`crate::CrosstermWindow::viewport`]) to a world position
+    pub fn viewport_to_world(&self, x: u16, y: u16) -> (i32, i32) {
+        (self.x + x as i32, self.y + y as i32)
+    }
+
+    /// Converts a world position to a position relative to the top left corner of the viewport. The result may be
+    /// outside of the viewport
+    pub fn world_to_viewport(&self, x: i32, y: i32) -> (i32, i32) {
+        (x - self.x, y - self.y)
+    }
+}
+
+/// Whether a [`RenderTarget`] is drawn again every frame
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RenderTargetRefresh {
+    /// Keep the sprite up to date with the world, for picture-in-picture and mirrors
+    #[default]
+    EveryFrame,
+    /// Draw the sprite once and keep it, for static backgrounds that are costly to composite. Call
+    /// [`RenderTarget::redraw`] to draw it again
+    Once,
+}
+
+/// A camera that draws its view into a [`ColoredSprite`] instead of onto the terminal. Show the sprite on another
+/// entity for picture-in-picture or mirrors, or draw a static scene once and keep the result as a background.
+///
+/// Like [`TerminalCamera`], `x`,`y` is the world coordinate drawn in the top left corner of the sprite. Render
+/// targets aren't terminal cameras, so they can be spawned alongside the one camera that scrolls the screen.
+/// Entities showing the target's own sprite are left out of it.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct RenderTarget {
+    pub x: i32,
+    pub y: i32,
+    pub width: u16,
+    pub height: u16,
+    /// The sprite to draw into, which is replaced whenever what the camera sees changes
+    pub target: Handle<ColoredSprite>,
+    pub refresh: RenderTargetRefresh,
+    /// Only draw entities with a z in this range. Draws all of them when `None`
+    pub layers: Option<RangeInclusive<i32>>,
+    drawn: bool,
+}
+
+impl RenderTarget {
+    pub fn new(target: Handle<ColoredSprite>, width: u16, height: u16) -> RenderTarget {
+        RenderTarget {
+            x: 0,
+            y: 0,
+            width,
+            height,
+            target,
+            refresh: RenderTargetRefresh::default(),
+            layers: None,
+            drawn: false,
+        }
+    }
+
+    #[must_use]
+    pub fn with_xy(mut self, x: i32, y: i32) -> Self {
+        self.x = x;
+        self.y = y;
+        self
+    }
+
+    #[must_use]
+    pub fn with_refresh(mut self, refresh: RenderTargetRefresh) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    #[must_use]
+    pub fn with_layers(mut self, layers: RangeInclusive<i32>) -> Self {
+        self.layers = Some(layers);
+        self
+    }
+
+    /// Draw the sprite again next frame, even if it's only drawn once
+    pub fn redraw(&mut self) {
+        self.drawn = false;
+    }
+
+    fn camera(&self) -> TerminalCamera {
+        TerminalCamera::new(self.x, self.y)
+    }
+}
+
+/// Draws what each render target sees into its sprite
+#[allow(clippy::type_complexity)]
+pub(crate) fn draw_render_targets(
+    mut targets: Query<&mut RenderTarget>,
+    entities: Query<(
+        &Position,
+        &Visible,
+        &Handle<Sprite>,
+        &Handle<StyleMap>,
+        Option<&BlendMode>,
+        Option<&Handle<ColoredSprite>>,
+    )>,
+    sprites: Res<Assets<Sprite>>,
+    stylemaps: Res<Assets<StyleMap>>,
+    theme: Res<Theme>,
+    mut colored_sprites: ResMut<Assets<ColoredSprite>>,
+) {
+    for mut target in &mut targets {
+        if target.drawn && target.refresh == RenderTargetRefresh::Once {
+            continue;
+        }
+        let target = target.bypass_change_detection();
+        target.drawn = true;
+
+        let mut drawn: Vec<_> = entities
+            .iter()
+            .filter(|(position, visible, ..)| {
+                visible.is_visible
+                    && target
+                        .layers
+                        .as_ref()
+                        .is_none_or(|layers| layers.contains(&position.z))
+            })
+            .filter(|(.., colored)| colored.is_none_or(|colored| *colored != target.target))
+            .collect();
+        drawn.sort_by_key(|(position, ..)| position.z);
+
+        let camera = target.camera();
+        let mut buffer = CellBuffer::new(target.width, target.height);
+        for (position, visible, sprite, stylemap, blend_mode, _) in drawn {
+            if let (Some(sprite), Some(stylemap)) = (sprites.get(sprite), stylemaps.get(stylemap)) {
+                let blend_mode = blend_mode.copied().unwrap_or_default();
+                render_graph::draw_sprite(
+                    &mut buffer,
+                    &camera,
+                    &theme,
+                    position,
+                    visible,
+                    sprite,
+                    stylemap,
+                    blend_mode,
+                );
+            }
+        }
+
+        let rows = (0..buffer.height())
+            .map(|y| {
+                (0..buffer.width())
+                    .map(|x| {
+                        buffer.get(x, y).cloned().unwrap_or_else(|| Cell {
+                            grapheme: " ".to_string(),
+                            style: buffer.style(),
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        let sprite = ColoredSprite::new(Style::default(), rows);
+        if colored_sprites.get(&target.target) != Some(&sprite) {
+            colored_sprites.insert(&target.target, sprite);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use bevy::prelude::*;
+
+use crate::color::ColorDepth;
+
+/// What the terminal turned out to support, detected when the app starts
+#[derive(Resource, Debug, Default, Clone, PartialEq, Eq)]
+pub struct TerminalCapabilities {
+    pub(crate) keyboard_enhancement: bool,
+    pub(crate) background: Option<(u8, u8, u8)>,
+    pub(crate) color_depth: ColorDepth,
+    pub(crate) styled_underlines: bool,
+    pub(crate) blink: bool,
+}
+
+impl TerminalCapabilities {
+    /// Whether the terminal speaks the kitty keyboard protocol
+    pub fn keyboard_enhancement(&self) -> bool {
+        self.keyboard_enhancement
+    }
+
+    /// How many colors the terminal can show, going by its environment variables
+    pub fn color_depth(&self) -> ColorDepth {
+        self.color_depth
+    }
+
+    /// Whether the terminal can draw curly, dotted and dashed underlines, and underlines in their own color
+    pub fn styled_underlines(&self) -> bool {
+        self.styled_underlines
+    }
+
+    /// Whether the terminal can make text blink
+    pub fn blink(&self) -> bool {
+        self.blink
+    }
+
+    /// The terminal's background color as RGB, if it told us
+    pub fn background(&self) -> Option<(u8, u8, u8)> {
+        self.background
+    }
+
+    /// Whether the terminal has a light background, or None if it's not known
+    pub fn is_light(&self) -> Option<bool> {
+        self.background.map(|(r, g, b)| {
+            // Relative luminance, https://www.w3.org/TR/WCAG20/#relativeluminancedef without the gamma correction
+            let luminance = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+            luminance > 127.5
+        })
+    }
+
+    /// Whether the terminal has a dark background, or None if it's not known
+    pub fn is_dark(&self) -> Option<bool> {
+        self.is_light().map(|light| !light)
+    }
+}
+
+/// Guess whether the terminal supports styled and colored underlines. There's no way to ask, so this goes by what
+/// terminal it is
+pub(crate) fn detect_styled_underlines(keyboard_enhancement: bool) -> bool {
+    // Every terminal that implements the kitty keyboard protocol supports these as well
+    if keyboard_enhancement {
+        return true;
+    }
+
+    let var = |name| std::env::var(name).unwrap_or_default();
+    let term = var("TERM");
+    let known_term = [
+        "kitty",
+        "wezterm",
+        "foot",
+        "ghostty",
+        "alacritty",
+        "contour",
+    ]
+    .iter()
+    .any(|name| term.contains(name));
+    let known_program = ["WezTerm", "ghostty", "iTerm.app"].contains(&var("TERM_PROGRAM").as_str());
+    // VTE based terminals (GNOME Terminal and friends) since 0.51
+    let vte = var("VTE_VERSION")
+        .parse::<u32>()
+        .is_ok_and(|version| version >= 5100);
+    known_term || known_program || vte
+}
+
+/// Guess whether the terminal blinks text. Most do, so this only rules out the ones known not to
+pub(crate) fn detect_blink() -> bool {
+    let var = |name| std::env::var(name).unwrap_or_default();
+    let alacritty = var("TERM").contains("alacritty") || var("TERM_PROGRAM") == "Alacritty";
+    // VTE based terminals (GNOME Terminal and friends) only blink since 0.52
+    let old_vte = var("VTE_VERSION")
+        .parse::<u32>()
+        .is_ok_and(|version| version < 5200);
+    !alacritty && !old_vte
+}
+
+// How long to wait for the terminal to answer a query. Terminals that answer at all usually do so almost instantly,
+// this is mostly slack for ssh
+const QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Ask the terminal for its background color with OSC 11. Needs raw mode, otherwise the answer ends up on screen
+#[cfg(unix)]
+pub(crate) fn query_background() -> Option<(u8, u8, u8)> {
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+
+    // Terminals that don't know OSC 11 ignore it, but every terminal answers the primary device attributes request
+    // (CSI c) that follows. Once that answer arrives there's nothing more to wait for
+    tty.write_all(b"\x1b]11;?\x1b\\\x1b[c").ok()?;
+    tty.flush().ok()?;
+
+    let deadline = std::time::Instant::now() + QUERY_TIMEOUT;
+    let mut response = Vec::new();
+    while !ends_with_device_attributes(&response) {
+        let remaining = deadline.checked_duration_since(std::time::Instant::now())?;
+        let mut fd = libc::pollfd {
+            fd: tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: fd is a single valid pollfd that lives for the duration of the call
+        let ready = unsafe { libc::poll(&mut fd, 1, remaining.as_millis() as libc::c_int) };
+        if ready <= 0 {
+            return None;
+        }
+
+        let mut buffer = [0; 64];
+        let read = tty.read(&mut buffer).ok()?;
+        if read == 0 {
+            return None;
+        }
+        response.extend_from_slice(&buffer[..read]);
+    }
+
+    parse_background(&response)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn query_background() -> Option<(u8, u8, u8)> {
+    None
+}
+
+// The device attributes answer looks like ESC [ ? 6 2 ; 2 2 c
+fn ends_with_device_attributes(response: &[u8]) -> bool {
+    response.ends_with(b"c")
+        && response
+            .windows(3)
+            .rposition(|window| window == b"\x1b[?")
+            .is_some()
+}
+
+// The answer to OSC 11 looks like ESC ] 1 1 ; r g b : R R R R / G G G G / B B B B, terminated by ST or BEL. Each
+// component has 1 to 4 hex digits
+fn parse_background(response: &[u8]) -> Option<(u8, u8, u8)> {
+    let response = std::str::from_utf8(response).ok()?;
+    let start = response.find("\x1b]11;rgb:")? + "\x1b]11;rgb:".len();
+    let end = response[start..].find(['\x1b', '\x07'])? + start;
+
+    let mut components = response[start..end].split('/').map(|component| {
+        if component.is_empty() || component.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(component, 16).ok()?;
+        let max = 16u32.pow(component.len() as u32) - 1;
+        Some((value * 255 / max) as u8)
+    });
+    let r = components.next()??;
+    let g = components.next()??;
+    let b = components.next()??;
+    Some((r, g, b))
+}
@@ -0,0 +1,458 @@
+//! Capturing what's on the terminal, e.g. to put a screenshot of the game on a web page.
+//!
+//! [`ScreenCapturePlugin`] keeps a [`Screen`] in step with everything the plugin draws, by reading back in the
+//! frames that were written to the terminal. It can be turned into HTML at any point, or recorded frame by frame and
+//! exported as an animation.
+
+use std::fmt::Write;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use crossterm::style::Attribute;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::components::{Color, Style};
+use crate::render::Cell;
+use crate::{CrosstermSet, CrosstermWindow};
+
+// What terminals with a dark background usually show for text without a color
+const DEFAULT_FOREGROUND: (u8, u8, u8) = (229, 229, 229);
+const DEFAULT_BACKGROUND: (u8, u8, u8) = (0, 0, 0);
+
+/// The contents of the terminal: a grapheme and style for every cell
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Screen {
+    width: u16,
+    height: u16,
+    cells: Vec<Cell>,
+    cursor: (u16, u16),
+    style: Style,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen::new(0, 0)
+    }
+}
+
+impl Screen {
+    /// A blank screen
+    pub fn new(width: u16, height: u16) -> Screen {
+        Screen {
+            width,
+            height,
+            cells: vec![blank(Style::default()); width as usize * height as usize],
+            cursor: (0, 0),
+            style: Style::default(),
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn get(&self, x: u16, y: u16) -> Option<&Cell> {
+        (x < self.width && y < self.height).then(|| &self.cells[self.index(x, y)])
+    }
+
+    /// Change the size, keeping what's in the top left corner
+    pub fn resize(&mut self, width: u16, height: u16) {
+        let mut resized = Screen::new(width, height);
+        for y in 0..height.min(self.height) {
+            for x in 0..width.min(self.width) {
+                let i = resized.index(x, y);
+                resized.cells[i] = self.cells[self.index(x, y)].clone();
+            }
+        }
+        resized.style = self.style;
+        *self = resized;
+    }
+
+    /// Apply output that was written to the terminal. This understands what the plugin writes: text, cursor
+    /// movement, clearing the screen and SGR styling. Anything else is skipped
+    pub fn write(&mut self, output: &[u8]) {
+        let output = String::from_utf8_lossy(output);
+        let mut chars = output.char_indices().peekable();
+        let mut run_start = 0;
+
+        while let Some((i, c)) = chars.next() {
+            if c != '\x1b' {
+                continue;
+            }
+            self.print(&output[run_start..i]);
+
+            match chars.next() {
+                Some((_, '[')) => {
+                    let start = i + 2;
+                    let mut end = output.len();
+                    let mut last = None;
+                    for (j, c) in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            end = j;
+                            last = Some(c);
+                            break;
+                        }
+                    }
+                    if let Some(last) = last {
+                        self.control_sequence(&output[start..end], last);
+                    }
+                }
+                // Strings, like the title set with OSC, run until BEL or ST (`ESC \`)
+                Some((_, ']' | 'P' | 'X' | '^' | '_')) => {
+                    for (_, c) in chars.by_ref() {
+                        if c == '\x07' {
+                            break;
+                        }
+                        if c == '\x1b' {
+                            chars.next_if(|(_, c)| *c == '\\');
+                            break;
+                        }
+                    }
+                }
+                // Everything else is any number of intermediate bytes followed by a final one, e.g. `ESC ( B`
+                Some((_, c)) if (' '..='/').contains(&c) => {
+                    while chars.next_if(|(_, c)| (' '..='/').contains(c)).is_some() {}
+                    chars.next();
+                }
+                _ => {}
+            }
+            run_start = chars.peek().map_or(output.len(), |(j, _)| *j);
+        }
+        self.print(&output[run_start..]);
+    }
+
+    /// The screen as a standalone HTML page
+    pub fn to_html(&self) -> String {
+        html_page(&self.to_html_pre(None))
+    }
+
+    fn index(&self, x: u16, y: u16) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    fn print(&mut self, text: &str) {
+        for grapheme in text.graphemes(true) {
+            if grapheme.chars().all(char::is_control) {
+                continue;
+            }
+            let (x, y) = self.cursor;
+            if x < self.width && y < self.height {
+                let i = self.index(x, y);
+                self.cells[i] = Cell {
+                    grapheme: grapheme.to_string(),
+                    style: self.style,
+                };
+            }
+            self.cursor.0 = x.saturating_add(1);
+        }
+    }
+
+    fn control_sequence(&mut self, params: &str, last: char) {
+        let number = |i: usize, default: u16| {
+            params
+                .split(';')
+                .nth(i)
+                .and_then(|n| n.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(default)
+        };
+        match last {
+            'm' => crate::ansi::apply_sgr(params, &mut self.style),
+            'H' => self.cursor = (number(1, 1) - 1, number(0, 1) - 1),
+            'C' => self.cursor.0 = self.cursor.0.saturating_add(number(0, 1)),
+            // Only clearing everything is used, which fills the screen with the current background
+            'J' if params == "2" => {
+                let cleared = blank(Style {
+                    attributes: Attribute::Reset.into(),
+                    underline_color: None,
+                    ..self.style
+                });
+                self.cells.fill(cleared);
+            }
+            _ => {}
+        }
+    }
+
+    fn to_html_pre(&self, time: Option<Duration>) -> String {
+        let mut html = match time {
+            Some(time) => format!("<pre data-time=\"{}\">", time.as_millis()),
+            None => "<pre>".to_string(),
+        };
+        for row in self.cells.chunks(self.width.max(1) as usize) {
+            let mut current = None;
+            for cell in row {
+                if current != Some(cell.style) {
+                    if current.is_some() {
+                        html.push_str("</span>");
+                    }
+                    let _ = write!(html, "<span style=\"{}\">", css(&cell.style));
+                    current = Some(cell.style);
+                }
+                for c in cell.grapheme.chars() {
+                    match c {
+                        '&' => html.push_str("&amp;"),
+                        '<' => html.push_str("&lt;"),
+                        '>' => html.push_str("&gt;"),
+                        c => html.push(c),
+                    }
+                }
+            }
+            if current.is_some() {
+                html.push_str("</span>");
+            }
+            html.push('\n');
+        }
+        html.push_str("</pre>");
+        html
+    }
+}
+
+fn blank(style: Style) -> Cell {
+    Cell {
+        grapheme: " ".to_string(),
+        style,
+    }
+}
+
+fn css_color((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+// The inline CSS for a style
+fn css(style: &Style) -> String {
+    let rgb = |color: Option<Color>, default| {
+        color
+            .and_then(crate::color::color_to_rgb)
+            .unwrap_or(default)
+    };
+    let mut foreground = rgb(style.colors.foreground, DEFAULT_FOREGROUND);
+    let mut background = rgb(style.colors.background, DEFAULT_BACKGROUND);
+    let has = |attribute| style.attributes.has(attribute);
+    if has(Attribute::Reverse) {
+        std::mem::swap(&mut foreground, &mut background);
+    }
+    if has(Attribute::Hidden) {
+        foreground = background;
+    }
+
+    let mut css = format!(
+        "color:{};background:{}",
+        css_color(foreground),
+        css_color(background)
+    );
+    if has(Attribute::Bold) {
+        css.push_str(";font-weight:bold");
+    }
+    if has(Attribute::Dim) {
+        css.push_str(";opacity:0.6");
+    }
+    if has(Attribute::Italic) {
+        css.push_str(";font-style:italic");
+    }
+    let underline = [
+        (Attribute::Underlined, "solid"),
+        (Attribute::DoubleUnderlined, "double"),
+        (Attribute::Undercurled, "wavy"),
+        (Attribute::Underdotted, "dotted"),
+        (Attribute::Underdashed, "dashed"),
+    ]
+    .into_iter()
+    .find(|(attribute, _)| has(*attribute));
+    let crossed_out = has(Attribute::CrossedOut);
+    match (underline, crossed_out) {
+        (Some(_), true) => css.push_str(";text-decoration-line:underline line-through"),
+        (Some(_), false) => css.push_str(";text-decoration-line:underline"),
+        (None, true) => css.push_str(";text-decoration-line:line-through"),
+        (None, false) => {}
+    }
+    if let Some((_, line_style)) = underline {
+        let _ = write!(css, ";text-decoration-style:{line_style}");
+        if let Some(color) = style.underline_color.and_then(crate::color::color_to_rgb) {
+            let _ = write!(css, ";text-decoration-color:{}", css_color(color));
+        }
+    }
+    css
+}
+
+fn html_page(body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>\n\
+         pre {{ margin: 0; display: inline-block; font-family: monospace; line-height: 1.2; background: {}; }}\n\
+         </style>\n</head>\n<body>\n{body}\n</body>\n</html>\n",
+        css_color(DEFAULT_BACKGROUND)
+    )
+}
+
+/// A standalone HTML page that plays back recorded frames, e.g. from [`ScreenCapture::stop_recording`]. Each frame
+/// is shown at the time it was captured, and the animation loops
+pub fn frames_to_html(frames: &[(Duration, Screen)]) -> String {
+    let mut body = String::new();
+    for (i, (time, screen)) in frames.iter().enumerate() {
+        let mut pre = screen.to_html_pre(Some(*time));
+        if i > 0 {
+            pre = pre.replacen("<pre", "<pre hidden", 1);
+        }
+        body.push_str(&pre);
+        body.push('\n');
+    }
+    body.push_str(
+        "<script>\n\
+         const frames = [...document.querySelectorAll('pre')];\n\
+         const times = frames.map(frame => Number(frame.dataset.time));\n\
+         let current = 0;\n\
+         function next() {\n\
+         frames[current].hidden = true;\n\
+         current = (current + 1) % frames.length;\n\
+         frames[current].hidden = false;\n\
+         const delay = current + 1 < frames.length ? times[current + 1] - times[current] : 1000;\n\
+         setTimeout(next, delay);\n\
+         }\n\
+         if (frames.length > 1) setTimeout(next, times[1] - times[0]);\n\
+         </script>",
+    );
+    html_page(&body)
+}
+
+/// Keeps track of what's on the terminal. Added by [`ScreenCapturePlugin`]
+#[derive(Resource, Debug)]
+pub struct ScreenCapture {
+    screen: Screen,
+    recording: Option<Vec<(Duration, Screen)>>,
+    started: Duration,
+}
+
+impl ScreenCapture {
+    /// What's on the terminal right now
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    /// The current frame as a standalone HTML page
+    pub fn to_html(&self) -> String {
+        self.screen.to_html()
+    }
+
+    /// Keep a copy of every frame from now on
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Stop recording, and return the frames along with when they were drawn, relative to the first one
+    pub fn stop_recording(&mut self) -> Vec<(Duration, Screen)> {
+        self.recording.take().unwrap_or_default()
+    }
+}
+
+impl Default for ScreenCapture {
+    fn default() -> Self {
+        ScreenCapture {
+            screen: Screen::new(0, 0),
+            recording: None,
+            started: Duration::ZERO,
+        }
+    }
+}
+
+/// Adds [`ScreenCapture`], to get at what's on the terminal
+#[derive(Default)]
+pub struct ScreenCapturePlugin;
+
+impl Plugin for ScreenCapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScreenCapture>().add_systems(
+            PostUpdate,
+            (
+                start_capturing.before(CrosstermSet::Flush),
+                capture_frame.after(CrosstermSet::Flush),
+            ),
+        );
+    }
+}
+
+// Frames are taken from the backend once they're written, so they include everything that was written after the
+// screen was drawn, and what the pipelined render app wrote, if it's used
+fn start_capturing(window: Query<&CrosstermWindow>) {
+    if let Ok(window) = window.get_single() {
+        window.backend.capture_frames();
+    }
+}
+
+fn capture_frame(
+    mut capture: ResMut<ScreenCapture>,
+    window: Query<&CrosstermWindow>,
+    time: Res<Time<Real>>,
+) {
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+    let (width, height) = window.terminal_size();
+    if (capture.screen.width, capture.screen.height) != (width, height) {
+        capture.screen.resize(width, height);
+    }
+    let output = window.backend.take_captured();
+    if output.is_empty() {
+        return;
+    }
+    capture.screen.write(&output);
+
+    let now = time.elapsed();
+    let capture = &mut *capture;
+    if let Some(frames) = &mut capture.recording {
+        if frames.is_empty() {
+            capture.started = now;
+        }
+        frames.push((now - capture.started, capture.screen.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(screen: &Screen, y: u16) -> String {
+        (0..screen.width())
+            .map(|x| screen.get(x, y).unwrap().grapheme.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn control_sequences_move_the_cursor() {
+        let mut screen = Screen::new(8, 2);
+        screen.write(b"\x1b[2;3Hab\x1b[2Cc");
+        assert_eq!(row(&screen, 0), "        ");
+        assert_eq!(row(&screen, 1), "  ab  c ");
+    }
+
+    #[test]
+    fn sgr_styles_the_text() {
+        let mut screen = Screen::new(3, 1);
+        screen.write(b"a\x1b[1mb\x1b[0mc");
+        let attributes = |x| screen.get(x, 0).unwrap().style.attributes;
+        assert_eq!(row(&screen, 0), "abc");
+        assert!(!attributes(0).has(Attribute::Bold));
+        assert!(attributes(1).has(Attribute::Bold));
+        assert!(!attributes(2).has(Attribute::Bold));
+    }
+
+    #[test]
+    fn osc_strings_are_skipped() {
+        let mut screen = Screen::new(4, 1);
+        screen.write(b"a\x1b]0;title\x07b\x1b]0;title\x1b\\c");
+        assert_eq!(row(&screen, 0), "abc ");
+    }
+
+    #[test]
+    fn escape_sequences_without_a_bracket_are_skipped() {
+        let mut screen = Screen::new(4, 1);
+        screen.write(b"a\x1b(Bb\x1b7c\x1b8d");
+        assert_eq!(row(&screen, 0), "abcd");
+    }
+}
@@ -0,0 +1,311 @@
+//! Mapping colors down to what the terminal can show.
+//!
+//! Not every terminal can display 24-bit color. These functions find the closest color a terminal with fewer colors
+//! can show, using a perceptual distance so the result looks close rather than just being numerically close. The
+//! depth of the current terminal is available from
+//! [`TerminalCapabilities::color_depth`](crate::capabilities::TerminalCapabilities::color_depth).
+//!
+//! Images lose more than single colors do, so [`Dither`] spreads the difference out across neighbouring pixels.
+
+use crate::components::Color;
+
+/// How many colors a terminal can show
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum ColorDepth {
+    /// The 16 standard ANSI colors
+    Ansi16,
+    /// The xterm 256 color palette
+    Ansi256,
+    /// Any 24-bit RGB color
+    #[default]
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Guess the depth from the `COLORTERM` and `TERM` environment variables, which is the best there is
+    pub fn from_env() -> ColorDepth {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+
+        let term = std::env::var("TERM").unwrap_or_default();
+        if term.ends_with("-direct") {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    /// The closest color to `color` at this depth. Colors that every depth can show are returned as is
+    pub fn quantize(&self, color: Color) -> Color {
+        let rgb = match color {
+            Color::Rgb { r, g, b } => (r, g, b),
+            Color::AnsiValue(index) if *self == ColorDepth::Ansi16 => ansi256_to_rgb(index),
+            color => return color,
+        };
+        match self {
+            ColorDepth::TrueColor => color,
+            ColorDepth::Ansi256 => Color::AnsiValue(rgb_to_ansi256(rgb)),
+            ColorDepth::Ansi16 => rgb_to_ansi16(rgb),
+        }
+    }
+}
+
+// The 16 ANSI colors, as xterm shows them by default
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+// The levels each channel of the 6x6x6 color cube can take
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// How different two colors look. This is the "redmean" weighted distance, which is cheap and a lot closer to what
+/// people see than plain RGB distance. Zero means the colors are the same
+pub fn perceptual_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> f32 {
+    let red_mean = (a.0 as f32 + b.0 as f32) / 2.0;
+    let dr = a.0 as f32 - b.0 as f32;
+    let dg = a.1 as f32 - b.1 as f32;
+    let db = a.2 as f32 - b.2 as f32;
+    ((2.0 + red_mean / 256.0) * dr * dr
+        + 4.0 * dg * dg
+        + (2.0 + (255.0 - red_mean) / 256.0) * db * db)
+        .sqrt()
+}
+
+/// The RGB value of an entry in the xterm 256 color palette
+pub fn ansi256_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI16[index as usize].1,
+        16..=231 => {
+            let index = index - 16;
+            (
+                CUBE_LEVELS[(index / 36) as usize],
+                CUBE_LEVELS[(index / 6 % 6) as usize],
+                CUBE_LEVELS[(index % 6) as usize],
+            )
+        }
+        232..=255 => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+/// The RGB value of a color, going by xterm's default palette for the named ones. [`Color::Reset`] has none, it's
+/// whatever the terminal's default is
+pub fn color_to_rgb(color: Color) -> Option<(u8, u8, u8)> {
+    match color {
+        Color::Reset => None,
+        Color::Rgb { r, g, b } => Some((r, g, b)),
+        Color::AnsiValue(index) => Some(ansi256_to_rgb(index)),
+        color => ANSI16
+            .iter()
+            .find(|(named, _)| *named == color)
+            .map(|(_, rgb)| *rgb),
+    }
+}
+
+/// The index of the closest color in the xterm 256 color palette. Only the color cube and the grey ramp are
+/// considered, since the first 16 colors are often changed by terminal themes
+pub fn rgb_to_ansi256(rgb: (u8, u8, u8)) -> u8 {
+    // The nearest cube level for each channel, and the nearest grey
+    let cube_index = |channel: u8| {
+        CUBE_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, level)| level.abs_diff(channel))
+            .map_or(0, |(i, _)| i as u8)
+    };
+    let cube = 16 + 36 * cube_index(rgb.0) + 6 * cube_index(rgb.1) + cube_index(rgb.2);
+
+    let average = (rgb.0 as u16 + rgb.1 as u16 + rgb.2 as u16) / 3;
+    let grey = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+
+    if perceptual_distance(rgb, ansi256_to_rgb(grey))
+        < perceptual_distance(rgb, ansi256_to_rgb(cube))
+    {
+        grey
+    } else {
+        cube
+    }
+}
+
+/// The closest of the 16 ANSI colors
+pub fn rgb_to_ansi16(rgb: (u8, u8, u8)) -> Color {
+    ANSI16
+        .iter()
+        .min_by(|(_, a), (_, b)| {
+            perceptual_distance(rgb, *a).total_cmp(&perceptual_distance(rgb, *b))
+        })
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+/// A color `amount` of the way from `from` to `to`, between 0 and 1. Colors without an RGB value, like
+/// [`Color::Reset`], can't be mixed, so they switch over halfway instead
+pub fn mix(from: Color, to: Color, amount: f32) -> Color {
+    let amount = amount.clamp(0.0, 1.0);
+    match (color_to_rgb(from), color_to_rgb(to)) {
+        (Some(from), Some(to)) => {
+            let channel =
+                |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+            Color::Rgb {
+                r: channel(from.0, to.0),
+                g: channel(from.1, to.1),
+                b: channel(from.2, to.2),
+            }
+        }
+        _ if amount < 0.5 => from,
+        _ => to,
+    }
+}
+
+/// A fully saturated, fully bright color with a hue of `hue` turns around the color wheel, so 0 is red, a third is
+/// green and two thirds is blue
+pub fn hue(hue: f32) -> Color {
+    let hue = hue.rem_euclid(1.0) * 6.0;
+    let rising = ((hue % 1.0) * 255.0).round() as u8;
+    let falling = 255 - rising;
+    let (r, g, b) = match hue as u8 {
+        0 => (255, rising, 0),
+        1 => (falling, 255, 0),
+        2 => (0, 255, rising),
+        3 => (0, falling, 255),
+        4 => (rising, 0, 255),
+        _ => (255, 0, falling),
+    };
+    Color::Rgb { r, g, b }
+}
+
+/// How to make up for the colors an image loses when it's brought down to fewer of them. Dithering mixes nearby
+/// colors in a pattern, so from a distance gradients stay smooth instead of turning into bands
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Dither {
+    /// Use the closest color for each pixel
+    None,
+    /// Nudge each pixel by a repeating 4x4 pattern before picking its color. The result is stable from frame to
+    /// frame, which suits images that change, like procedural textures or camera input
+    Ordered,
+    /// Pass each pixel's error on to the pixels after it. Looks the best for still images, but a small change can
+    /// ripple across the whole image
+    #[default]
+    FloydSteinberg,
+}
+
+impl Dither {
+    /// Bring an image `width` pixels wide, given row by row, down to the colors `depth` can show
+    pub fn apply(&self, pixels: &[(u8, u8, u8)], width: usize, depth: ColorDepth) -> Vec<Color> {
+        match self {
+            Dither::None => pixels
+                .iter()
+                .map(|(r, g, b)| {
+                    depth.quantize(Color::Rgb {
+                        r: *r,
+                        g: *g,
+                        b: *b,
+                    })
+                })
+                .collect(),
+            Dither::Ordered => ordered_dither(pixels, width, depth),
+            Dither::FloydSteinberg => floyd_steinberg_dither(pixels, width, depth),
+        }
+    }
+}
+
+// A 4x4 Bayer matrix, the order pixels in each block get pushed over the threshold
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+// Roughly how far apart neighbouring colors are at each depth, which is how far dithering has to nudge a pixel
+fn dither_spread(depth: ColorDepth) -> f32 {
+    match depth {
+        ColorDepth::Ansi16 => 64.0,
+        ColorDepth::Ansi256 => 40.0,
+        ColorDepth::TrueColor => 0.0,
+    }
+}
+
+fn quantize_rgb(depth: ColorDepth, (r, g, b): (f32, f32, f32)) -> (Color, (f32, f32, f32)) {
+    let channel = |channel: f32| channel.round().clamp(0.0, 255.0) as u8;
+    let color = depth.quantize(Color::Rgb {
+        r: channel(r),
+        g: channel(g),
+        b: channel(b),
+    });
+    let (qr, qg, qb) = color_to_rgb(color).unwrap_or_default();
+    (color, (qr as f32, qg as f32, qb as f32))
+}
+
+/// Bring an image `width` pixels wide, given row by row, down to the colors `depth` can show, with ordered (Bayer)
+/// dithering. See [`Dither::Ordered`]
+pub fn ordered_dither(pixels: &[(u8, u8, u8)], width: usize, depth: ColorDepth) -> Vec<Color> {
+    let width = width.max(1);
+    let spread = dither_spread(depth);
+    pixels
+        .iter()
+        .enumerate()
+        .map(|(i, (r, g, b))| {
+            let (x, y) = (i % width, i / width);
+            let nudge = ((BAYER[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5) * spread;
+            quantize_rgb(
+                depth,
+                (*r as f32 + nudge, *g as f32 + nudge, *b as f32 + nudge),
+            )
+            .0
+        })
+        .collect()
+}
+
+/// Bring an image `width` pixels wide, given row by row, down to the colors `depth` can show, with Floyd–Steinberg
+/// error diffusion. See [`Dither::FloydSteinberg`]
+pub fn floyd_steinberg_dither(
+    pixels: &[(u8, u8, u8)],
+    width: usize,
+    depth: ColorDepth,
+) -> Vec<Color> {
+    let width = width.max(1);
+    let mut errors = vec![(0.0f32, 0.0f32, 0.0f32); pixels.len()];
+    let mut colors = Vec::with_capacity(pixels.len());
+
+    for (i, (r, g, b)) in pixels.iter().enumerate() {
+        let (er, eg, eb) = errors[i];
+        let wanted = (*r as f32 + er, *g as f32 + eg, *b as f32 + eb);
+        let (color, got) = quantize_rgb(depth, wanted);
+        colors.push(color);
+
+        let error = (wanted.0 - got.0, wanted.1 - got.1, wanted.2 - got.2);
+        let x = i % width;
+        let mut spread = |j: usize, share: f32| {
+            if let Some(pixel) = errors.get_mut(j) {
+                pixel.0 += error.0 * share;
+                pixel.1 += error.1 * share;
+                pixel.2 += error.2 * share;
+            }
+        };
+        if x + 1 < width {
+            spread(i + 1, 7.0 / 16.0);
+            spread(i + width + 1, 1.0 / 16.0);
+        }
+        if x > 0 {
+            spread(i + width - 1, 3.0 / 16.0);
+        }
+        spread(i + width, 5.0 / 16.0);
+    }
+    colors
+}
@@ -3,13 +3,30 @@ use bevy::reflect::TypePath;
 use bevy::utils::{HashMap, HashSet};
 use bevy_asset::Handle;
 use serde::{Deserialize, Serialize};
+
+use crate::render::Cell;
+use crate::theme::{Theme, ThemeColors};
 use std::default::Default;
 use unicode_segmentation::UnicodeSegmentation;
 
 pub use crossterm::style::Color;
 
+/// The position and size every entity had the last time it was drawn.
+///
+/// The renderer uses this to blank out the area an entity used to cover before drawing it somewhere else, and to
+/// find the other entities that were underneath it. An entry is created once an entity's sprite has loaded. After
+/// rendering, only the entries of visible entities that moved, switched sprites, or whose sprite changed shape are
+/// updated, so the rest keep what they had when they were last drawn. An entry is removed once its entity loses its
+/// sprite or is despawned, after the area it covered has been cleared.
 #[derive(Default, Resource)]
-pub(crate) struct PreviousEntityDetails(pub HashMap<Entity, (PreviousPosition, PreviousSize)>);
+pub struct PreviousEntityDetails(pub HashMap<Entity, (PreviousPosition, PreviousSize)>);
+
+impl PreviousEntityDetails {
+    /// The position and size `entity` had last frame, or None if it hasn't been recorded yet
+    pub fn get(&self, entity: Entity) -> Option<&(PreviousPosition, PreviousSize)> {
+        self.0.get(&entity)
+    }
+}
 
 #[derive(Resource)]
 pub(crate) struct PreviousWindowColors(pub Colors);
@@ -20,14 +37,46 @@ impl Default for PreviousWindowColors {
     }
 }
 
+/// Everything written to the terminal during a frame. It's written out in one go at the end of the frame, so slow
+/// terminals don't show half drawn frames. The buffer is kept between frames to avoid reallocating it
+#[derive(Resource, Default)]
+pub(crate) struct FrameOutput(pub Vec<u8>);
+
+/// The work the renderer has to do this frame.
+///
+/// This is rebuilt every frame in `PostUpdate` by the systems in [`crate::CrosstermSet::CalculateRedraw`] and consumed
+/// by [`crate::CrosstermSet::Render`]. Custom render systems can run between the two sets to read it, or to add
+/// entities of their own so they get drawn in the right order.
 #[derive(Default, Resource)]
-pub(crate) struct EntitiesToRedraw {
+pub struct EntitiesToRedraw {
+    /// The whole screen is cleared before drawing. When this is set, `to_draw` contains every entity and
+    /// `to_clear` is empty.
     pub full_redraw: bool,
+    /// Entities whose previous area (see [`PreviousEntityDetails`]) is blanked out before anything is drawn
     pub to_clear: HashSet<Entity>,
+    /// Entities to draw, sorted from back to front by their z position
     pub to_draw: Vec<EntityDepth>,
 }
 
-pub(crate) struct EntityDepth {
+impl EntitiesToRedraw {
+    /// Returns true if `entity` is going to be drawn this frame
+    pub fn will_draw(&self, entity: Entity) -> bool {
+        self.full_redraw || self.to_draw.iter().any(|item| item.entity == entity)
+    }
+
+    /// Queue `entity` to be drawn at depth `z`, keeping `to_draw` sorted. Does nothing if it is already queued
+    pub fn draw(&mut self, entity: Entity, z: i32) {
+        if self.to_draw.iter().any(|item| item.entity == entity) {
+            return;
+        }
+        let index = self.to_draw.partition_point(|item| item.z <= z);
+        self.to_draw.insert(index, EntityDepth { entity, z });
+    }
+}
+
+/// An entity scheduled to be drawn, along with the depth it is drawn at
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct EntityDepth {
     pub entity: Entity,
     pub z: i32,
 }
@@ -92,8 +141,11 @@ impl Colors {
     }
 }
 
+/// Attributes are written as a list of names, e.g. `[Bold, Italic]`. Older stylemaps stored them as a bit mask, which
+/// can still be read
 mod attribute_parser {
-    use serde::de::Visitor;
+    use crossterm::style::Attribute;
+    use serde::de::{SeqAccess, Visitor};
     use serde::{Deserializer, Serializer};
 
     pub fn serialize<S>(
@@ -103,13 +155,7 @@ mod attribute_parser {
     where
         S: Serializer,
     {
-        let mut attr_bits = 0u32;
-        for attr in crossterm::style::Attribute::iterator() {
-            if attrs.has(attr) {
-                attr_bits |= attr.bytes();
-            }
-        }
-        serializer.serialize_u32(attr_bits)
+        serializer.collect_seq(Attribute::iterator().filter(|attr| attrs.has(*attr)))
     }
 
     struct AttrVisitor;
@@ -118,28 +164,47 @@ mod attribute_parser {
         type Value = crossterm::style::Attributes;
 
         fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-            formatter.write_str("expecting an u32")
+            formatter.write_str("expecting a list of attributes or an u32")
         }
 
-        fn visit_u32<E>(self, attr_bits: u32) -> Result<Self::Value, E>
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut attrs = crossterm::style::Attributes::default();
+            while let Some(attr) = seq.next_element::<Attribute>()? {
+                attrs.set(attr);
+            }
+            Ok(attrs)
+        }
+
+        fn visit_u64<E>(self, attr_bits: u64) -> Result<Self::Value, E>
         where
             E: serde::de::Error,
         {
+            let attr_bits = u32::try_from(attr_bits).map_err(E::custom)?;
             let mut attrs = crossterm::style::Attributes::default();
-            for attr in crossterm::style::Attribute::iterator() {
+            for attr in Attribute::iterator() {
                 if attr_bits & attr.bytes() != 0 {
                     attrs.set(attr);
                 }
             }
             Ok(attrs)
         }
+
+        fn visit_i64<E>(self, attr_bits: i64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            self.visit_u64(u64::try_from(attr_bits).map_err(E::custom)?)
+        }
     }
 
     pub fn deserialize<'de, D>(deserializer: D) -> Result<crossterm::style::Attributes, D::Error>
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_u32(AttrVisitor)
+        deserializer.deserialize_any(AttrVisitor)
     }
 }
 
@@ -149,22 +214,31 @@ mod attribute_parser {
 // impl Reflect for StyleAttributes {}
 // impl FromReflect for StyleAttributes {}
 
-#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Style {
     pub colors: Colors,
     #[serde(with = "attribute_parser")]
     pub attributes: crossterm::style::Attributes,
+    /// The color of any underline, if it should differ from the text. Only some terminals support this, and the
+    /// curly, dotted and dashed underline attributes. Elsewhere they're drawn as a plain underline in the text color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub underline_color: Option<Color>,
 }
 
 impl Style {
     pub fn new(colors: Colors, attributes: crossterm::style::Attributes) -> Style {
-        Style { colors, attributes }
+        Style {
+            colors,
+            attributes,
+            underline_color: None,
+        }
     }
 
     pub fn with_attrib(attribute: crossterm::style::Attribute) -> Style {
         Style {
             colors: Colors::default(),
             attributes: attribute.into(),
+            underline_color: None,
         }
     }
 
@@ -172,6 +246,7 @@ impl Style {
         Style {
             colors: Colors::default(),
             attributes,
+            underline_color: None,
         }
     }
 
@@ -179,6 +254,7 @@ impl Style {
         Style {
             colors: Colors::fg(foreground),
             attributes: crossterm::style::Attribute::Reset.into(),
+            underline_color: None,
         }
     }
 
@@ -186,6 +262,7 @@ impl Style {
         Style {
             colors: Colors::bg(background),
             attributes: crossterm::style::Attribute::Reset.into(),
+            underline_color: None,
         }
     }
 
@@ -193,8 +270,29 @@ impl Style {
         Style {
             colors,
             attributes: crossterm::style::Attribute::Reset.into(),
+            underline_color: None,
         }
     }
+
+    /// Make the text blink. Terminals that can't blink show it steady, unless software blinking is turned on with
+    /// [`CrosstermWindowSettings::set_software_blink`](crate::CrosstermWindowSettings::set_software_blink)
+    #[must_use]
+    pub fn with_blink(mut self) -> Style {
+        self.attributes.set(crossterm::style::Attribute::SlowBlink);
+        self
+    }
+
+    pub fn has_blink(&self) -> bool {
+        self.attributes.has(crossterm::style::Attribute::SlowBlink)
+            || self.attributes.has(crossterm::style::Attribute::RapidBlink)
+    }
+
+    /// Underline the text in `color`, see [`Style::underline_color`]
+    #[must_use]
+    pub fn with_underline_color(mut self, color: Color) -> Style {
+        self.underline_color = Some(color);
+        self
+    }
 }
 
 impl Default for Style {
@@ -202,19 +300,204 @@ impl Default for Style {
         Style {
             attributes: crossterm::style::Attribute::Reset.into(),
             colors: Colors::default(),
+            underline_color: None,
         }
     }
 }
 
-#[derive(Default, Serialize, Deserialize, PartialEq, Eq, TypePath, Asset)]
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, TypePath, Asset)]
 pub struct StyleMap {
     pub style: Style,
     pub map: Vec<Vec<Style>>,
+    /// Theme colors that replace the colors of `style`, see [`Theme`]
+    #[serde(default, skip_serializing_if = "ThemeColors::is_empty")]
+    pub theme: ThemeColors,
+    /// Styles for runs of the sprite's text rather than for cells. They're placed on the sprite's text when it's
+    /// drawn, so they follow the text if it's re-wrapped, and win over the styles in `map`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<StyleSpan>,
+    /// Numbered color pairs from the [`Theme`] for cells, laid out like `map`. A cell's pair colors it over its style
+    /// in `map`, or the base style where `map` doesn't reach, see [`Theme::pair`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pairs: Vec<Vec<Option<u16>>>,
+}
+
+/// A style for part of a sprite's text, see [`StyleMap::spans`]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StyleSpan {
+    pub range: SpanRange,
+    pub style: Style,
+}
+
+impl StyleSpan {
+    pub fn new(range: SpanRange, style: Style) -> StyleSpan {
+        StyleSpan { range, style }
+    }
+}
+
+/// Which part of a sprite's text a [`StyleSpan`] styles
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SpanRange {
+    /// Graphemes of the whole text, counting each line break as one. Re-wrapping the text by turning spaces into
+    /// line breaks leaves the span on the same words
+    Graphemes(std::ops::Range<usize>),
+    /// Bytes of the whole text, as in the string the sprite was made from, e.g. the range of a regex match. A
+    /// grapheme is styled if it starts in the range
+    Bytes(std::ops::Range<usize>),
+    /// Graphemes of one line, as (line, range)
+    Line(usize, std::ops::Range<usize>),
 }
 
 impl StyleMap {
     pub fn new(style: Style, map: Vec<Vec<Style>>) -> StyleMap {
-        StyleMap { style, map }
+        StyleMap {
+            style,
+            map,
+            theme: ThemeColors::default(),
+            spans: Vec::new(),
+            pairs: Vec::new(),
+        }
+    }
+
+    /// Build a stylemap up a row, region or cell at a time, see [`StyleMapBuilder`]
+    pub fn builder() -> StyleMapBuilder {
+        StyleMapBuilder::default()
+    }
+
+    /// Style the parts of `sprite` that match each pattern, a line at a time. Where matches overlap, the later
+    /// pattern's style wins. Useful for picking out numbers, keywords or map symbols, e.g.
+    /// `StyleMap::from_patterns(&map, &[(Regex::new("~+").unwrap(), water)])`
+    #[cfg(feature = "regex")]
+    pub fn from_patterns(sprite: &Sprite, patterns: &[(regex::Regex, Style)]) -> StyleMap {
+        let style = Style::default();
+        let map = sprite
+            .graphemes()
+            .iter()
+            .map(|row| {
+                let line: String = row
+                    .iter()
+                    .map(|grapheme| sprite.grapheme(grapheme))
+                    .collect();
+                // Where each grapheme starts in the line
+                let starts: Vec<usize> = row
+                    .iter()
+                    .scan(0, |start, grapheme| {
+                        let this = *start;
+                        *start += grapheme.1 - grapheme.0;
+                        Some(this)
+                    })
+                    .collect();
+
+                let mut styles = vec![style; row.len()];
+                for (pattern, pattern_style) in patterns {
+                    for found in pattern.find_iter(&line) {
+                        for (column, start) in starts.iter().enumerate() {
+                            if found.range().contains(start) {
+                                styles[column] = *pattern_style;
+                            }
+                        }
+                    }
+                }
+                styles
+            })
+            .collect();
+        StyleMap::new(style, map)
+    }
+
+    /// A stylemap that takes its colors from the [`Theme`]
+    pub fn with_theme(theme: ThemeColors) -> StyleMap {
+        StyleMap {
+            theme,
+            ..Default::default()
+        }
+    }
+
+    /// The style for graphemes that don't have their own, with the theme colors applied
+    pub fn base_style(&self, theme: &Theme) -> Style {
+        theme.apply(&self.theme, self.style)
+    }
+
+    /// Returns true if any of the styles blink
+    pub fn has_blink(&self) -> bool {
+        std::iter::once(&self.style)
+            .chain(self.map.iter().flatten())
+            .chain(self.spans.iter().map(|span| &span.style))
+            .any(Style::has_blink)
+    }
+
+    /// The stylemap with the colors of its pairs looked up in `theme` and put in the map. It's only copied if it has
+    /// pairs
+    pub fn resolve_pairs(&self, theme: &Theme) -> std::borrow::Cow<'_, StyleMap> {
+        if self.pairs.is_empty() {
+            return std::borrow::Cow::Borrowed(self);
+        }
+
+        let mut map = self.map.clone();
+        let base_style = self.base_style(theme);
+        for (y, row) in self.pairs.iter().enumerate() {
+            for (x, number) in row.iter().enumerate() {
+                let Some(number) = *number else {
+                    continue;
+                };
+                if map.len() <= y {
+                    map.resize_with(y + 1, Vec::new);
+                }
+                if map[y].len() <= x {
+                    map[y].resize(x + 1, base_style);
+                }
+                map[y][x] = theme.apply_pair(number, map[y][x]);
+            }
+        }
+        std::borrow::Cow::Owned(StyleMap {
+            map,
+            pairs: Vec::new(),
+            ..self.clone()
+        })
+    }
+
+    /// The stylemap with its spans placed on `sprite`'s text, as cells of the map. It's only copied if it has spans
+    pub fn resolve_spans(&self, sprite: &Sprite) -> std::borrow::Cow<'_, StyleMap> {
+        if self.spans.is_empty() {
+            return std::borrow::Cow::Borrowed(self);
+        }
+
+        let mut map = self.map.clone();
+        // How many graphemes come before each line, line breaks included
+        let line_starts: Vec<usize> = sprite
+            .graphemes()
+            .iter()
+            .scan(0, |start, row| {
+                let this = *start;
+                *start += row.len() + 1;
+                Some(this)
+            })
+            .collect();
+        for span in &self.spans {
+            for (y, row) in sprite.graphemes().iter().enumerate() {
+                for (x, grapheme) in row.iter().enumerate() {
+                    let styled = match &span.range {
+                        SpanRange::Graphemes(range) => range.contains(&(line_starts[y] + x)),
+                        SpanRange::Bytes(range) => range.contains(&grapheme.0),
+                        SpanRange::Line(line, range) => *line == y && range.contains(&x),
+                    };
+                    if !styled {
+                        continue;
+                    }
+                    if map.len() <= y {
+                        map.resize_with(y + 1, Vec::new);
+                    }
+                    if map[y].len() <= x {
+                        map[y].resize(x + 1, self.style);
+                    }
+                    map[y][x] = span.style;
+                }
+            }
+        }
+        std::borrow::Cow::Owned(StyleMap {
+            map,
+            spans: Vec::new(),
+            ..self.clone()
+        })
     }
 
     pub fn with_attrib(attribute: crossterm::style::Attribute) -> StyleMap {
@@ -252,6 +535,22 @@ impl StyleMap {
         }
     }
 
+    /// Write the stylemap to a `.stylemap` file
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        let ron = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        std::fs::write(path, ron)
+    }
+
+    /// The first style in the map for a cell that `sprite` doesn't have, as (x, y). Rows can be shorter than the
+    /// sprite's, but not longer
+    pub fn out_of_bounds(&self, sprite: &Sprite) -> Option<(usize, usize)> {
+        self.map.iter().enumerate().find_map(|(y, row)| {
+            let width = sprite.graphemes().get(y).map_or(0, Vec::len);
+            (row.len() > width).then_some((width, y))
+        })
+    }
+
     /// If there is a style available in the map, this fetches it. Otherwise, this returns None
     pub fn style_at(&self, x: usize, y: usize) -> Option<&Style> {
         self.map.get(y).and_then(|vec| vec.get(x))
@@ -269,10 +568,137 @@ impl StyleMap {
     }
 }
 
-#[derive(Eq, PartialEq, Debug, Component)]
+enum StyleMapEdit {
+    Row(usize, Style),
+    Region(URect, Style),
+    Cell(usize, usize, Style),
+    Span(SpanRange, Style),
+}
+
+/// Builds a [`StyleMap`] from styles for rows, regions and single cells, e.g.
+/// `StyleMap::builder().row(0, title).region(URect::new(2, 1, 6, 3), highlight).build()`. Later styles replace
+/// earlier ones where they overlap, and cells without a style get the base style
+#[derive(Default)]
+pub struct StyleMapBuilder {
+    style: Style,
+    width: Option<usize>,
+    edits: Vec<StyleMapEdit>,
+}
+
+impl StyleMapBuilder {
+    /// The style of cells that aren't given one
+    #[must_use]
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// How wide rows are. Without this, rows are as wide as the furthest right cell given a style
+    #[must_use]
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// Style every cell in row `y`
+    #[must_use]
+    pub fn row(mut self, y: usize, style: Style) -> Self {
+        self.edits.push(StyleMapEdit::Row(y, style));
+        self
+    }
+
+    /// Style every cell in `rect`, not including its max corner
+    #[must_use]
+    pub fn region(mut self, rect: URect, style: Style) -> Self {
+        self.edits.push(StyleMapEdit::Region(rect, style));
+        self
+    }
+
+    #[must_use]
+    pub fn cell(mut self, x: usize, y: usize, style: Style) -> Self {
+        self.edits.push(StyleMapEdit::Cell(x, y, style));
+        self
+    }
+
+    /// Style each cell, given as ((x, y), style)
+    #[must_use]
+    pub fn cells<I: IntoIterator<Item = ((usize, usize), Style)>>(mut self, cells: I) -> Self {
+        self.edits.extend(
+            cells
+                .into_iter()
+                .map(|((x, y), style)| StyleMapEdit::Cell(x, y, style)),
+        );
+        self
+    }
+
+    /// Style part of the sprite's text, wherever it ends up, see [`StyleMap::spans`]
+    #[must_use]
+    pub fn span(mut self, range: SpanRange, style: Style) -> Self {
+        self.edits.push(StyleMapEdit::Span(range, style));
+        self
+    }
+
+    pub fn build(self) -> StyleMap {
+        let width = self.width.unwrap_or_else(|| {
+            self.edits
+                .iter()
+                .map(|edit| match edit {
+                    StyleMapEdit::Row(..) | StyleMapEdit::Span(..) => 0,
+                    StyleMapEdit::Region(rect, _) => rect.max.x as usize,
+                    StyleMapEdit::Cell(x, _, _) => x + 1,
+                })
+                .max()
+                .unwrap_or(0)
+        });
+
+        let mut map: Vec<Vec<Style>> = Vec::new();
+        let mut spans = Vec::new();
+        let mut set = |x: usize, y: usize, style: Style| {
+            if map.len() <= y {
+                map.resize_with(y + 1, Vec::new);
+            }
+            let row = &mut map[y];
+            if row.len() <= x {
+                row.resize(x + 1, self.style);
+            }
+            row[x] = style;
+        };
+        for edit in &self.edits {
+            match *edit {
+                StyleMapEdit::Row(y, style) => {
+                    for x in 0..width {
+                        set(x, y, style);
+                    }
+                }
+                StyleMapEdit::Region(rect, style) => {
+                    for y in rect.min.y..rect.max.y {
+                        for x in rect.min.x..rect.max.x {
+                            set(x as usize, y as usize, style);
+                        }
+                    }
+                }
+                StyleMapEdit::Cell(x, y, style) => set(x, y, style),
+                StyleMapEdit::Span(ref range, style) => {
+                    spans.push(StyleSpan::new(range.clone(), style))
+                }
+            }
+        }
+        StyleMap {
+            spans,
+            ..StyleMap::new(self.style, map)
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Component)]
 pub struct Visible {
     pub is_visible: bool,
+    /// Whether cells holding `transparent_char`, and the padding past the end of short lines, are left undrawn so
+    /// whatever is beneath shows through. Cells the stylemap gives a style to are always drawn
     pub is_transparent: bool,
+    /// The character that's see-through when the entity is transparent, a space by default. Art that uses something
+    /// else, like `.`, can have spaces that are drawn like any other character
+    pub transparent_char: char,
 }
 
 impl Default for Visible {
@@ -280,6 +706,7 @@ impl Default for Visible {
         Visible {
             is_visible: true,
             is_transparent: false,
+            transparent_char: ' ',
         }
     }
 }
@@ -287,20 +714,48 @@ impl Default for Visible {
 impl Visible {
     pub fn invisible() -> Visible {
         Visible {
-            is_transparent: false,
             is_visible: false,
+            ..Default::default()
         }
     }
 
     pub fn transparent() -> Visible {
         Visible {
-            is_visible: true,
             is_transparent: true,
+            ..Default::default()
         }
     }
+
+    /// Change which character is see-through, e.g. `Visible::transparent().with_transparent_char('.')`
+    #[must_use]
+    pub fn with_transparent_char(mut self, transparent_char: char) -> Self {
+        self.transparent_char = transparent_char;
+        self
+    }
+
+    /// Whether `grapheme` is the transparent character
+    pub fn is_transparent_char(&self, grapheme: &str) -> bool {
+        let mut chars = grapheme.chars();
+        chars.next() == Some(self.transparent_char) && chars.next().is_none()
+    }
+}
+
+/// How an entity is drawn over whatever is already on the screen beneath it. Cells that are see-through, because of
+/// [`Visible::transparent`] or the sprite's mask, are skipped in every mode
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Draw the sprite as it is, covering everything beneath it
+    #[default]
+    Replace,
+    /// Draw the sprite's characters in its own foreground color, keeping the background beneath it
+    Foreground,
+    /// Only change the background color, keeping the characters beneath it and their foreground color
+    Background,
+    /// Show what's beneath the sprite in reverse video, using the sprite's shape as a mask
+    Invert,
 }
 
-#[derive(Default, Eq, PartialEq, Debug, Reflect, Asset)]
+#[derive(Default, Clone, Eq, PartialEq, Debug, Reflect, Asset)]
 pub struct Sprite {
     // The whole sprites's data
     data: String,
@@ -309,6 +764,8 @@ pub struct Sprite {
     // must be updated in tandem
     graphemes: Vec<Vec<(usize, usize)>>,
     max_width: usize,
+    // Which cells are drawn, if that's set explicitly rather than going by the graphemes
+    mask: Option<Vec<Vec<bool>>>,
 }
 
 impl Sprite {
@@ -376,9 +833,214 @@ impl Sprite {
         self.graphemes.clear();
         Sprite::convert_to_sprite(self);
     }
+
+    /// Which cells are drawn, row by row, if the sprite has a mask
+    pub fn mask(&self) -> Option<&[Vec<bool>]> {
+        self.mask.as_deref()
+    }
+
+    /// Set exactly which cells are drawn, row by row, with `true` for cells that are drawn and `false` for cells
+    /// that are see-through. Cells past the end of the mask are see-through. This takes the place of
+    /// [`Visible::transparent`], so sprites can have spaces that paint their background next to holes that don't.
+    /// `None` removes the mask
+    pub fn set_mask(&mut self, mask: Option<Vec<Vec<bool>>>) -> &mut Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Read a mask from text laid out like the sprite, where spaces are see-through and anything else is drawn
+    pub fn mask_from_text(text: &str) -> Vec<Vec<bool>> {
+        text.lines()
+            .map(|line| {
+                line.graphemes(true)
+                    .map(|grapheme| grapheme != " ")
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Whether the mask hides the cell at (`x`, `y`), or `None` if the sprite doesn't have a mask
+    pub fn is_masked(&self, x: usize, y: usize) -> Option<bool> {
+        let mask = self.mask.as_ref()?;
+        Some(
+            !mask
+                .get(y)
+                .and_then(|row| row.get(x))
+                .copied()
+                .unwrap_or(false),
+        )
+    }
+
+    /// Write the sprite to a text file, which can be loaded back in as a sprite
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, &self.data)
+    }
+}
+
+/// A sprite that carries the style of every grapheme along with it, so it doesn't need a [`StyleMap`].
+///
+/// Spawn an entity with a `Handle<ColoredSprite>` instead of the sprite and stylemap handles, and the plugin fills
+/// those in. Entities sharing a colored sprite share the generated sprite and stylemap as well.
+#[derive(Clone, Default, Eq, PartialEq, Debug, Serialize, Deserialize, TypePath, Asset)]
+pub struct ColoredSprite {
+    /// The style of cells past the end of shorter rows
+    pub style: Style,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+impl ColoredSprite {
+    pub fn new(style: Style, rows: Vec<Vec<Cell>>) -> ColoredSprite {
+        ColoredSprite { style, rows }
+    }
+
+    /// Parse text colored with ANSI escape sequences (SGR), such as exported ANSI art. Other escape sequences are
+    /// ignored
+    pub fn from_ansi(text: &str) -> ColoredSprite {
+        crate::ansi::parse(text)
+    }
+
+    /// Combine a sprite with its stylemap. Theme colors are resolved with `theme`, since a colored sprite doesn't
+    /// refer to the theme
+    pub fn from_parts(sprite: &Sprite, stylemap: &StyleMap, theme: &Theme) -> ColoredSprite {
+        let stylemap = stylemap.resolve_pairs(theme);
+        let stylemap = stylemap.resolve_spans(sprite);
+        let style = stylemap.base_style(theme);
+        let rows = sprite
+            .graphemes()
+            .iter()
+            .enumerate()
+            .map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(x, grapheme)| Cell {
+                        grapheme: sprite.grapheme(grapheme).to_string(),
+                        style: stylemap.style_at(x, y).copied().unwrap_or(style),
+                    })
+                    .collect()
+            })
+            .collect();
+        ColoredSprite { style, rows }
+    }
+
+    /// Split into a sprite and a stylemap that draw the same thing
+    pub fn to_parts(&self) -> (Sprite, StyleMap) {
+        let data = self
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| cell.grapheme.as_str())
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let map = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.style).collect())
+            .collect();
+        (Sprite::new(data), StyleMap::new(self.style, map))
+    }
+
+    /// The sprite as text with ANSI escape sequences, the same as [`ColoredSprite::from_ansi`] reads
+    pub fn to_ansi(&self) -> String {
+        crate::ansi::write(self)
+    }
+
+    /// Write the sprite to a `.ans` file. To save it as a sprite and stylemap, use [`ColoredSprite::to_parts`]
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_ansi())
+    }
+
+    pub fn width(&self) -> usize {
+        self.rows.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> Option<&Cell> {
+        self.rows.get(y).and_then(|row| row.get(x))
+    }
+
+    /// Stretch the sprite to `width` by `height` as a nine-patch. The outer `border` cells, as (columns, rows), make
+    /// up the edges: the corners are kept as they are, the edges repeat along their length, and the middle repeats to
+    /// fill the rest. A 3x3 sprite with a border of (1, 1) is enough for a box of any size
+    pub fn nine_patch(&self, border: (usize, usize), width: usize, height: usize) -> ColoredSprite {
+        // Which column or row of the sprite ends up at `i` along an axis `size` long
+        fn source(i: usize, size: usize, length: usize, border: usize) -> usize {
+            let border = border.min(length / 2);
+            let middle = length - border * 2;
+            if i < border {
+                i
+            } else if i + border >= size {
+                length - (size - i)
+            } else if middle == 0 {
+                border.saturating_sub(1)
+            } else {
+                border + (i - border) % middle
+            }
+        }
+
+        let (source_width, source_height) = (self.width(), self.height());
+        if source_width == 0 || source_height == 0 {
+            return ColoredSprite::new(self.style, Vec::new());
+        }
+        let rows = (0..height)
+            .map(|y| {
+                let y = source(y, height, source_height, border.1);
+                (0..width)
+                    .map(|x| {
+                        let x = source(x, width, source_width, border.0);
+                        self.cell(x, y).cloned().unwrap_or_else(|| Cell {
+                            grapheme: " ".to_string(),
+                            style: self.style,
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        ColoredSprite::new(self.style, rows)
+    }
+}
+
+/// A corner of the screen
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Corner {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    pub fn is_top(&self) -> bool {
+        matches!(self, Corner::TopLeft | Corner::TopRight)
+    }
+
+    pub fn is_left(&self) -> bool {
+        matches!(self, Corner::TopLeft | Corner::BottomLeft)
+    }
+
+    /// Where the top left of something `size` big goes to fit in this corner of `area`, both as (width, height)
+    pub fn place(&self, area: (u16, u16), size: (u16, u16)) -> (u16, u16) {
+        let x = if self.is_left() {
+            0
+        } else {
+            area.0.saturating_sub(size.0)
+        };
+        let y = if self.is_top() {
+            0
+        } else {
+            area.1.saturating_sub(size.1)
+        };
+        (x, y)
+    }
 }
 
-#[derive(Default, Eq, PartialEq, Debug, Component)]
+#[derive(Default, Clone, Eq, PartialEq, Debug, Component)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
@@ -413,15 +1075,17 @@ impl Position {
     }
 }
 
+/// Where an entity was last drawn. See [`PreviousEntityDetails`]
 #[derive(Default, Eq, PartialEq, Debug)]
-pub(crate) struct PreviousPosition {
+pub struct PreviousPosition {
     pub x: i32,
     pub y: i32,
     pub z: i32,
 }
 
+/// The size an entity's sprite had when it was last drawn. See [`PreviousEntityDetails`]
 #[derive(Default, Eq, PartialEq, Debug)]
-pub(crate) struct PreviousSize {
+pub struct PreviousSize {
     pub width: u16,
     pub height: u16,
 }
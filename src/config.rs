@@ -0,0 +1,146 @@
+//! Letting the players of a game change its window settings and theme without recompiling it.
+//!
+//! Point [`CrosstermWindowSettings::set_config_file`] at a RON file and it's read when the app starts. Everything in
+//! the file is optional, and anything left out keeps the value the app set up:
+//!
+//! ```ron
+//! #![enable(implicit_some)]
+//! (
+//!     fps: 30,
+//!     mouse_capture: false,
+//!     color_choice: Never,
+//!     theme: (
+//!         base: "light",
+//!         colors: { "text.error": "#c80000", "ui.border": "dark_grey" },
+//!         pairs: { 1: (foreground: "white", background: "dark_blue") },
+//!     ),
+//! )
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::components::{Color, Colors};
+use crate::theme::Theme;
+use crate::{ColorChoice, CrosstermWindowSettings};
+
+/// The contents of a config file, see the [module docs](self)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub title: Option<String>,
+    pub colors: Option<Colors>,
+    /// See [`CrosstermWindowSettings::set_frame_rate`]
+    pub fps: Option<u32>,
+    pub min_size: Option<(u16, u16)>,
+    pub mouse_capture: Option<bool>,
+    pub focus_reporting: Option<bool>,
+    pub alternate_screen: Option<bool>,
+    pub color_choice: Option<ColorChoice>,
+    pub theme: Option<ThemeConfig>,
+}
+
+/// Changes to the app's [`Theme`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// One of the built-in themes to start from: "dark", "light" or "high_contrast". Starts from the app's theme
+    /// when `None`
+    pub base: Option<String>,
+    /// Colors to change or add
+    pub colors: HashMap<String, Color>,
+    /// Color pairs to change or add, by number
+    pub pairs: HashMap<u16, Colors>,
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("could not read config file {}: {error}", path.display())]
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("error deserializing config from ron data at {}:{error}", path.display())]
+    Deserialize {
+        path: PathBuf,
+        error: ron::error::SpannedError,
+    },
+    #[error("unknown base theme {0:?}, expected \"dark\", \"light\" or \"high_contrast\"")]
+    UnknownTheme(String),
+}
+
+impl WindowConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<WindowConfig, ConfigError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|error| ConfigError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let config: WindowConfig =
+            ron::from_str(&text).map_err(|error| ConfigError::Deserialize {
+                path: path.to_path_buf(),
+                error,
+            })?;
+        // Catch a misspelled theme now rather than quietly ignoring it later
+        if let Some(base) = config.theme.as_ref().and_then(|theme| theme.base.as_ref()) {
+            builtin_theme(base).ok_or_else(|| ConfigError::UnknownTheme(base.clone()))?;
+        }
+        Ok(config)
+    }
+
+    /// Change whatever settings the config has values for
+    pub fn apply(&self, settings: &mut CrosstermWindowSettings) {
+        if let Some(title) = &self.title {
+            settings.set_title(title);
+        }
+        if let Some(colors) = self.colors {
+            settings.set_colors(colors);
+        }
+        if let Some(fps) = self.fps {
+            settings.set_frame_rate(fps);
+        }
+        if let Some((width, height)) = self.min_size {
+            settings.set_min_size(width, height);
+        }
+        if let Some(capture) = self.mouse_capture {
+            settings.set_mouse_capture(capture);
+        }
+        if let Some(report) = self.focus_reporting {
+            settings.set_focus_reporting(report);
+        }
+        if let Some(alternate) = self.alternate_screen {
+            settings.set_alternate_screen(alternate);
+        }
+        if let Some(choice) = self.color_choice {
+            settings.set_color_choice(choice);
+        }
+    }
+
+    /// Change the theme, if the config has a theme
+    pub fn apply_theme(&self, theme: &mut Theme) {
+        let Some(config) = &self.theme else {
+            return;
+        };
+        if let Some(base) = config.base.as_deref().and_then(builtin_theme) {
+            *theme = base;
+        }
+        for (name, color) in &config.colors {
+            theme.set(name, *color);
+        }
+        for (number, colors) in &config.pairs {
+            theme.set_pair(*number, *colors);
+        }
+    }
+}
+
+fn builtin_theme(name: &str) -> Option<Theme> {
+    match name {
+        "dark" => Some(Theme::dark()),
+        "light" => Some(Theme::light()),
+        "high_contrast" => Some(Theme::high_contrast()),
+        _ => None,
+    }
+}
@@ -0,0 +1,449 @@
+//! Tools for tracking down problems in a running app.
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use std::time::Duration;
+
+use crate::camera::TerminalCamera;
+use crate::capture::ScreenCapture;
+use crate::components::{Corner, EntitiesToRedraw, Position, Sprite, Style, Visible};
+use crate::render::{CellBuffer, TerminalRender};
+use crate::{CrosstermKeyEventWrapper, CrosstermWindow, ForceRedraw};
+
+/// Pauses the app and runs it a single frame at a time, e.g. to see exactly when something gets drawn wrong.
+///
+/// Insert this as a resource. The pause key (F9 by default) pauses and resumes the app, and while it's paused the
+/// step key (F10 by default) runs one more frame. Input that arrives while the app is paused is handed to it on the
+/// next frame. The stepper can also be driven from systems with [`FrameStepper::pause`] and [`FrameStepper::step`].
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct FrameStepper {
+    paused: bool,
+    steps: u32,
+    pause_key: Option<KeyCode>,
+    step_key: KeyCode,
+}
+
+impl Default for FrameStepper {
+    fn default() -> Self {
+        FrameStepper {
+            paused: false,
+            steps: 0,
+            pause_key: Some(KeyCode::F(9)),
+            step_key: KeyCode::F(10),
+        }
+    }
+}
+
+impl FrameStepper {
+    /// Start out paused, so the first frame only runs once the step key is pressed
+    pub fn paused() -> FrameStepper {
+        FrameStepper {
+            paused: true,
+            ..Default::default()
+        }
+    }
+
+    /// The key that pauses and resumes the app, or None to only do that from code
+    pub fn set_pause_key(&mut self, key: Option<KeyCode>) -> &mut Self {
+        self.pause_key = key;
+        self
+    }
+
+    pub fn set_step_key(&mut self, key: KeyCode) -> &mut Self {
+        self.step_key = key;
+        self
+    }
+
+    pub fn pause_key(&self) -> Option<KeyCode> {
+        self.pause_key
+    }
+
+    pub fn step_key(&self) -> KeyCode {
+        self.step_key
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.steps = 0;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Run one more frame while paused
+    pub fn step(&mut self) {
+        if self.paused {
+            self.steps += 1;
+        }
+    }
+
+    /// Handle the pause and step keys. Returns true if the key was one of them, and shouldn't go to the app
+    pub(crate) fn handle_key(&mut self, key_event: &KeyEvent) -> bool {
+        let pressed = key_event.kind != KeyEventKind::Release;
+        if Some(key_event.code) == self.pause_key {
+            if key_event.kind == KeyEventKind::Press {
+                if self.paused {
+                    self.resume();
+                } else {
+                    self.pause();
+                }
+            }
+            true
+        } else if key_event.code == self.step_key && self.paused {
+            if pressed {
+                self.step();
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the next frame has to wait. Uses up a step if there is one
+    pub(crate) fn hold(&mut self) -> bool {
+        if !self.paused {
+            return false;
+        }
+        if self.steps > 0 {
+            self.steps -= 1;
+            return false;
+        }
+        true
+    }
+}
+
+/// Something [`DebugKeys`] can do
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DebugAction {
+    /// Show or hide the [`DebugOverlay`]
+    ToggleOverlay,
+    /// Redraw the whole screen, see [`ForceRedraw`]
+    ForceRedraw,
+    /// Save the screen to an HTML file in the working directory. Needs
+    /// [`ScreenCapturePlugin`](crate::capture::ScreenCapturePlugin)
+    Screenshot,
+    /// Pause or resume the app, see [`FrameStepper`]
+    PauseFrames,
+    /// Pause the app, or run one more frame if it's paused
+    StepFrame,
+}
+
+/// Keys for the built-in debug actions. They're only enabled in debug builds unless turned on with
+/// [`DebugKeys::set_enabled`].
+///
+/// | Key | Action                          |
+/// |-----|---------------------------------|
+/// | F1  | [`DebugAction::ToggleOverlay`]  |
+/// | F5  | [`DebugAction::ForceRedraw`]    |
+/// | F9  | [`DebugAction::PauseFrames`]    |
+/// | F10 | [`DebugAction::StepFrame`]      |
+/// | F12 | [`DebugAction::Screenshot`]     |
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct DebugKeys {
+    enabled: bool,
+    bindings: HashMap<KeyCode, DebugAction>,
+}
+
+impl Default for DebugKeys {
+    fn default() -> Self {
+        let mut keys = DebugKeys {
+            enabled: cfg!(debug_assertions),
+            bindings: HashMap::new(),
+        };
+        keys.bind(KeyCode::F(1), DebugAction::ToggleOverlay)
+            .bind(KeyCode::F(5), DebugAction::ForceRedraw)
+            .bind(KeyCode::F(9), DebugAction::PauseFrames)
+            .bind(KeyCode::F(10), DebugAction::StepFrame)
+            .bind(KeyCode::F(12), DebugAction::Screenshot);
+        keys
+    }
+}
+
+impl DebugKeys {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> &mut Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Make `key` do `action`, replacing whatever it did before
+    pub fn bind(&mut self, key: KeyCode, action: DebugAction) -> &mut Self {
+        self.bindings.insert(key, action);
+        self
+    }
+
+    pub fn unbind(&mut self, key: KeyCode) -> &mut Self {
+        self.bindings.remove(&key);
+        self
+    }
+
+    /// The action `key` does, if the debug keys are enabled
+    pub fn action(&self, key: KeyCode) -> Option<DebugAction> {
+        self.enabled
+            .then(|| self.bindings.get(&key).copied())
+            .flatten()
+    }
+}
+
+/// Let the debug keys and the frame stepper have a key before the app sees it. They have to be handled before the
+/// app updates, since a paused app doesn't update. Returns true if the key was used up
+pub(crate) fn intercept_key(world: &mut World, key_event: &KeyEvent) -> bool {
+    let action = world
+        .get_resource::<DebugKeys>()
+        .and_then(|keys| keys.action(key_event.code));
+    if let Some(action @ (DebugAction::PauseFrames | DebugAction::StepFrame)) = action {
+        if key_event.kind != KeyEventKind::Press {
+            return true;
+        }
+        let mut stepper = world.get_resource_or_insert_with(|| {
+            let mut stepper = FrameStepper::default();
+            stepper.set_pause_key(None);
+            stepper
+        });
+        match action {
+            DebugAction::PauseFrames if stepper.is_paused() => stepper.resume(),
+            DebugAction::StepFrame if stepper.is_paused() => stepper.step(),
+            _ => stepper.pause(),
+        }
+        return true;
+    }
+
+    world
+        .get_resource_mut::<FrameStepper>()
+        .is_some_and(|mut stepper| stepper.handle_key(key_event))
+}
+
+pub(crate) fn debug_keys(
+    mut commands: Commands,
+    keys: Res<DebugKeys>,
+    mut key_events: EventReader<CrosstermKeyEventWrapper>,
+    mut redraw: EventWriter<ForceRedraw>,
+    capture: Option<Res<ScreenCapture>>,
+    overlays: Query<Entity, With<DebugOverlay>>,
+) {
+    for CrosstermKeyEventWrapper(key_event) in key_events.read() {
+        if key_event.kind != KeyEventKind::Press {
+            continue;
+        }
+        match keys.action(key_event.code) {
+            Some(DebugAction::ToggleOverlay) => {
+                if overlays.is_empty() {
+                    commands.spawn((
+                        DebugOverlay::default(),
+                        Position::default(),
+                        Visible::default(),
+                    ));
+                } else {
+                    for overlay in &overlays {
+                        commands.entity(overlay).despawn();
+                    }
+                }
+            }
+            Some(DebugAction::ForceRedraw) => {
+                redraw.send(ForceRedraw);
+            }
+            Some(DebugAction::Screenshot) => save_screenshot(capture.as_deref()),
+            _ => {}
+        }
+    }
+}
+
+fn save_screenshot(capture: Option<&ScreenCapture>) {
+    let Some(capture) = capture else {
+        warn!("Screenshots need the ScreenCapturePlugin");
+        return;
+    };
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let path = format!("screenshot-{}.html", time.as_millis());
+    match std::fs::write(&path, capture.to_html()) {
+        Ok(()) => info!("Saved screenshot to {path}"),
+        Err(err) => error!("Could not save screenshot to {path}: {err}"),
+    }
+}
+
+/// Shows what the renderer is up to in the top left corner of the screen. Toggled with
+/// [`DebugAction::ToggleOverlay`]
+#[derive(Component, Debug, Default, Clone, PartialEq, Eq)]
+pub struct DebugOverlay {
+    lines: Vec<String>,
+}
+
+impl TerminalRender for DebugOverlay {
+    fn size(&self) -> (u16, u16) {
+        let width = self.lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        (width as u16, self.lines.len() as u16)
+    }
+
+    fn render(&self, buffer: &mut CellBuffer) {
+        buffer.set_style(Style::with_attrib(crossterm::style::Attribute::Reverse));
+        for (y, line) in self.lines.iter().enumerate() {
+            buffer.print(0, y as u16, line, buffer.style());
+        }
+    }
+}
+
+pub(crate) fn update_debug_overlay(
+    mut frame: Local<u64>,
+    mut overlays: Query<(&mut DebugOverlay, &mut Position)>,
+    camera: Query<&TerminalCamera>,
+    window: Query<&CrosstermWindow>,
+    redraw: Res<EntitiesToRedraw>,
+    sprites: Query<(), With<Handle<Sprite>>>,
+) {
+    *frame += 1;
+    if overlays.is_empty() {
+        return;
+    }
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+
+    let (width, height) = window.terminal_size();
+    let lines = vec![
+        format!(" frame   {} ", *frame),
+        format!(" size    {width}x{height} "),
+        format!(" sprites {} ", sprites.iter().count()),
+        format!(
+            " drawn   {}{} ",
+            redraw.to_draw.len(),
+            if redraw.full_redraw { " (full)" } else { "" }
+        ),
+        format!(" cleared {} ", redraw.to_clear.len()),
+    ];
+    // Stay in the corner of the screen wherever the camera goes
+    let camera = camera.get_single().copied().unwrap_or_default();
+    let corner = Position::new(camera.x, camera.y, i32::MAX);
+
+    for (mut overlay, mut position) in &mut overlays {
+        if overlay.lines != lines {
+            overlay.lines = lines.clone();
+        }
+        if *position != corner {
+            *position = Position::new(corner.x, corner.y, corner.z);
+        }
+    }
+}
+
+// How often the counter changes, so it can be read
+const FPS_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Shows the frame rate and frame time, averaged over the last half second. Spawn it with [`FpsCounterBundle`]
+#[derive(Component, Debug, Default, Clone, PartialEq)]
+pub struct FpsCounter {
+    corner: Corner,
+    text: String,
+    frames: u32,
+    elapsed: Duration,
+}
+
+impl FpsCounter {
+    pub fn new(corner: Corner) -> FpsCounter {
+        FpsCounter {
+            corner,
+            text: " -- fps ".to_string(),
+            frames: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn corner(&self) -> Corner {
+        self.corner
+    }
+}
+
+impl TerminalRender for FpsCounter {
+    fn size(&self) -> (u16, u16) {
+        (self.text.len() as u16, 1)
+    }
+
+    fn render(&self, buffer: &mut CellBuffer) {
+        buffer.set_style(Style::with_attrib(crossterm::style::Attribute::Reverse));
+        buffer.print(0, 0, &self.text, buffer.style());
+    }
+}
+
+/// A frame rate counter that stays in a corner of the screen, e.g. `commands.spawn(FpsCounterBundle::top_right())`
+#[derive(Bundle, Debug, Default)]
+pub struct FpsCounterBundle {
+    pub counter: FpsCounter,
+    pub position: Position,
+    pub visible: Visible,
+}
+
+impl FpsCounterBundle {
+    pub fn new(corner: Corner) -> FpsCounterBundle {
+        FpsCounterBundle {
+            counter: FpsCounter::new(corner),
+            // Drawn over everything else
+            position: Position::new(0, 0, i32::MAX),
+            visible: Visible::default(),
+        }
+    }
+
+    pub fn top_left() -> FpsCounterBundle {
+        FpsCounterBundle::new(Corner::TopLeft)
+    }
+
+    pub fn top_right() -> FpsCounterBundle {
+        FpsCounterBundle::new(Corner::TopRight)
+    }
+
+    pub fn bottom_left() -> FpsCounterBundle {
+        FpsCounterBundle::new(Corner::BottomLeft)
+    }
+
+    pub fn bottom_right() -> FpsCounterBundle {
+        FpsCounterBundle::new(Corner::BottomRight)
+    }
+}
+
+pub(crate) fn update_fps_counters(
+    time: Res<Time<Real>>,
+    mut counters: Query<(&mut FpsCounter, &mut Position)>,
+    camera: Query<&TerminalCamera>,
+    window: Query<&CrosstermWindow>,
+) {
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+    let view = window.viewport();
+    let camera = camera.get_single().copied().unwrap_or_default();
+
+    for (mut counter, mut position) in &mut counters {
+        // Only touch the counter when the text changes, since that redraws it
+        let counter_ref = counter.bypass_change_detection();
+        counter_ref.frames += 1;
+        counter_ref.elapsed += time.delta();
+        if counter_ref.elapsed >= FPS_UPDATE_INTERVAL {
+            let frame_time = counter_ref.elapsed / counter_ref.frames;
+            let fps = counter_ref.frames as f32 / counter_ref.elapsed.as_secs_f32();
+            counter_ref.frames = 0;
+            counter_ref.elapsed = Duration::ZERO;
+            let text = format!(" {fps:.0} fps {:.1} ms ", frame_time.as_secs_f32() * 1000.0);
+            if counter.text != text {
+                counter.text = text;
+            }
+        }
+
+        // Stay in the corner as the terminal is resized or the camera moves
+        let (x, y) = counter
+            .corner
+            .place((view.width, view.height), counter.size());
+        let (x, y) = camera.viewport_to_world(x, y);
+        if position.x != x || position.y != y {
+            position.x = x;
+            position.y = y;
+        }
+    }
+}
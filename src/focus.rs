@@ -0,0 +1,215 @@
+//! Keyboard focus for widgets.
+//!
+//! Give widgets that take keyboard input a [`Focusable`] component. At most one of them has focus at a time, and only
+//! that one should act on key presses, see [`FocusedEntity`]. Tab and Shift-Tab move the focus through the
+//! focusable entities, and [`FocusGained`] and [`FocusLost`] are sent whenever it moves. The arrow keys can move it
+//! too, to the nearest entity in that direction, see [`FocusNavigation`].
+
+use bevy::prelude::*;
+use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
+
+use crate::components::{Position, Visible};
+use crate::CrosstermKeyEventWrapper;
+
+/// An entity that can have keyboard focus. Tab moves through them by `order`, then from top to bottom and left to
+/// right. Invisible entities are skipped
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Focusable {
+    pub order: i32,
+}
+
+impl Focusable {
+    pub fn new(order: i32) -> Focusable {
+        Focusable { order }
+    }
+}
+
+/// The entity that has keyboard focus, if any. Setting it moves the focus
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FocusedEntity(pub Option<Entity>);
+
+impl FocusedEntity {
+    pub fn get(&self) -> Option<Entity> {
+        self.0
+    }
+
+    /// Returns true if `entity` has focus
+    pub fn is_focused(&self, entity: Entity) -> bool {
+        self.0 == Some(entity)
+    }
+}
+
+/// Which keys move the focus
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusNavigation {
+    tab: bool,
+    arrows: bool,
+}
+
+impl Default for FocusNavigation {
+    fn default() -> Self {
+        FocusNavigation {
+            tab: true,
+            arrows: false,
+        }
+    }
+}
+
+impl FocusNavigation {
+    pub fn tab(&self) -> bool {
+        self.tab
+    }
+
+    /// Tab and Shift-Tab move through the focusable entities in order. On by default
+    pub fn set_tab(&mut self, tab: bool) -> &mut Self {
+        self.tab = tab;
+        self
+    }
+
+    pub fn arrows(&self) -> bool {
+        self.arrows
+    }
+
+    /// The arrow keys move to the nearest focusable entity in their direction, going by [`Position`]. This suits
+    /// menus laid out in a grid. Off by default, since widgets often use the arrow keys themselves
+    pub fn set_arrows(&mut self, arrows: bool) -> &mut Self {
+        self.arrows = arrows;
+        self
+    }
+}
+
+/// Sent when an entity gets keyboard focus
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusGained(pub Entity);
+
+/// Sent when an entity loses keyboard focus, including when it's despawned or stops being [`Focusable`]
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusLost(pub Entity);
+
+/// The focusable entities in tab order
+pub(crate) fn tab_order(
+    focusables: &Query<(Entity, &Focusable, Option<&Position>, Option<&Visible>)>,
+) -> Vec<Entity> {
+    let mut entities: Vec<_> = focusables
+        .iter()
+        .filter(|(_, _, _, visible)| !visible.is_some_and(|visible| !visible.is_visible))
+        .map(|(entity, focusable, position, _)| {
+            let (x, y) = position.map_or((0, 0), |position| (position.x, position.y));
+            ((focusable.order, y, x, entity), entity)
+        })
+        .collect();
+    entities.sort_by_key(|(key, _)| *key);
+    entities.into_iter().map(|(_, entity)| entity).collect()
+}
+
+/// Moves the focus with Tab and Shift-Tab
+pub(crate) fn cycle_focus(
+    mut key_events: EventReader<CrosstermKeyEventWrapper>,
+    navigation: Res<FocusNavigation>,
+    mut focused: ResMut<FocusedEntity>,
+    focusables: Query<(Entity, &Focusable, Option<&Position>, Option<&Visible>)>,
+) {
+    for CrosstermKeyEventWrapper(key_event) in key_events.read() {
+        if key_event.kind == KeyEventKind::Release || !navigation.tab {
+            continue;
+        }
+        // Terminals report Shift-Tab either as BackTab or as Tab with shift held
+        let backwards = match key_event.code {
+            KeyCode::BackTab => true,
+            KeyCode::Tab => key_event.modifiers.contains(KeyModifiers::SHIFT),
+            _ => continue,
+        };
+
+        let order = tab_order(&focusables);
+        if order.is_empty() {
+            continue;
+        }
+        let current = focused
+            .0
+            .and_then(|entity| order.iter().position(|e| *e == entity));
+        let next = match (current, backwards) {
+            (None, false) => 0,
+            (None, true) => order.len() - 1,
+            (Some(i), false) => (i + 1) % order.len(),
+            (Some(i), true) => (i + order.len() - 1) % order.len(),
+        };
+        focused.0 = Some(order[next]);
+    }
+}
+
+/// Moves the focus with the arrow keys, if [`FocusNavigation::arrows`] is on
+pub(crate) fn move_focus_with_arrows(
+    mut key_events: EventReader<CrosstermKeyEventWrapper>,
+    navigation: Res<FocusNavigation>,
+    mut focused: ResMut<FocusedEntity>,
+    focusables: Query<(Entity, &Focusable, Option<&Position>, Option<&Visible>)>,
+) {
+    for CrosstermKeyEventWrapper(key_event) in key_events.read() {
+        if key_event.kind == KeyEventKind::Release || !navigation.arrows {
+            continue;
+        }
+        let (dx, dy) = match key_event.code {
+            KeyCode::Left => (-1, 0),
+            KeyCode::Right => (1, 0),
+            KeyCode::Up => (0, -1),
+            KeyCode::Down => (0, 1),
+            _ => continue,
+        };
+
+        let position_of = |entity| {
+            focusables
+                .get(entity)
+                .ok()
+                .and_then(|(_, _, position, _)| position)
+                .map_or((0, 0), |position| (position.x, position.y))
+        };
+        let order = tab_order(&focusables);
+        let Some(current) = focused.0.filter(|entity| order.contains(entity)) else {
+            // Nothing to move from, so start at the beginning
+            focused.0 = order.first().copied();
+            continue;
+        };
+
+        // The distance along the direction pressed counts for less than the distance across it, so the focus
+        // prefers to stay in the same row or column
+        let (x, y) = position_of(current);
+        let nearest = order
+            .iter()
+            .filter(|entity| **entity != current)
+            .filter_map(|entity| {
+                let (ex, ey) = position_of(*entity);
+                let along = (ex - x) * dx + (ey - y) * dy;
+                let across = ((ex - x) * dy + (ey - y) * dx).abs();
+                (along > 0).then_some((along + across * 2, *entity))
+            })
+            .min_by_key(|(distance, _)| *distance);
+        if let Some((_, entity)) = nearest {
+            focused.0 = Some(entity);
+        }
+    }
+}
+
+/// Sends [`FocusGained`] and [`FocusLost`] when the focus moves, and drops the focus when the focused entity can't
+/// have it anymore
+pub(crate) fn send_focus_events(
+    mut previous: Local<Option<Entity>>,
+    mut focused: ResMut<FocusedEntity>,
+    focusables: Query<(), With<Focusable>>,
+    mut gained: EventWriter<FocusGained>,
+    mut lost: EventWriter<FocusLost>,
+) {
+    if focused.0.is_some_and(|entity| !focusables.contains(entity)) {
+        focused.0 = None;
+    }
+    if *previous == focused.0 {
+        return;
+    }
+
+    if let Some(entity) = *previous {
+        lost.send(FocusLost(entity));
+    }
+    if let Some(entity) = focused.0 {
+        gained.send(FocusGained(entity));
+    }
+    *previous = focused.0;
+}
@@ -0,0 +1,38 @@
+//! Reading gamepads with bevy_gilrs under the crossterm runner.
+//!
+//! Gamepads don't go through the terminal, so they work the same as in any other bevy app: add bevy's `GilrsPlugin`
+//! (it's part of [`CrosstermDefaultPlugins`] and bevy's `DefaultPlugins` with the "gamepad" feature on) and read
+//! `ButtonInput<GamepadButton>`, `Axis<GamepadAxis>` or `GamepadEvent`s, next to the terminal's key events. gilrs is
+//! polled from `PreUpdate` rather than by winit's event loop, and its state can only be used from the thread it was
+//! made on, which the runner calls `App::update` from every frame.
+//!
+//! The runner only hears about the terminal's input though, and slows down while the app is idle or the terminal is
+//! in the background, see [`CrosstermWindowSettings::set_idle_pacing`]. That would leave the gamepad lagging, and a
+//! couch game's terminal is often in the background, so while a gamepad is in use the app counts as busy and runs at
+//! its normal frame rate.
+//!
+//! [`CrosstermDefaultPlugins`]: crate::CrosstermDefaultPlugins
+//! [`CrosstermWindowSettings::set_idle_pacing`]: crate::CrosstermWindowSettings::set_idle_pacing
+
+use bevy::input::gamepad::{GamepadButton, GamepadEvent};
+use bevy::prelude::*;
+
+/// Whether any gamepad sent an event or had a button held during the last frame
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct GamepadActivity {
+    pub(crate) busy: bool,
+}
+
+/// Checks whether any gamepads are in use, for the runner to keep up with them
+pub(crate) fn track_gamepads(
+    mut activity: ResMut<GamepadActivity>,
+    mut events: EventReader<GamepadEvent>,
+    buttons: Option<Res<ButtonInput<GamepadButton>>>,
+) {
+    // A stick held in one place stops sending events, but whatever it's moving is drawn every frame
+    let busy = events.read().count() > 0
+        || buttons.is_some_and(|buttons| buttons.get_pressed().next().is_some());
+    if activity.busy != busy {
+        activity.busy = busy;
+    }
+}
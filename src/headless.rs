@@ -0,0 +1,83 @@
+//! Running an app without a terminal, for end to end tests.
+//!
+//! Insert a [`Headless`] resource before running the app. The app then draws to a [`TestBackend`], time advances by
+//! the same amount every frame no matter how long the frame really took, input comes from a script instead of the
+//! keyboard, and the app exits after a set number of frames. Two runs of the same app with the same script produce
+//! the same output.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use crossterm::event::Event;
+
+use crate::backend::TestBackend;
+
+/// Runs the app headless and reproducibly, see the [module docs](self)
+#[derive(Resource, Debug, Clone)]
+pub struct Headless {
+    backend: TestBackend,
+    frame_time: Duration,
+    frames: Option<u64>,
+    script: Vec<(u64, Event)>,
+    frame: u64,
+}
+
+impl Headless {
+    /// Run on `backend`, at 60 frames per second until the app exits by itself
+    pub fn new(backend: TestBackend) -> Headless {
+        Headless {
+            backend,
+            frame_time: Duration::from_secs(1) / 60,
+            frames: None,
+            script: Vec::new(),
+            frame: 0,
+        }
+    }
+
+    /// How far time moves on each frame
+    #[must_use]
+    pub fn with_frame_time(mut self, frame_time: Duration) -> Self {
+        self.frame_time = frame_time;
+        self
+    }
+
+    /// Exit after this many frames
+    #[must_use]
+    pub fn with_frames(mut self, frames: u64) -> Self {
+        self.frames = Some(frames);
+        self
+    }
+
+    /// Have the app read `event` at the start of `frame`, counting from 0
+    #[must_use]
+    pub fn with_input(mut self, frame: u64, event: Event) -> Self {
+        self.script.push((frame, event));
+        self
+    }
+
+    pub fn backend(&self) -> &TestBackend {
+        &self.backend
+    }
+
+    pub fn frame_time(&self) -> Duration {
+        self.frame_time
+    }
+
+    /// The frame the app is on
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Hand this frame's scripted input to the app, and move on to the next frame
+    pub(crate) fn start_frame(&mut self) {
+        for (_, event) in self.script.iter().filter(|(frame, _)| *frame == self.frame) {
+            self.backend.push_event(event.clone());
+        }
+        self.frame += 1;
+    }
+
+    /// Whether all the frames have been run
+    pub(crate) fn finished(&self) -> bool {
+        self.frames.is_some_and(|frames| self.frame >= frames)
+    }
+}
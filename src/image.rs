@@ -0,0 +1,242 @@
+//! Showing bevy [`Image`]s in the terminal, like procedural textures generated elsewhere in the app.
+//!
+//! Give an entity an [`ImageSprite`] and the image is turned into a [`ColoredSprite`] at the size asked for, and
+//! again whenever the image changes. The app needs images as an asset type, which `ImagePlugin` or
+//! `app.init_asset::<Image>()` takes care of.
+
+use bevy::ecs::event::ManualEventReader;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+use crate::capabilities::TerminalCapabilities;
+use crate::color::{ColorDepth, Dither};
+use crate::components::{Color, ColoredSprite, Colors, Style};
+use crate::render::Cell;
+
+// Characters from least to most ink, for drawing images as text
+const ASCII_RAMP: &[&str] = &[" ", ".", ":", "-", "=", "+", "*", "#", "%", "@"];
+
+// Pixels less opaque than this are left out
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// How an image is turned into cells
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageMode {
+    /// Two pixels to a cell, one above the other, drawn with half block characters. Cells are about twice as tall as
+    /// they are wide, so this keeps the image's proportions
+    #[default]
+    HalfBlocks,
+    /// One pixel to a cell, drawn as a character with more ink the brighter the pixel is, in the pixel's color
+    Ascii,
+}
+
+/// Draws an [`Image`] on the entity, scaled to `width` by `height` cells. The entity is given the
+/// `Handle<ColoredSprite>` it's drawn with, and needs a [`Position`](crate::components::Position) and
+/// [`Visible`](crate::components::Visible) like any sprite
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct ImageSprite {
+    pub image: Handle<Image>,
+    pub width: u16,
+    pub height: u16,
+    pub mode: ImageMode,
+    /// How to make up for colors the terminal can't show, see [`TerminalCapabilities::color_depth`]
+    pub dither: Dither,
+}
+
+impl ImageSprite {
+    pub fn new(image: Handle<Image>, width: u16, height: u16) -> ImageSprite {
+        ImageSprite {
+            image,
+            width,
+            height,
+            mode: ImageMode::default(),
+            dither: Dither::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_mode(mut self, mode: ImageMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    #[must_use]
+    pub fn with_dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+}
+
+/// Turn `image` into a colored sprite `width` by `height` cells, with colors `depth` can show. Returns `None` if the
+/// image's format can't be read
+pub fn image_to_sprite(
+    image: &Image,
+    width: u16,
+    height: u16,
+    mode: ImageMode,
+    dither: Dither,
+    depth: ColorDepth,
+) -> Option<ColoredSprite> {
+    let rgba = image.clone().try_into_dynamic().ok()?.to_rgba8();
+    let (columns, rows) = match mode {
+        ImageMode::HalfBlocks => (width as usize, height as usize * 2),
+        ImageMode::Ascii => (width as usize, height as usize),
+    };
+    let pixels = resample(
+        rgba.as_raw(),
+        rgba.width() as usize,
+        rgba.height() as usize,
+        columns,
+        rows,
+    );
+    let rgb: Vec<(u8, u8, u8)> = pixels.iter().map(|(r, g, b, _)| (*r, *g, *b)).collect();
+    let colors = dither.apply(&rgb, columns, depth);
+    // See-through pixels are left to the terminal's default colors
+    let color = |i: usize| (pixels[i].3 >= ALPHA_THRESHOLD).then_some(colors[i]);
+
+    let cell = |grapheme: &str, foreground, background| Cell {
+        grapheme: grapheme.to_string(),
+        style: Style {
+            colors: Colors {
+                foreground,
+                background,
+            },
+            ..Default::default()
+        },
+    };
+    let sprite_rows = (0..height as usize)
+        .map(|y| {
+            (0..columns)
+                .map(|x| match mode {
+                    ImageMode::HalfBlocks => {
+                        let top = color(2 * y * columns + x);
+                        let bottom = color((2 * y + 1) * columns + x);
+                        match (top, bottom) {
+                            (Some(_), _) => cell("▀", top, bottom.or(Some(Color::Reset))),
+                            (None, Some(_)) => cell("▄", bottom, Some(Color::Reset)),
+                            (None, None) => cell(" ", None, Some(Color::Reset)),
+                        }
+                    }
+                    ImageMode::Ascii => {
+                        let i = y * columns + x;
+                        let (r, g, b) = rgb[i];
+                        let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+                        let ink = (luma / 256.0 * ASCII_RAMP.len() as f32) as usize;
+                        match color(i) {
+                            Some(_) => cell(ASCII_RAMP[ink], color(i), None),
+                            None => cell(" ", None, None),
+                        }
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    Some(ColoredSprite::new(Style::default(), sprite_rows))
+}
+
+// Scale RGBA pixels to `columns` by `rows`, averaging the pixels that end up in each one
+fn resample(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    columns: usize,
+    rows: usize,
+) -> Vec<(u8, u8, u8, u8)> {
+    let mut pixels = Vec::with_capacity(columns * rows);
+    for row in 0..rows {
+        let (top, bottom) = span(row, rows, height);
+        for column in 0..columns {
+            let (left, right) = span(column, columns, width);
+            let mut sum = [0u32; 4];
+            for y in top..bottom {
+                for x in left..right {
+                    let i = (y * width + x) * 4;
+                    for (channel, value) in sum.iter_mut().zip(&data[i..i + 4]) {
+                        *channel += *value as u32;
+                    }
+                }
+            }
+            let count = ((bottom - top) * (right - left)).max(1) as u32;
+            pixels.push((
+                (sum[0] / count) as u8,
+                (sum[1] / count) as u8,
+                (sum[2] / count) as u8,
+                (sum[3] / count) as u8,
+            ));
+        }
+    }
+    pixels
+}
+
+// The source pixels that output pixel `i` of `count` covers, always at least one if there are any
+fn span(i: usize, count: usize, size: usize) -> (usize, usize) {
+    if size == 0 {
+        return (0, 0);
+    }
+    let start = (i * size / count).min(size - 1);
+    let end = ((i + 1) * size / count).clamp(start + 1, size);
+    (start, end)
+}
+
+/// Turns image sprites into colored sprites, whenever they or their images change
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_image_sprites(
+    mut commands: Commands,
+    mut image_events: Local<ManualEventReader<AssetEvent<Image>>>,
+    // Images might not be registered as an asset type
+    events: Option<Res<Events<AssetEvent<Image>>>>,
+    images: Option<Res<Assets<Image>>>,
+    capabilities: Option<Res<TerminalCapabilities>>,
+    mut colored_sprites: ResMut<Assets<ColoredSprite>>,
+    entities: Query<(Entity, Ref<ImageSprite>, Option<&Handle<ColoredSprite>>)>,
+) {
+    let (Some(images), Some(events)) = (images, events) else {
+        return;
+    };
+    let changed_images: HashSet<AssetId<Image>> = image_events
+        .read(&events)
+        .filter_map(|event| match event {
+            AssetEvent::Added { id }
+            | AssetEvent::Modified { id }
+            | AssetEvent::LoadedWithDependencies { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+    let depth = capabilities.map_or(ColorDepth::TrueColor, |capabilities| {
+        capabilities.color_depth()
+    });
+
+    for (entity, image_sprite, handle) in &entities {
+        if !image_sprite.is_changed() && !changed_images.contains(&image_sprite.image.id()) {
+            continue;
+        }
+        let Some(image) = images.get(&image_sprite.image) else {
+            continue;
+        };
+        let Some(sprite) = image_to_sprite(
+            image,
+            image_sprite.width,
+            image_sprite.height,
+            image_sprite.mode,
+            image_sprite.dither,
+            depth,
+        ) else {
+            warn!(
+                "Can't draw an image in the {:?} format",
+                image.texture_descriptor.format
+            );
+            continue;
+        };
+
+        match handle {
+            Some(handle) => {
+                if colored_sprites.get(handle) != Some(&sprite) {
+                    colored_sprites.insert(handle, sprite);
+                }
+            }
+            None => {
+                commands.entity(entity).insert(colored_sprites.add(sprite));
+            }
+        }
+    }
+}
@@ -0,0 +1,621 @@
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy::window::{WindowFocused, WindowResized};
+use crossterm::event::{
+    KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind,
+};
+
+use crate::capabilities::TerminalCapabilities;
+use crate::{CrosstermKeyEventWrapper, CrosstermMouseEventWrapper};
+
+/// A single key press, with the modifiers that have to be held for it, e.g. `Ctrl-x`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        KeyChord { code, modifiers }.normalized()
+    }
+
+    /// A key pressed without any modifiers
+    pub fn key(code: KeyCode) -> KeyChord {
+        KeyChord::new(code, KeyModifiers::NONE)
+    }
+
+    /// A character key pressed without any modifiers
+    pub fn char(c: char) -> KeyChord {
+        KeyChord::key(KeyCode::Char(c))
+    }
+
+    /// A character key pressed while holding control
+    pub fn ctrl(c: char) -> KeyChord {
+        KeyChord::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    /// A character key pressed while holding alt
+    pub fn alt(c: char) -> KeyChord {
+        KeyChord::new(KeyCode::Char(c), KeyModifiers::ALT)
+    }
+
+    /// The chord a key event represents
+    pub fn from_event(event: &KeyEvent) -> KeyChord {
+        KeyChord::new(event.code, event.modifiers)
+    }
+
+    // Characters already tell us whether shift was held ('G' vs 'g'), and terminals disagree on whether they also
+    // report the shift modifier, so ignore it for them
+    fn normalized(mut self) -> KeyChord {
+        if let KeyCode::Char(_) = self.code {
+            self.modifiers.remove(KeyModifiers::SHIFT);
+        }
+        self
+    }
+}
+
+/// The modifier keys currently held down.
+///
+/// This is updated by the runner before any systems run, at the same time it sends the matching bevy
+/// `KeyboardInput` events, so it agrees with `ButtonInput<KeyCode>`.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyModifiersState(KeyModifiers);
+
+impl Default for KeyModifiersState {
+    fn default() -> Self {
+        KeyModifiersState(KeyModifiers::NONE)
+    }
+}
+
+impl KeyModifiersState {
+    pub fn modifiers(&self) -> KeyModifiers {
+        self.0
+    }
+
+    /// Returns true if all of `modifiers` are held
+    pub fn contains(&self, modifiers: KeyModifiers) -> bool {
+        self.0.contains(modifiers)
+    }
+
+    pub fn shift(&self) -> bool {
+        self.contains(KeyModifiers::SHIFT)
+    }
+
+    pub fn control(&self) -> bool {
+        self.contains(KeyModifiers::CONTROL)
+    }
+
+    pub fn alt(&self) -> bool {
+        self.contains(KeyModifiers::ALT)
+    }
+
+    pub fn super_key(&self) -> bool {
+        self.contains(KeyModifiers::SUPER)
+    }
+
+    pub(crate) fn set(&mut self, modifiers: KeyModifiers) {
+        self.0 = modifiers;
+    }
+}
+
+/// Sent when the keys of a sequence bound in [`KeySequences`] were pressed in order
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct KeySequenceEvent {
+    pub name: String,
+}
+
+/// Multi key sequences, like `g g` or `Ctrl-x Ctrl-s`, and the name of the [`KeySequenceEvent`] sent when each of
+/// them is typed.
+///
+/// The keys of a sequence have to be pressed within `timeout` of each other. If a sequence is also the start of a
+/// longer one, its event is only sent once the timeout passes without the longer sequence being continued.
+#[derive(Resource)]
+pub struct KeySequences {
+    sequences: Vec<(Vec<KeyChord>, String)>,
+    timeout: Duration,
+    pending: Vec<KeyChord>,
+    last_press: Duration,
+}
+
+impl Default for KeySequences {
+    fn default() -> Self {
+        KeySequences {
+            sequences: Vec::new(),
+            timeout: Duration::from_millis(1000),
+            pending: Vec::new(),
+            last_press: Duration::ZERO,
+        }
+    }
+}
+
+impl KeySequences {
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send a [`KeySequenceEvent`] named `name` whenever `sequence` is typed
+    pub fn bind<T: ToString>(
+        &mut self,
+        name: T,
+        sequence: impl IntoIterator<Item = KeyChord>,
+    ) -> &mut Self {
+        let sequence: Vec<KeyChord> = sequence.into_iter().collect();
+        if !sequence.is_empty() {
+            self.sequences.push((sequence, name.to_string()));
+        }
+        self
+    }
+
+    /// Remove every sequence bound to `name`
+    pub fn unbind(&mut self, name: &str) -> &mut Self {
+        self.sequences.retain(|(_, bound)| bound != name);
+        self.pending.clear();
+        self
+    }
+
+    /// The keys typed so far of a sequence that hasn't finished yet
+    pub fn pending(&self) -> &[KeyChord] {
+        &self.pending
+    }
+
+    fn exact_match(&self, keys: &[KeyChord]) -> Option<&str> {
+        self.sequences
+            .iter()
+            .find(|(sequence, _)| sequence.as_slice() == keys)
+            .map(|(_, name)| name.as_str())
+    }
+
+    fn is_prefix(&self, keys: &[KeyChord]) -> bool {
+        self.sequences
+            .iter()
+            .any(|(sequence, _)| sequence.len() > keys.len() && sequence.starts_with(keys))
+    }
+
+    /// Feed a key press in, returning the names of the sequences it completed
+    fn press(&mut self, chord: KeyChord) -> Vec<String> {
+        let mut completed = Vec::new();
+
+        // A sequence that was waiting on a longer one is finished if this key doesn't continue the longer one
+        if !self.pending.is_empty() {
+            let mut continued = self.pending.clone();
+            continued.push(chord);
+            if !self.is_prefix(&continued) && self.exact_match(&continued).is_none() {
+                completed.extend(self.expire());
+            }
+        }
+
+        self.pending.push(chord);
+
+        // Drop keys from the front until what's left could still turn into a sequence. This way a wrong key in the
+        // middle of a sequence can still start a new one.
+        while !self.pending.is_empty() {
+            if self.is_prefix(&self.pending) {
+                break;
+            }
+            if let Some(name) = self.exact_match(&self.pending) {
+                completed.push(name.to_string());
+                self.pending.clear();
+                break;
+            }
+            self.pending.remove(0);
+        }
+
+        completed
+    }
+
+    /// Called when no key was pressed in time. Completes a sequence that was waiting on a longer one
+    fn expire(&mut self) -> Option<String> {
+        let name = self.exact_match(&self.pending).map(str::to_string);
+        self.pending.clear();
+        name
+    }
+}
+
+/// Turns key presses into [`KeySequenceEvent`]s
+pub(crate) fn key_sequences(
+    time: Res<Time>,
+    mut sequences: ResMut<KeySequences>,
+    mut keys: EventReader<CrosstermKeyEventWrapper>,
+    mut events: EventWriter<KeySequenceEvent>,
+) {
+    if sequences.sequences.is_empty() {
+        keys.clear();
+        return;
+    }
+
+    let now = time.elapsed();
+    for key in keys.read() {
+        // Modifier keys on their own are reported as key presses by some terminals, but they're never part of a chord
+        if !key.is_press() || matches!(key.key(), KeyCode::Modifier(_)) {
+            continue;
+        }
+
+        if !sequences.pending.is_empty() && now - sequences.last_press > sequences.timeout {
+            if let Some(name) = sequences.expire() {
+                events.send(KeySequenceEvent { name });
+            }
+        }
+        sequences.last_press = now;
+
+        for name in sequences.press(KeyChord::from_event(key)) {
+            events.send(KeySequenceEvent { name });
+        }
+    }
+
+    if !sequences.pending.is_empty() && now - sequences.last_press > sequences.timeout {
+        if let Some(name) = sequences.expire() {
+            events.send(KeySequenceEvent { name });
+        }
+    }
+}
+
+/// A key chord or mouse button an action can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputBinding {
+    Key(KeyChord),
+    Mouse(MouseButton, KeyModifiers),
+}
+
+impl From<KeyChord> for InputBinding {
+    fn from(chord: KeyChord) -> Self {
+        InputBinding::Key(chord)
+    }
+}
+
+impl From<KeyCode> for InputBinding {
+    fn from(code: KeyCode) -> Self {
+        InputBinding::Key(KeyChord::key(code))
+    }
+}
+
+impl From<MouseButton> for InputBinding {
+    fn from(button: MouseButton) -> Self {
+        InputBinding::Mouse(button, KeyModifiers::NONE)
+    }
+}
+
+/// Binds keys and mouse buttons to the actions of a game, usually an enum. [`ActionState`] tracks which actions are
+/// held based on these bindings. An action can have any number of bindings.
+///
+/// Most terminals only report key releases with [keyboard
+/// enhancement](crate::capabilities::TerminalCapabilities::keyboard_enhancement). Without it, an action pressed by a
+/// key is released once no press of it has been seen for `hold_timeout`, and the terminal's key repeat keeps it held.
+/// The default of zero releases it on the next frame.
+///
+/// Added by [`InputMapPlugin`].
+#[derive(Resource)]
+pub struct InputMap<A> {
+    bindings: Vec<(InputBinding, A)>,
+    hold_timeout: Duration,
+}
+
+impl<A> Default for InputMap<A> {
+    fn default() -> Self {
+        InputMap {
+            bindings: Vec::new(),
+            hold_timeout: Duration::ZERO,
+        }
+    }
+}
+
+impl<A: Copy + Eq + Hash> InputMap<A> {
+    pub fn hold_timeout(&self) -> Duration {
+        self.hold_timeout
+    }
+
+    pub fn set_hold_timeout(&mut self, hold_timeout: Duration) -> &mut Self {
+        self.hold_timeout = hold_timeout;
+        self
+    }
+
+    pub fn bind<T: Into<InputBinding>>(&mut self, action: A, binding: T) -> &mut Self {
+        self.bindings.push((binding.into(), action));
+        self
+    }
+
+    /// Remove every binding for `action`
+    pub fn unbind(&mut self, action: A) -> &mut Self {
+        self.bindings.retain(|(_, bound)| *bound != action);
+        self
+    }
+
+    /// All the bindings for `action`
+    pub fn bindings(&self, action: A) -> impl Iterator<Item = &InputBinding> {
+        self.bindings
+            .iter()
+            .filter(move |(_, bound)| *bound == action)
+            .map(|(binding, _)| binding)
+    }
+}
+
+/// Which actions from the [`InputMap`] are held down, and which were pressed or released this frame.
+///
+/// Everything is released when the terminal loses focus, since the releases wouldn't be seen.
+#[derive(Resource)]
+pub struct ActionState<A> {
+    pressed: HashSet<A>,
+    just_pressed: HashSet<A>,
+    just_released: HashSet<A>,
+    // When each action held by a key was last pressed, for terminals that don't report key releases
+    key_presses: HashMap<A, Duration>,
+}
+
+impl<A> Default for ActionState<A> {
+    fn default() -> Self {
+        ActionState {
+            pressed: HashSet::default(),
+            just_pressed: HashSet::default(),
+            just_released: HashSet::default(),
+            key_presses: HashMap::default(),
+        }
+    }
+}
+
+impl<A: Copy + Eq + Hash> ActionState<A> {
+    pub fn pressed(&self, action: A) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: A) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: A) -> bool {
+        self.just_released.contains(&action)
+    }
+
+    pub fn get_pressed(&self) -> impl Iterator<Item = &A> {
+        self.pressed.iter()
+    }
+
+    fn press(&mut self, action: A) {
+        if self.pressed.insert(action) {
+            self.just_pressed.insert(action);
+        }
+    }
+
+    fn release(&mut self, action: A) {
+        self.key_presses.remove(&action);
+        if self.pressed.remove(&action) {
+            self.just_released.insert(action);
+        }
+    }
+
+    fn release_all(&mut self) {
+        let pressed: Vec<A> = self.pressed.iter().copied().collect();
+        for action in pressed {
+            self.release(action);
+        }
+    }
+
+    /// Release the actions held by a key that haven't been pressed again within `timeout`, but not ones pressed this
+    /// frame
+    fn release_expired(&mut self, now: Duration, timeout: Duration) {
+        let expired: Vec<A> = self
+            .key_presses
+            .iter()
+            .filter(|(action, pressed_at)| {
+                !self.just_pressed.contains(*action) && now.saturating_sub(**pressed_at) >= timeout
+            })
+            .map(|(action, _)| *action)
+            .collect();
+        for action in expired {
+            self.release(action);
+        }
+    }
+}
+
+/// Adds an [`InputMap`] and [`ActionState`] for the action type `A`
+pub struct InputMapPlugin<A>(PhantomData<A>);
+
+impl<A> Default for InputMapPlugin<A> {
+    fn default() -> Self {
+        InputMapPlugin(PhantomData)
+    }
+}
+
+impl<A: Copy + Eq + Hash + Send + Sync + 'static> Plugin for InputMapPlugin<A> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputMap<A>>()
+            .init_resource::<ActionState<A>>()
+            .add_systems(PreUpdate, update_action_state::<A>);
+    }
+}
+
+// Modifiers may be let go of before the key itself, and shift changes the character, so releases only compare keys
+fn same_key(a: &KeyCode, b: &KeyCode) -> bool {
+    match (a, b) {
+        (KeyCode::Char(a), KeyCode::Char(b)) => a.to_lowercase().eq(b.to_lowercase()),
+        (a, b) => a == b,
+    }
+}
+
+fn update_action_state<A: Copy + Eq + Hash + Send + Sync + 'static>(
+    map: Res<InputMap<A>>,
+    mut state: ResMut<ActionState<A>>,
+    time: Res<Time>,
+    capabilities: Option<Res<TerminalCapabilities>>,
+    mut keys: EventReader<CrosstermKeyEventWrapper>,
+    mut mouse: EventReader<CrosstermMouseEventWrapper>,
+    mut focus: EventReader<WindowFocused>,
+) {
+    state.just_pressed.clear();
+    state.just_released.clear();
+
+    let releases_reported =
+        capabilities.is_some_and(|capabilities| capabilities.keyboard_enhancement());
+    let now = time.elapsed();
+    for key in keys.read() {
+        let chord = KeyChord::from_event(key);
+        for (binding, action) in &map.bindings {
+            let InputBinding::Key(bound) = binding else {
+                continue;
+            };
+            match key.0.kind {
+                KeyEventKind::Press if *bound == chord => {
+                    state.press(*action);
+                    if !releases_reported {
+                        state.key_presses.insert(*action, now);
+                    }
+                }
+                KeyEventKind::Release if same_key(&bound.code, &chord.code) => {
+                    state.release(*action)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for event in mouse.read() {
+        for (binding, action) in &map.bindings {
+            let InputBinding::Mouse(button, modifiers) = binding else {
+                continue;
+            };
+            match event.0.kind {
+                MouseEventKind::Down(pressed)
+                    if pressed == *button && event.0.modifiers == *modifiers =>
+                {
+                    state.press(*action);
+                    state.key_presses.remove(action);
+                }
+                MouseEventKind::Up(released) if released == *button => state.release(*action),
+                _ => {}
+            }
+        }
+    }
+
+    if !releases_reported {
+        state.release_expired(now, map.hold_timeout);
+    }
+
+    if focus.read().any(|event| !event.focused) {
+        state.release_all();
+    }
+}
+
+/// A run condition that's true when `code` was pressed this frame, e.g.
+/// `app.add_systems(Update, submit.run_if(on_key_press(KeyCode::Enter)))`
+pub fn on_key_press(
+    code: KeyCode,
+) -> impl FnMut(EventReader<CrosstermKeyEventWrapper>) -> bool + Clone {
+    // Every event is read, even after a match, so none are left over to be seen again next frame
+    move |mut keys: EventReader<CrosstermKeyEventWrapper>| {
+        keys.read()
+            .filter(|key| key.is_press() && key.key() == code)
+            .count()
+            > 0
+    }
+}
+
+/// A run condition that's true when any key was pressed this frame
+pub fn on_any_key() -> impl FnMut(EventReader<CrosstermKeyEventWrapper>) -> bool + Clone {
+    |mut keys: EventReader<CrosstermKeyEventWrapper>| {
+        keys.read().filter(|key| key.is_press()).count() > 0
+    }
+}
+
+/// A run condition that's true when `button` was clicked this frame, anywhere in the terminal
+pub fn on_mouse_click(
+    button: MouseButton,
+) -> impl FnMut(EventReader<CrosstermMouseEventWrapper>) -> bool + Clone {
+    move |mut mouse: EventReader<CrosstermMouseEventWrapper>| {
+        mouse.read().filter(|event| event.is_down(button)).count() > 0
+    }
+}
+
+/// A run condition that's true when the terminal was resized this frame
+pub fn on_resize() -> impl FnMut(EventReader<WindowResized>) -> bool + Clone {
+    |mut resized: EventReader<WindowResized>| resized.read().count() > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctrl(c: char) -> KeyChord {
+        KeyChord::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    fn key(c: char) -> KeyChord {
+        KeyChord::key(KeyCode::Char(c))
+    }
+
+    #[test]
+    fn sequences_complete_when_typed_in_order() {
+        let mut sequences = KeySequences::default();
+        sequences.bind("save", [ctrl('x'), ctrl('s')]);
+        assert!(sequences.press(ctrl('x')).is_empty());
+        assert_eq!(sequences.pending(), &[ctrl('x')]);
+        assert_eq!(sequences.press(ctrl('s')), ["save"]);
+        assert!(sequences.pending().is_empty());
+    }
+
+    #[test]
+    fn a_wrong_key_can_start_a_new_sequence() {
+        let mut sequences = KeySequences::default();
+        sequences.bind("top", [key('g'), key('g')]);
+        assert!(sequences.press(key('x')).is_empty());
+        assert!(sequences.pending().is_empty());
+        assert!(sequences.press(key('g')).is_empty());
+        assert_eq!(sequences.press(key('g')), ["top"]);
+    }
+
+    #[test]
+    fn sequences_that_start_longer_ones_wait() {
+        let mut sequences = KeySequences::default();
+        sequences.bind("delete", [key('d')]);
+        sequences.bind("delete line", [key('d'), key('d')]);
+        assert!(sequences.press(key('d')).is_empty());
+        assert_eq!(sequences.press(key('d')), ["delete line"]);
+
+        // A key that doesn't continue the longer sequence finishes the shorter one
+        assert!(sequences.press(key('d')).is_empty());
+        assert_eq!(sequences.press(key('x')), ["delete"]);
+        assert!(sequences.pending().is_empty());
+
+        // So does running out of time
+        assert!(sequences.press(key('d')).is_empty());
+        assert_eq!(sequences.expire().as_deref(), Some("delete"));
+        assert!(sequences.pending().is_empty());
+        assert_eq!(sequences.expire(), None);
+    }
+
+    #[test]
+    fn key_held_actions_expire_after_the_timeout() {
+        let mut state = ActionState::default();
+        state.press(1);
+        state.key_presses.insert(1, Duration::from_millis(100));
+
+        // Not released on the frame it was pressed, even with no timeout
+        state.release_expired(Duration::from_millis(100), Duration::ZERO);
+        assert!(state.pressed(1));
+
+        state.just_pressed.clear();
+        state.release_expired(Duration::from_millis(300), Duration::from_millis(500));
+        assert!(state.pressed(1));
+
+        state.release_expired(Duration::from_millis(600), Duration::from_millis(500));
+        assert!(!state.pressed(1));
+        assert!(state.just_released(1));
+    }
+
+    #[test]
+    fn release_all_releases_every_action() {
+        let mut state = ActionState::default();
+        state.press(1);
+        state.press(2);
+        state.release_all();
+        assert_eq!(state.get_pressed().count(), 0);
+        assert!(state.just_released(1) && state.just_released(2));
+    }
+}
@@ -0,0 +1,380 @@
+//! Placing entities relative to the screen, so they stay put when the terminal is resized.
+//!
+//! [`Anchored`] keeps an entity at an edge or the middle of the screen. [`RelativePosition`] and [`Size`] place and
+//! size it as a percentage of the screen, or of its parent's sprite if it has a [`Parent`].
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::window::WindowResized;
+
+use crate::camera::TerminalCamera;
+use crate::components::{Position, Sprite};
+use crate::{CrosstermWindow, ForceRedraw};
+
+/// A point on the edge of the screen, or its middle
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    // Where along an axis to go: 0 for the start, 1 for the middle and 2 for the end
+    fn alignment(&self) -> (u8, u8) {
+        match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        }
+    }
+
+    /// Where the top left of something `size` big goes to sit at this anchor in `area`, with `offset` moving it
+    /// away from the edges it's anchored to. In the middle, `offset` moves it right and down. Sizes are
+    /// (width, height)
+    pub fn place(&self, area: (u16, u16), size: (u16, u16), offset: (i32, i32)) -> (i32, i32) {
+        let place = |alignment, area: u16, size: u16, offset: i32| match alignment {
+            0 => offset,
+            1 => (area as i32 - size as i32) / 2 + offset,
+            _ => area as i32 - size as i32 - offset,
+        };
+        let (x, y) = self.alignment();
+        (
+            place(x, area.0, size.0, offset.0),
+            place(y, area.1, size.1, offset.1),
+        )
+    }
+}
+
+/// Keeps an entity's [`Position`] at an [`Anchor`] on the screen, wherever the camera is and however big the terminal
+/// gets, e.g. `Anchored::bottom_right((1, 0))` for something one cell in from the bottom right corner. The position's
+/// z is left alone. The entity's size comes from its sprite, so anchoring to the right or bottom waits until the
+/// sprite has loaded
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Anchored {
+    pub anchor: Anchor,
+    /// Cells to move away from the anchored edges, as (x, y)
+    pub offset: (i32, i32),
+}
+
+impl Anchored {
+    pub fn new(anchor: Anchor, offset: (i32, i32)) -> Anchored {
+        Anchored { anchor, offset }
+    }
+
+    pub fn top_left(offset: (i32, i32)) -> Anchored {
+        Anchored::new(Anchor::TopLeft, offset)
+    }
+
+    pub fn top(offset: (i32, i32)) -> Anchored {
+        Anchored::new(Anchor::Top, offset)
+    }
+
+    pub fn top_right(offset: (i32, i32)) -> Anchored {
+        Anchored::new(Anchor::TopRight, offset)
+    }
+
+    pub fn left(offset: (i32, i32)) -> Anchored {
+        Anchored::new(Anchor::Left, offset)
+    }
+
+    pub fn center(offset: (i32, i32)) -> Anchored {
+        Anchored::new(Anchor::Center, offset)
+    }
+
+    pub fn right(offset: (i32, i32)) -> Anchored {
+        Anchored::new(Anchor::Right, offset)
+    }
+
+    pub fn bottom_left(offset: (i32, i32)) -> Anchored {
+        Anchored::new(Anchor::BottomLeft, offset)
+    }
+
+    pub fn bottom(offset: (i32, i32)) -> Anchored {
+        Anchored::new(Anchor::Bottom, offset)
+    }
+
+    pub fn bottom_right(offset: (i32, i32)) -> Anchored {
+        Anchored::new(Anchor::BottomRight, offset)
+    }
+}
+
+/// Moves anchored entities to their anchors
+pub(crate) fn update_anchored(
+    mut anchored: Query<(&Anchored, &mut Position, Option<&Handle<Sprite>>)>,
+    sprites: Res<Assets<Sprite>>,
+    camera: Query<&TerminalCamera>,
+    window: Query<&CrosstermWindow>,
+) {
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+    let view = window.viewport();
+    let camera = camera.get_single().copied().unwrap_or_default();
+
+    for (anchored, mut position, sprite) in &mut anchored {
+        let size = sprite
+            .and_then(|sprite| sprites.get(sprite))
+            .map_or((0, 0), |sprite| {
+                (sprite.width() as u16, sprite.height() as u16)
+            });
+        let (x, y) = anchored
+            .anchor
+            .place((view.width, view.height), size, anchored.offset);
+
+        let (x, y) = (camera.x + x, camera.y + y);
+        if position.x != x || position.y != y {
+            position.x = x;
+            position.y = y;
+        }
+    }
+}
+
+/// A distance in cells, or a percentage of the space available
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Cells(i32),
+    Percent(f32),
+}
+
+impl Default for Length {
+    fn default() -> Self {
+        Length::Cells(0)
+    }
+}
+
+impl Length {
+    /// The length in cells, out of `total`. Percentages are rounded down
+    pub fn resolve(&self, total: u16) -> i32 {
+        match self {
+            Length::Cells(cells) => *cells,
+            Length::Percent(percent) => (total as f32 * percent / 100.0).floor() as i32,
+        }
+    }
+}
+
+/// Keeps an entity's [`Position`] a set distance across and down the screen, or across and down its parent's sprite
+/// if it has a [`Parent`] with a [`Position`]. For example `RelativePosition::percent(50.0, 25.0)` puts the top left
+/// of the entity halfway across and a quarter of the way down. The position's z is left alone
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub struct RelativePosition {
+    pub x: Length,
+    pub y: Length,
+}
+
+impl RelativePosition {
+    pub fn new(x: Length, y: Length) -> RelativePosition {
+        RelativePosition { x, y }
+    }
+
+    pub fn percent(x: f32, y: f32) -> RelativePosition {
+        RelativePosition::new(Length::Percent(x), Length::Percent(y))
+    }
+}
+
+/// How big an entity wants to be, in cells or as a percentage of the screen, or of its parent's sprite if it has a
+/// [`Parent`]. For example `Size::percent(80.0, 50.0)`. Sprites have a size of their own, so this is for things that
+/// draw themselves to fit, which read [`Size::resolved`]
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Size {
+    pub width: Length,
+    pub height: Length,
+    resolved: (u16, u16),
+}
+
+impl Size {
+    pub fn new(width: Length, height: Length) -> Size {
+        Size {
+            width,
+            height,
+            resolved: (0, 0),
+        }
+    }
+
+    pub fn cells(width: u16, height: u16) -> Size {
+        Size::new(Length::Cells(width as i32), Length::Cells(height as i32))
+    }
+
+    pub fn percent(width: f32, height: f32) -> Size {
+        Size::new(Length::Percent(width), Length::Percent(height))
+    }
+
+    /// The size in cells as of the last frame, as (width, height)
+    pub fn resolved(&self) -> (u16, u16) {
+        self.resolved
+    }
+}
+
+/// Resolves relative positions and sizes against the screen or the entity's parent
+pub(crate) fn update_relative_layout(
+    relative: Query<(Entity, &RelativePosition, Option<&Parent>)>,
+    mut sizes: Query<(&mut Size, Option<&Parent>)>,
+    mut positions: Query<(&mut Position, Option<&Handle<Sprite>>)>,
+    sprites: Res<Assets<Sprite>>,
+    camera: Query<&TerminalCamera>,
+    window: Query<&CrosstermWindow>,
+) {
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+    let view = window.viewport();
+    let camera = camera.get_single().copied().unwrap_or_default();
+
+    // The area to be relative to, as its top left and its size
+    let area = |positions: &Query<(&mut Position, Option<&Handle<Sprite>>)>,
+                parent: Option<&Parent>| {
+        parent
+            .and_then(|parent| positions.get(parent.get()).ok())
+            .map_or(
+                ((camera.x, camera.y), (view.width, view.height)),
+                |(position, sprite)| {
+                    let size = sprite
+                        .and_then(|sprite| sprites.get(sprite))
+                        .map_or((0, 0), |sprite| {
+                            (sprite.width() as u16, sprite.height() as u16)
+                        });
+                    ((position.x, position.y), size)
+                },
+            )
+    };
+
+    for (mut size, parent) in &mut sizes {
+        let (_, (width, height)) = area(&positions, parent);
+        let resolved = (
+            size.width.resolve(width).clamp(0, u16::MAX as i32) as u16,
+            size.height.resolve(height).clamp(0, u16::MAX as i32) as u16,
+        );
+        if size.resolved != resolved {
+            size.resolved = resolved;
+        }
+    }
+
+    // Parents can be placed relatively too, so work out every position before moving anything
+    let moves: Vec<_> = relative
+        .iter()
+        .map(|(entity, relative, parent)| {
+            let ((left, top), (width, height)) = area(&positions, parent);
+            let x = left + relative.x.resolve(width);
+            let y = top + relative.y.resolve(height);
+            (entity, x, y)
+        })
+        .collect();
+    for (entity, x, y) in moves {
+        let Ok((mut position, _)) = positions.get_mut(entity) else {
+            continue;
+        };
+        if position.x != x || position.y != y {
+            position.x = x;
+            position.y = y;
+        }
+    }
+}
+
+/// How the screen is redrawn after the terminal is resized
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutSettings {
+    redraw_delay: Option<Duration>,
+}
+
+impl Default for LayoutSettings {
+    fn default() -> Self {
+        LayoutSettings {
+            redraw_delay: Some(Duration::from_millis(150)),
+        }
+    }
+}
+
+impl LayoutSettings {
+    pub fn redraw_delay(&self) -> Option<Duration> {
+        self.redraw_delay
+    }
+
+    /// How long the terminal has to stay the same size before the screen is cleared and drawn again from scratch.
+    /// Every resize already redraws the screen, but some terminals rewrap what's on screen as they shrink, leaving
+    /// pieces of old frames behind once they're done. The delay is in game time, like the rest of [`Time`], so headless
+    /// runs redraw on the same frame every time. `None` turns this off
+    pub fn set_redraw_delay(&mut self, redraw_delay: Option<Duration>) -> &mut Self {
+        self.redraw_delay = redraw_delay;
+        self
+    }
+}
+
+/// Forces a redraw once the terminal has settled on a size
+pub(crate) fn redraw_after_resize(
+    mut last_resize: Local<Option<Duration>>,
+    time: Res<Time>,
+    settings: Res<LayoutSettings>,
+    mut resized: EventReader<WindowResized>,
+    mut redraw: EventWriter<ForceRedraw>,
+) {
+    if resized.read().count() > 0 {
+        *last_resize = Some(time.elapsed());
+    }
+    let Some(delay) = settings.redraw_delay else {
+        *last_resize = None;
+        return;
+    };
+    if last_resize.is_some_and(|last_resize| time.elapsed() >= last_resize + delay) {
+        redraw.send(ForceRedraw);
+        *last_resize = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anchors_place_things_in_the_area() {
+        let area = (20, 10);
+        let size = (4, 2);
+        let cases = [
+            (Anchor::TopLeft, (0, 0)),
+            (Anchor::Top, (8, 0)),
+            (Anchor::TopRight, (16, 0)),
+            (Anchor::Left, (0, 4)),
+            (Anchor::Center, (8, 4)),
+            (Anchor::Right, (16, 4)),
+            (Anchor::BottomLeft, (0, 8)),
+            (Anchor::Bottom, (8, 8)),
+            (Anchor::BottomRight, (16, 8)),
+        ];
+        for (anchor, expected) in cases {
+            assert_eq!(anchor.place(area, size, (0, 0)), expected, "{anchor:?}");
+        }
+    }
+
+    #[test]
+    fn offsets_move_away_from_the_anchored_edges() {
+        let area = (20, 10);
+        let size = (4, 2);
+        assert_eq!(Anchor::TopLeft.place(area, size, (1, 2)), (1, 2));
+        assert_eq!(Anchor::BottomRight.place(area, size, (1, 2)), (15, 6));
+        assert_eq!(Anchor::Center.place(area, size, (1, 2)), (9, 6));
+        // Something bigger than the area hangs off the edges
+        assert_eq!(Anchor::Center.place((3, 3), (5, 5), (0, 0)), (-1, -1));
+    }
+
+    #[test]
+    fn lengths_resolve_to_cells() {
+        assert_eq!(Length::Cells(3).resolve(80), 3);
+        assert_eq!(Length::Percent(50.0).resolve(80), 40);
+        // Rounded down
+        assert_eq!(Length::Percent(50.0).resolve(5), 2);
+        assert_eq!(Length::Percent(100.0).resolve(7), 7);
+    }
+}
@@ -3,56 +3,507 @@
 use bevy::prelude::*;
 use bevy_app::App;
 
+pub mod animation;
+mod ansi;
 mod asset_loaders;
+#[cfg(feature = "audio")]
+mod audio;
+pub mod backend;
+pub mod camera;
+pub mod capabilities;
+pub mod capture;
+pub mod color;
 pub mod components;
+pub mod config;
+pub mod debug;
+pub mod focus;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+pub mod headless;
+#[cfg(feature = "image")]
+pub mod image;
+pub mod input;
+pub mod layout;
+pub mod line_editor;
+mod markdown;
+pub mod minimap;
+pub mod mouse;
+pub mod pipeline;
+pub mod post_process;
+pub mod prefab;
 pub mod prelude;
+pub mod render;
+pub mod render_graph;
 mod runner;
+pub mod screen_stack;
+pub mod selection;
+pub mod state;
+pub mod suspend;
 mod systems;
+pub mod text;
+pub mod theme;
+pub mod ttyrec;
+pub mod widgets;
 
 pub struct CrosstermPlugin;
 
 impl Plugin for CrosstermPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Cursor::default())
+            .init_resource::<TerminalCommands>()
             .insert_resource(components::PreviousEntityDetails::default())
             .insert_resource(components::EntitiesToRedraw::default())
             .insert_resource(components::PreviousWindowColors::default())
+            .init_resource::<components::FrameOutput>()
             // Custom assets
             .register_asset_loader(asset_loaders::SpriteLoader)
             .init_asset::<components::Sprite>()
             .register_asset_loader(asset_loaders::StyleMapLoader)
             .init_asset::<components::StyleMap>()
+            .register_asset_loader(asset_loaders::ColoredSpriteLoader)
+            .register_asset_loader(asset_loaders::MarkdownLoader)
+            .init_asset::<components::ColoredSprite>()
+            .register_asset_loader(prefab::PrefabLoader)
+            .init_asset::<prefab::Prefab>()
+            .add_systems(
+                PostUpdate,
+                prefab::spawn_prefabs.before(CrosstermSet::Extract),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    camera::draw_render_targets,
+                    minimap::update_minimaps,
+                    systems::update_colored_sprites,
+                )
+                    .chain()
+                    .in_set(CrosstermSet::Extract),
+            )
             // Crossterm events
             .add_event::<CrosstermKeyEventWrapper>()
             .add_event::<CrosstermMouseEventWrapper>()
+            .add_event::<UnhandledKeySequence>()
+            .add_event::<WorldMouseEvent>()
+            .add_event::<ForceRedraw>()
+            .add_event::<AppExitError>()
+            // Input mapping
+            .init_resource::<input::KeyModifiersState>()
+            .init_resource::<input::KeySequences>()
+            .add_event::<input::KeySequenceEvent>()
+            .add_systems(PreUpdate, input::key_sequences)
+            .init_resource::<line_editor::LineEditor>()
+            .add_event::<line_editor::LineSubmitted>()
+            .add_systems(PreUpdate, line_editor::line_editor_input)
+            .add_systems(Update, line_editor::update_carets)
+            .init_resource::<selection::SelectionHighlight>()
+            .add_event::<selection::MouseSelection>()
+            .add_systems(PreUpdate, selection::track_mouse_selection)
+            .add_systems(Update, selection::update_selection_highlight)
+            .init_resource::<screen_stack::ScreenStack>()
+            .add_systems(
+                PostUpdate,
+                screen_stack::update_screen_stack.before(CrosstermSet::Extract),
+            )
+            .init_resource::<focus::FocusedEntity>()
+            .init_resource::<focus::FocusNavigation>()
+            .add_event::<focus::FocusGained>()
+            .add_event::<focus::FocusLost>()
+            .add_systems(
+                Update,
+                (
+                    (focus::cycle_focus, focus::move_focus_with_arrows),
+                    focus::send_focus_events,
+                )
+                    .chain(),
+            )
+            .init_resource::<layout::LayoutSettings>()
+            .add_systems(
+                PostUpdate,
+                (
+                    // Sizes are worked out first, then drawn at that size, then placed
+                    (
+                        layout::update_relative_layout,
+                        widgets::update_nine_patches,
+                        layout::update_anchored,
+                    )
+                        .chain(),
+                    layout::redraw_after_resize,
+                )
+                    .in_set(CrosstermSet::Layout),
+            )
+            .add_systems(Update, text::update_text_wrap)
+            .add_systems(Update, widgets::scroll_text_logs)
+            .init_resource::<widgets::ToastSettings>()
+            .add_systems(
+                Update,
+                (widgets::update_toasts, widgets::update_status_bars),
+            )
+            .add_event::<suspend::ProcessExited>()
+            .init_resource::<suspend::RawTerminalGuard>()
+            .add_systems(
+                PostUpdate,
+                suspend::update_raw_terminal_guard.after(CrosstermSet::Write),
+            )
+            .init_resource::<debug::DebugKeys>()
+            .add_systems(
+                Update,
+                (
+                    debug::debug_keys.run_if(|keys: Res<debug::DebugKeys>| keys.is_enabled()),
+                    debug::update_debug_overlay,
+                    debug::update_fps_counters,
+                ),
+            )
+            .add_plugins((
+                render::TerminalRenderPlugin::<debug::DebugOverlay>::default(),
+                render::TerminalRenderPlugin::<debug::FpsCounter>::default(),
+                render::TerminalRenderPlugin::<widgets::Toast>::default(),
+                render::TerminalRenderPlugin::<widgets::StatusBar>::default(),
+                render::TerminalRenderPlugin::<text::Text>::default(),
+                render::TerminalRenderPlugin::<widgets::TextLog>::default(),
+                render::TerminalRenderPlugin::<line_editor::Caret>::default(),
+                render::TerminalRenderPlugin::<mouse::MouseCursor>::default(),
+            ))
+            .add_systems(
+                PostUpdate,
+                mouse::update_mouse_cursors.in_set(CrosstermSet::Layout),
+            )
+            .add_systems(Update, systems::software_blink)
+            .init_resource::<animation::PaletteCycle>()
+            .add_event::<animation::AnimationFrameReached>()
+            .add_event::<animation::AnimationFinished>()
+            .add_systems(
+                Update,
+                (
+                    animation::animate_colors,
+                    animation::cycle_palettes,
+                    (
+                        animation::update_animation_controllers,
+                        animation::animate_sprites,
+                    )
+                        .chain(),
+                ),
+            )
+            .init_resource::<theme::Theme>()
+            .init_resource::<render_graph::RenderGraph>()
+            .init_resource::<capabilities::TerminalCapabilities>()
+            .add_systems(
+                PostUpdate,
+                theme::redraw_on_theme_change.before(CrosstermSet::CalculateRedraw),
+            )
             .set_runner(runner::crossterm_runner)
             // TODO check if asset events work correctly this way
             // Old comment:
             // This must be before LAST because change tracking is cleared during LAST, but AssetEvents are published
             // after POST_UPDATE. The timing for all these things is pretty delicate
+            .configure_sets(
+                PostUpdate,
+                (
+                    CrosstermSet::Extract,
+                    CrosstermSet::Layout,
+                    CrosstermSet::Prepare,
+                    CrosstermSet::Write,
+                )
+                    .chain(),
+            )
+            .configure_sets(
+                PostUpdate,
+                CrosstermSet::CalculateRedraw.in_set(CrosstermSet::Prepare),
+            )
+            .configure_sets(
+                PostUpdate,
+                (
+                    CrosstermSet::Render,
+                    TerminalPostRender,
+                    CrosstermSet::Flush,
+                )
+                    .chain()
+                    .in_set(CrosstermSet::Write),
+            )
             .add_systems(
                 PostUpdate,
                 (
-                    systems::add_previous_position,
-                    systems::calculate_entities_to_redraw,
-                    systems::crossterm_render,
-                    systems::update_previous_position,
+                    systems::add_previous_position.in_set(CrosstermSet::Prepare),
+                    systems::calculate_entities_to_redraw.in_set(CrosstermSet::CalculateRedraw),
+                    systems::crossterm_render.in_set(CrosstermSet::Render),
+                    render_graph::run_render_graph.in_set(CrosstermSet::Render),
+                    systems::update_terminal_modes.in_set(CrosstermSet::Write),
+                    systems::crossterm_flush.in_set(CrosstermSet::Flush),
+                    systems::update_previous_position.in_set(CrosstermSet::Write),
                 )
                     .chain(),
             );
+
+        #[cfg(feature = "syntect")]
+        app.register_asset_loader(asset_loaders::SyntaxLoader::default());
+        #[cfg(feature = "audio")]
+        app.init_resource::<audio::AudioActivity>()
+            .add_systems(Last, audio::track_audio);
+        #[cfg(feature = "gamepad")]
+        app.init_resource::<gamepad::GamepadActivity>()
+            .add_systems(Last, gamepad::track_gamepads);
+        #[cfg(feature = "image")]
+        app.add_systems(
+            PostUpdate,
+            image::update_image_sprites
+                .in_set(CrosstermSet::Extract)
+                .before(systems::update_colored_sprites),
+        );
     }
 }
 
-#[derive(Event)]
+/// Everything a terminal app needs, without the windowing and rendering plugins in bevy's `DefaultPlugins`: the
+/// time, task pool, hierarchy, input, window event and asset plugins, followed by [`CrosstermPlugin`].
+///
+/// The runner runs at 20 frames a second unless a `ScheduleRunnerPlugin` is added with another rate. Nothing is
+/// logged, as log messages would be drawn over the app; add bevy's `LogPlugin` to see them. With the "gamepad"
+/// feature, bevy's `GilrsPlugin` is included for reading gamepads. Other plugins, like `AudioPlugin` for the "audio"
+/// feature, are added as usual.
+pub struct CrosstermDefaultPlugins;
+
+impl PluginGroup for CrosstermDefaultPlugins {
+    fn build(self) -> bevy::app::PluginGroupBuilder {
+        let group = bevy::app::PluginGroupBuilder::start::<Self>()
+            .add(TaskPoolPlugin::default())
+            .add(TypeRegistrationPlugin)
+            .add(FrameCountPlugin)
+            .add(bevy::time::TimePlugin)
+            .add(HierarchyPlugin)
+            .add(bevy::input::InputPlugin)
+            // The terminal isn't a bevy window, so bevy mustn't open one, exit when there are none, or despawn the
+            // terminal's entity when it's asked to close
+            .add(bevy::window::WindowPlugin {
+                primary_window: None,
+                exit_condition: bevy::window::ExitCondition::DontExit,
+                close_when_requested: false,
+            });
+        #[cfg(feature = "gamepad")]
+        let group = group.add(bevy::gilrs::GilrsPlugin);
+        group.add(AssetPlugin::default()).add(CrosstermPlugin)
+    }
+}
+
+/// The sets the plugin's rendering systems run in during `PostUpdate`. Use these to order custom render systems
+/// against the built-in ones.
+///
+/// Rendering happens in phases, one after the other: [`Extract`](CrosstermSet::Extract),
+/// [`Layout`](CrosstermSet::Layout), [`Prepare`](CrosstermSet::Prepare) and [`Write`](CrosstermSet::Write). The other
+/// sets run inside them.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CrosstermSet {
+    /// Turns everything that can be drawn into sprites and stylemaps, like [`render::TerminalRender`] components and
+    /// [`components::ColoredSprite`]s. Custom renderables that make sprites of their own belong here, so they're laid
+    /// out and drawn on the same frame
+    Extract,
+    /// Moves entities that are placed relative to the screen, like [`layout::Anchored`] ones, and redraws the screen
+    /// once the terminal has stopped being resized. Add layout systems of your own here, so they see the terminal's
+    /// new size on the same frame and get drawn where they end up
+    Layout,
+    /// Works out what changed since the last frame and what has to be drawn because of it. Nothing is written to the
+    /// terminal yet
+    Prepare,
+    /// Works out which entities need to be drawn this frame and stores the result in
+    /// [`components::EntitiesToRedraw`]. Part of [`Prepare`](CrosstermSet::Prepare)
+    CalculateRedraw,
+    /// Writes the frame to the terminal, then remembers where everything was drawn for the next one
+    Write,
+    /// Draws everything in [`components::EntitiesToRedraw`] to the terminal. Part of [`Write`](CrosstermSet::Write)
+    Render,
+    /// Positions the cursor and flushes the frame's output to the terminal. Part of [`Write`](CrosstermSet::Write)
+    Flush,
+}
+
+/// Systems in this set run once per frame, after the renderer has queued its output but before that output is
+/// flushed. Commands queued to [`TerminalCommands`] from here end up in the same write as the frame, which makes it a
+/// safe place to emit custom escape sequences (a progress indicator in the title, cursor tricks, etc).
+///
+/// The cursor is placed according to the [`Cursor`] resource after this set runs.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TerminalPostRender;
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CrosstermKeyEventWrapper(pub crossterm::event::KeyEvent);
 
-#[derive(Event)]
+impl std::ops::Deref for CrosstermKeyEventWrapper {
+    type Target = crossterm::event::KeyEvent;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl CrosstermKeyEventWrapper {
+    pub fn key(&self) -> crossterm::event::KeyCode {
+        self.0.code
+    }
+
+    /// The character typed, if the key is a character key
+    pub fn char(&self) -> Option<char> {
+        match self.0.code {
+            crossterm::event::KeyCode::Char(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    pub fn modifiers(&self) -> crossterm::event::KeyModifiers {
+        self.0.modifiers
+    }
+
+    /// Whether the key was pressed. Terminals without the kitty keyboard protocol only report presses
+    pub fn is_press(&self) -> bool {
+        self.0.kind == crossterm::event::KeyEventKind::Press
+    }
+
+    /// Whether the key is being held down and auto-repeated
+    pub fn is_repeat(&self) -> bool {
+        self.0.kind == crossterm::event::KeyEventKind::Repeat
+    }
+
+    pub fn is_release(&self) -> bool {
+        self.0.kind == crossterm::event::KeyEventKind::Release
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CrosstermMouseEventWrapper(pub crossterm::event::MouseEvent);
 
+impl std::ops::Deref for CrosstermMouseEventWrapper {
+    type Target = crossterm::event::MouseEvent;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl CrosstermMouseEventWrapper {
+    /// The column and row of the terminal the event happened at. See [`WorldMouseEvent`] for where it is in the world
+    pub fn position(&self) -> (u16, u16) {
+        (self.0.column, self.0.row)
+    }
+
+    /// The button pressed, released or dragged with, if any
+    pub fn button(&self) -> Option<crossterm::event::MouseButton> {
+        use crossterm::event::MouseEventKind;
+        match self.0.kind {
+            MouseEventKind::Down(button)
+            | MouseEventKind::Up(button)
+            | MouseEventKind::Drag(button) => Some(button),
+            _ => None,
+        }
+    }
+
+    pub fn is_down(&self, button: crossterm::event::MouseButton) -> bool {
+        self.0.kind == crossterm::event::MouseEventKind::Down(button)
+    }
+
+    pub fn is_up(&self, button: crossterm::event::MouseButton) -> bool {
+        self.0.kind == crossterm::event::MouseEventKind::Up(button)
+    }
+
+    /// Whether the mouse moved, with or without a button held
+    pub fn is_move(&self) -> bool {
+        use crossterm::event::MouseEventKind;
+        matches!(self.0.kind, MouseEventKind::Moved | MouseEventKind::Drag(_))
+    }
+
+    /// How far the wheel scrolled, in lines, with down being positive. Zero for anything but scrolling
+    pub fn scroll(&self) -> i32 {
+        use crossterm::event::MouseEventKind;
+        match self.0.kind {
+            MouseEventKind::ScrollDown => 1,
+            MouseEventKind::ScrollUp => -1,
+            _ => 0,
+        }
+    }
+}
+
+/// Key presses that couldn't be turned into a single character, like a dead key followed by a letter it doesn't
+/// combine with. The keys that weren't part of the dead key are still sent as regular key events.
+#[derive(Event, Debug, Clone)]
+pub struct UnhandledKeySequence(pub Vec<crossterm::event::KeyEvent>);
+
+/// A mouse event translated into world coordinates, taking letterboxing and the [`camera::TerminalCamera`] into
+/// account. Only sent for events inside the viewport. The raw event is still sent as a [`CrosstermMouseEventWrapper`]
+#[derive(Event, Debug, Clone, Copy)]
+pub struct WorldMouseEvent {
+    pub event: crossterm::event::MouseEvent,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Send this event to clear the terminal and redraw every entity on the next frame. Useful for recovering
+/// after something outside of bevy (another program, a multiplexer detach, etc) has scribbled over the screen.
+#[derive(Event, Default, Debug, Clone, Copy)]
+pub struct ForceRedraw;
+
+/// Send this event to exit the app with a non-zero exit code, reporting a failure to the shell. Sending [`AppExit`]
+/// exits with a code of zero.
+///
+/// [`AppExit`]: bevy::app::AppExit
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppExitError(pub std::num::NonZeroU8);
+
 #[derive(Clone, Eq, PartialEq, Resource)]
 pub struct CrosstermWindowSettings {
     colors: components::Colors,
     title: Option<String>,
+    min_size: Option<(u16, u16)>,
+    virtual_size: Option<(u16, u16)>,
+    letterbox_style: components::Style,
+    unfocused_wait: Option<std::time::Duration>,
+    idle_pacing: Option<IdlePacing>,
+    mouse_capture: bool,
+    focus_reporting: bool,
+    alternate_screen: bool,
+    raw_mode: bool,
+    color_choice: ColorChoice,
+    software_blink: Option<std::time::Duration>,
+    render_thread: bool,
+    keyboard_enhancement_flags: crossterm::event::KeyboardEnhancementFlags,
+    frame_rate: Option<u32>,
+    color_depth: Option<color::ColorDepth>,
+    config_file: Option<std::path::PathBuf>,
+}
+
+/// Whether to draw in color, see [`CrosstermWindowSettings::set_color_choice`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum ColorChoice {
+    /// Follow the `NO_COLOR`, `CLICOLOR` and `CLICOLOR_FORCE` environment variables, and use color if they don't say
+    /// otherwise
+    #[default]
+    Auto,
+    Always,
+    /// Only draw text attributes (bold, underline and so on), leaving the colors up to the terminal
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether this choice means drawing in color, given the current environment
+    pub fn use_color(&self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                // See https://no-color.org/ and https://bixense.com/clicolors/
+                let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+                if var("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+                    true
+                } else if var("NO_COLOR").is_some() {
+                    false
+                } else {
+                    !var("CLICOLOR").is_some_and(|value| value == "0")
+                }
+            }
+        }
+    }
+}
+
+/// Frame pacing for mostly static apps, see [`CrosstermWindowSettings::set_idle_pacing`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IdlePacing {
+    /// The minimum time between frames while idle
+    pub wait: std::time::Duration,
+    /// How long nothing has to happen for before the app counts as idle
+    pub after: std::time::Duration,
 }
 
 impl Default for CrosstermWindowSettings {
@@ -60,11 +511,53 @@ impl Default for CrosstermWindowSettings {
         CrosstermWindowSettings {
             colors: components::Colors::term_colors(),
             title: None,
+            min_size: None,
+            virtual_size: None,
+            letterbox_style: components::Style::default(),
+            unfocused_wait: None,
+            idle_pacing: None,
+            mouse_capture: true,
+            focus_reporting: true,
+            alternate_screen: true,
+            raw_mode: true,
+            color_choice: ColorChoice::Auto,
+            software_blink: None,
+            render_thread: false,
+            keyboard_enhancement_flags:
+                crossterm::event::KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                    | crossterm::event::KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                    | crossterm::event::KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS
+                    | crossterm::event::KeyboardEnhancementFlags::REPORT_EVENT_TYPES,
+            frame_rate: None,
+            color_depth: None,
+            config_file: None,
         }
     }
 }
 
 impl CrosstermWindowSettings {
+    /// The default settings, adjusted for the terminal the environment describes. `NO_COLOR`, `CLICOLOR` and
+    /// `CLICOLOR_FORCE` decide whether to draw in color, `COLORTERM` and `TERM` how many colors there are, and a `TERM`
+    /// of `dumb` turns off color, mouse capture and the alternate screen
+    pub fn from_env() -> CrosstermWindowSettings {
+        let mut settings = CrosstermWindowSettings::default();
+        let color = ColorChoice::Auto.use_color();
+        settings
+            .set_color_choice(if color {
+                ColorChoice::Always
+            } else {
+                ColorChoice::Never
+            })
+            .set_color_depth(color::ColorDepth::from_env());
+        if std::env::var("TERM").is_ok_and(|term| term == "dumb") {
+            settings
+                .set_color_choice(ColorChoice::Never)
+                .set_mouse_capture(false)
+                .set_alternate_screen(false);
+        }
+        settings
+    }
+
     pub fn colors(&self) -> components::Colors {
         self.colors
     }
@@ -82,6 +575,309 @@ impl CrosstermWindowSettings {
         self.colors = colors;
         self
     }
+
+    /// The smallest terminal size (width, height) the app can be played at, if any
+    pub fn min_size(&self) -> Option<(u16, u16)> {
+        self.min_size
+    }
+
+    /// While the terminal is smaller than `width`x`height`, normal rendering is paused and a message asking the user
+    /// to enlarge their terminal is shown instead. Rendering resumes as soon as the terminal is big enough again.
+    pub fn set_min_size(&mut self, width: u16, height: u16) -> &mut Self {
+        self.min_size = Some((width, height));
+        self
+    }
+
+    /// The fixed logical grid (width, height) the scene is laid out in, if any
+    pub fn virtual_size(&self) -> Option<(u16, u16)> {
+        self.virtual_size
+    }
+
+    /// Lay the scene out on a fixed `width`x`height` grid regardless of the terminal's size. If the terminal is larger,
+    /// the grid is centered and the space around it is filled with the letterbox style. If it's smaller, the grid is
+    /// clipped on the right and bottom.
+    pub fn set_virtual_size(&mut self, width: u16, height: u16) -> &mut Self {
+        self.virtual_size = Some((width, height));
+        self
+    }
+
+    pub fn letterbox_style(&self) -> components::Style {
+        self.letterbox_style
+    }
+
+    /// The style used to fill the terminal outside of the virtual grid. Unset colors fall back to the window colors
+    pub fn set_letterbox_style(&mut self, style: components::Style) -> &mut Self {
+        self.letterbox_style = style;
+        self
+    }
+
+    /// The minimum time between frames while the terminal doesn't have focus, if throttling is enabled
+    pub fn unfocused_wait(&self) -> Option<std::time::Duration> {
+        self.unfocused_wait
+    }
+
+    /// Run at most one frame every `wait` while the terminal doesn't have focus (e.g. 500ms for 2 FPS), so the app
+    /// doesn't eat CPU in a background tab. The normal frame rate is restored when focus comes back. This has no
+    /// effect on terminals that don't report focus changes.
+    pub fn set_unfocused_wait(&mut self, wait: std::time::Duration) -> &mut Self {
+        self.unfocused_wait = Some(wait);
+        self
+    }
+
+    pub fn idle_pacing(&self) -> Option<IdlePacing> {
+        self.idle_pacing
+    }
+
+    /// Run slowly while the app is idle, and at the normal frame rate as soon as something happens. The app is idle
+    /// once no terminal events have arrived and nothing has been drawn for `pacing.after`. Input wakes the runner up
+    /// immediately, but anything driven purely by timers will only update at the idle rate, unless it sends a
+    /// [`RequestRedraw`](bevy::window::RequestRedraw) to have the next frame run at the normal rate. That's only the
+    /// one frame, sending it doesn't stop the app being idle.
+    pub fn set_idle_pacing(&mut self, pacing: IdlePacing) -> &mut Self {
+        self.idle_pacing = Some(pacing);
+        self
+    }
+
+    pub fn mouse_capture(&self) -> bool {
+        self.mouse_capture
+    }
+
+    /// Whether the app receives mouse events. While the mouse is captured the terminal's own text selection doesn't
+    /// work, so turn this off for apps that don't need the mouse. Enabled by default, and can be changed while running
+    /// with [`CrosstermWindow::set_mouse_capture`].
+    pub fn set_mouse_capture(&mut self, capture: bool) -> &mut Self {
+        self.mouse_capture = capture;
+        self
+    }
+
+    pub fn focus_reporting(&self) -> bool {
+        self.focus_reporting
+    }
+
+    /// Whether to ask the terminal to report focus changes. Some terminals and multiplexers misbehave with focus
+    /// reporting on (e.g. by typing the reports into the app), so it can be turned off. Without it the app always
+    /// counts as focused. Enabled by default.
+    pub fn set_focus_reporting(&mut self, report: bool) -> &mut Self {
+        self.focus_reporting = report;
+        self
+    }
+
+    pub fn alternate_screen(&self) -> bool {
+        self.alternate_screen
+    }
+
+    /// Whether to draw on the terminal's alternate screen, which is thrown away on exit. Turn this off to draw on the
+    /// main screen instead, so the last frame stays in the scrollback after the app exits. Enabled by default.
+    pub fn set_alternate_screen(&mut self, alternate: bool) -> &mut Self {
+        self.alternate_screen = alternate;
+        self
+    }
+
+    pub fn raw_mode(&self) -> bool {
+        self.raw_mode
+    }
+
+    /// Whether to put the terminal in raw mode, which is needed for keyboard, mouse and focus events. Turn this off
+    /// for render only apps like dashboards: the terminal stays line buffered, no input events are sent and the
+    /// terminal is only checked for resizes. Ctrl-C exits the app as usual. Enabled by default.
+    pub fn set_raw_mode(&mut self, raw: bool) -> &mut Self {
+        self.raw_mode = raw;
+        self
+    }
+
+    pub fn color_choice(&self) -> ColorChoice {
+        self.color_choice
+    }
+
+    /// Whether to draw in color. By default the `NO_COLOR`, `CLICOLOR` and `CLICOLOR_FORCE` conventions are followed,
+    /// this overrides them
+    pub fn set_color_choice(&mut self, choice: ColorChoice) -> &mut Self {
+        self.color_choice = choice;
+        self
+    }
+
+    pub fn software_blink(&self) -> Option<std::time::Duration> {
+        self.software_blink
+    }
+
+    /// On terminals that can't blink text, blink it anyway by hiding and showing it every `interval`. Without this,
+    /// blinking text is shown steady on those terminals.
+    pub fn set_software_blink(&mut self, interval: std::time::Duration) -> &mut Self {
+        self.software_blink = Some(interval);
+        self
+    }
+
+    pub fn render_thread(&self) -> bool {
+        self.render_thread
+    }
+
+    /// Write each frame to the terminal on a thread of its own, so a slow terminal (e.g. over ssh) doesn't hold up the
+    /// next update. Frames are still worked out in `PostUpdate`, only writing them happens in the background, and
+    /// frames that pile up while the terminal catches up are written together. Headless runs always write straight
+    /// away. Disabled by default.
+    pub fn set_render_thread(&mut self, render_thread: bool) -> &mut Self {
+        self.render_thread = render_thread;
+        self
+    }
+
+    pub fn keyboard_enhancement_flags(&self) -> crossterm::event::KeyboardEnhancementFlags {
+        self.keyboard_enhancement_flags
+    }
+
+    /// The kitty keyboard protocol features to ask terminals that support it for. By default that's all of them, which
+    /// bevy's key events need: without `REPORT_EVENT_TYPES` keys are never released, and without
+    /// `REPORT_ALL_KEYS_AS_ESCAPE_CODES` modifier keys aren't reported on their own
+    pub fn set_keyboard_enhancement_flags(
+        &mut self,
+        flags: crossterm::event::KeyboardEnhancementFlags,
+    ) -> &mut Self {
+        self.keyboard_enhancement_flags = flags;
+        self
+    }
+
+    pub fn frame_rate(&self) -> Option<u32> {
+        self.frame_rate
+    }
+
+    /// Run at most `fps` frames a second, instead of the rate the `ScheduleRunnerPlugin` was set up with
+    pub fn set_frame_rate(&mut self, fps: u32) -> &mut Self {
+        self.frame_rate = Some(fps.max(1));
+        self
+    }
+
+    pub fn color_depth(&self) -> Option<color::ColorDepth> {
+        self.color_depth
+    }
+
+    /// How many colors the terminal can show, instead of guessing from the environment at startup. See
+    /// [`TerminalCapabilities::color_depth`](capabilities::TerminalCapabilities::color_depth)
+    pub fn set_color_depth(&mut self, depth: color::ColorDepth) -> &mut Self {
+        self.color_depth = Some(depth);
+        self
+    }
+
+    pub fn config_file(&self) -> Option<&std::path::Path> {
+        self.config_file.as_deref()
+    }
+
+    /// Read more settings and theme colors from this file when the app starts, see [`config`]. They take precedence
+    /// over the ones set here. It's fine for the file not to exist, so a game can look for one the player may have
+    /// written. A file that can't be used is ignored, and what's wrong with it is printed to stderr once the app exits
+    pub fn set_config_file(&mut self, path: impl Into<std::path::PathBuf>) -> &mut Self {
+        self.config_file = Some(path.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_title<T: ToString>(mut self, title: T) -> Self {
+        self.set_title(title);
+        self
+    }
+
+    #[must_use]
+    pub fn with_colors(mut self, colors: components::Colors) -> Self {
+        self.set_colors(colors);
+        self
+    }
+
+    #[must_use]
+    pub fn with_min_size(mut self, width: u16, height: u16) -> Self {
+        self.set_min_size(width, height);
+        self
+    }
+
+    #[must_use]
+    pub fn with_virtual_size(mut self, width: u16, height: u16) -> Self {
+        self.set_virtual_size(width, height);
+        self
+    }
+
+    #[must_use]
+    pub fn with_letterbox_style(mut self, style: components::Style) -> Self {
+        self.set_letterbox_style(style);
+        self
+    }
+
+    #[must_use]
+    pub fn with_unfocused_wait(mut self, wait: std::time::Duration) -> Self {
+        self.set_unfocused_wait(wait);
+        self
+    }
+
+    #[must_use]
+    pub fn with_idle_pacing(mut self, pacing: IdlePacing) -> Self {
+        self.set_idle_pacing(pacing);
+        self
+    }
+
+    #[must_use]
+    pub fn with_mouse_capture(mut self, capture: bool) -> Self {
+        self.set_mouse_capture(capture);
+        self
+    }
+
+    #[must_use]
+    pub fn with_focus_reporting(mut self, report: bool) -> Self {
+        self.set_focus_reporting(report);
+        self
+    }
+
+    #[must_use]
+    pub fn with_alternate_screen(mut self, alternate: bool) -> Self {
+        self.set_alternate_screen(alternate);
+        self
+    }
+
+    #[must_use]
+    pub fn with_raw_mode(mut self, raw: bool) -> Self {
+        self.set_raw_mode(raw);
+        self
+    }
+
+    #[must_use]
+    pub fn with_color_choice(mut self, choice: ColorChoice) -> Self {
+        self.set_color_choice(choice);
+        self
+    }
+
+    #[must_use]
+    pub fn with_software_blink(mut self, interval: std::time::Duration) -> Self {
+        self.set_software_blink(interval);
+        self
+    }
+
+    #[must_use]
+    pub fn with_render_thread(mut self, render_thread: bool) -> Self {
+        self.set_render_thread(render_thread);
+        self
+    }
+
+    #[must_use]
+    pub fn with_keyboard_enhancement_flags(
+        mut self,
+        flags: crossterm::event::KeyboardEnhancementFlags,
+    ) -> Self {
+        self.set_keyboard_enhancement_flags(flags);
+        self
+    }
+
+    #[must_use]
+    pub fn with_frame_rate(mut self, fps: u32) -> Self {
+        self.set_frame_rate(fps);
+        self
+    }
+
+    #[must_use]
+    pub fn with_color_depth(mut self, depth: color::ColorDepth) -> Self {
+        self.set_color_depth(depth);
+        self
+    }
+
+    #[must_use]
+    pub fn with_config_file(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.set_config_file(path);
+        self
+    }
 }
 
 #[derive(Debug, Component)]
@@ -90,18 +886,96 @@ pub struct CrosstermWindow {
     width: u16,
     colors: components::Colors,
     title: Option<String>,
+    min_size: Option<(u16, u16)>,
+    virtual_size: Option<(u16, u16)>,
+    letterbox_style: components::Style,
     supports_keyboard_enhancement: bool,
+    keyboard_enhancement_flags: crossterm::event::KeyboardEnhancementFlags,
+    mouse_capture: bool,
+    focus_reporting: bool,
+    alternate_screen: bool,
+    raw_mode: bool,
+    monochrome: bool,
+    styled_underlines: bool,
+    native_blink: bool,
+    software_blink: Option<std::time::Duration>,
+    // Whether software blinking text is currently hidden
+    blink_hidden: bool,
+    // Set once the terminal has been restored, so it only happens once
+    torn_down: bool,
+    backend: backend::SharedBackend,
+}
+
+/// The region of the terminal the scene is drawn into, in terminal cells
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Viewport {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Viewport {
+    /// Returns true if the terminal cell at `x`,`y` is inside the viewport
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
 }
 
 impl CrosstermWindow {
+    /// The height of the terminal. The scene can be a different size, see [`CrosstermWindow::virtual_height`]
     pub fn height(&self) -> u16 {
         self.height
     }
 
+    /// The width of the terminal. The scene can be a different size, see [`CrosstermWindow::virtual_width`]
     pub fn width(&self) -> u16 {
         self.width
     }
 
+    /// The height of the scene. This is the virtual height if one was set, otherwise the terminal's height
+    pub fn virtual_height(&self) -> u16 {
+        self.virtual_size.map_or(self.height, |(_, height)| height)
+    }
+
+    /// The width of the scene. This is the virtual width if one was set, otherwise the terminal's width
+    pub fn virtual_width(&self) -> u16 {
+        self.virtual_size.map_or(self.width, |(width, _)| width)
+    }
+
+    /// The actual size of the terminal as (width, height)
+    pub fn terminal_size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    /// Converts a terminal cell to a position relative to the viewport, or None if the cell is outside of it
+    pub fn terminal_to_viewport(&self, column: u16, row: u16) -> Option<(u16, u16)> {
+        let view = self.viewport();
+        if view.contains(column, row) {
+            Some((column - view.x, row - view.y))
+        } else {
+            None
+        }
+    }
+
+    /// Where the scene ends up on the terminal. Without a virtual size this covers the whole terminal
+    pub fn viewport(&self) -> Viewport {
+        match self.virtual_size {
+            Some((width, height)) => Viewport {
+                x: self.width.saturating_sub(width) / 2,
+                y: self.height.saturating_sub(height) / 2,
+                width: std::cmp::min(width, self.width),
+                height: std::cmp::min(height, self.height),
+            },
+            None => Viewport {
+                x: 0,
+                y: 0,
+                width: self.width,
+                height: self.height,
+            },
+        }
+    }
+
     pub fn title(&self) -> Option<&str> {
         self.title.as_deref()
     }
@@ -114,12 +988,101 @@ impl CrosstermWindow {
         self.colors = new_colors;
     }
 
+    /// Whether colors are left out of the output, see [`CrosstermWindowSettings::set_color_choice`]
+    pub fn is_monochrome(&self) -> bool {
+        self.monochrome
+    }
+
+    /// The attributes to actually send to the terminal, with underline styles it can't show replaced by a plain
+    /// underline, and blinking done in software if needed
+    pub(crate) fn terminal_attributes(
+        &self,
+        attributes: crossterm::style::Attributes,
+    ) -> crossterm::style::Attributes {
+        use crossterm::style::Attribute;
+
+        let mut attributes = attributes;
+        if !self.native_blink
+            && (attributes.has(Attribute::SlowBlink) || attributes.has(Attribute::RapidBlink))
+        {
+            attributes.unset(Attribute::SlowBlink);
+            attributes.unset(Attribute::RapidBlink);
+            if self.blink_hidden {
+                attributes.set(Attribute::Hidden);
+            }
+        }
+
+        if self.styled_underlines {
+            return attributes;
+        }
+        for styled in [
+            Attribute::DoubleUnderlined,
+            Attribute::Undercurled,
+            Attribute::Underdotted,
+            Attribute::Underdashed,
+        ] {
+            if attributes.has(styled) {
+                attributes.unset(styled);
+                attributes.set(Attribute::Underlined);
+            }
+        }
+        attributes
+    }
+
+    /// The underline color to actually send to the terminal, if it can show one
+    pub(crate) fn underline_color(
+        &self,
+        color: Option<components::Color>,
+    ) -> Option<components::Color> {
+        color.filter(|_| self.styled_underlines && !self.monochrome)
+    }
+
+    /// The colors to actually send to the terminal, which are always the terminal's own in monochrome mode
+    pub(crate) fn to_crossterm(&self, colors: components::Colors) -> crossterm::style::Colors {
+        if self.monochrome {
+            components::Colors::term_colors().to_crossterm()
+        } else {
+            colors.to_crossterm()
+        }
+    }
+
+    /// Whether the terminal is in raw mode, see [`CrosstermWindowSettings::set_raw_mode`]
+    pub fn raw_mode(&self) -> bool {
+        self.raw_mode
+    }
+
+    /// Whether mouse events are captured. Always false without raw mode
+    pub fn mouse_capture(&self) -> bool {
+        self.mouse_capture && self.raw_mode
+    }
+
+    /// Start or stop receiving mouse events, e.g. to let the user select and copy text with the terminal while a
+    /// "copy mode" is active. Takes effect at the end of the frame.
+    pub fn set_mouse_capture(&mut self, capture: bool) {
+        self.mouse_capture = capture;
+    }
+
+    /// The column in the middle of the scene, which is the virtual grid if there is one
     pub fn x_center(&self) -> u16 {
-        self.width / 2
+        self.virtual_width() / 2
     }
 
+    /// The row in the middle of the scene, which is the virtual grid if there is one
     pub fn y_center(&self) -> u16 {
-        self.height / 2
+        self.virtual_height() / 2
+    }
+
+    pub fn min_size(&self) -> Option<(u16, u16)> {
+        self.min_size
+    }
+
+    /// Returns true if the terminal is smaller than the minimum size in [`CrosstermWindowSettings`]. Nothing is
+    /// rendered while this is the case.
+    pub fn is_too_small(&self) -> bool {
+        match self.min_size {
+            Some((min_width, min_height)) => self.width < min_width || self.height < min_height,
+            None => false,
+        }
     }
 }
 
@@ -129,3 +1092,34 @@ pub struct Cursor {
     pub y: i32,
     pub hidden: bool,
 }
+
+/// Raw crossterm commands to send to the terminal this frame.
+///
+/// Use this instead of writing to stdout directly so output doesn't race the renderer. Queued commands are written once
+/// per frame, in the order they were queued, after the frame has been rendered and after [`TerminalPostRender`] runs.
+#[derive(Debug, Default, Resource)]
+pub struct TerminalCommands {
+    buffer: String,
+}
+
+impl TerminalCommands {
+    pub fn queue(&mut self, command: impl crossterm::Command) -> &mut Self {
+        command
+            .write_ansi(&mut self.buffer)
+            .expect("Could not queue terminal command");
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Drop everything that was queued but not written yet
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    pub(crate) fn take(&mut self) -> String {
+        std::mem::take(&mut self.buffer)
+    }
+}
@@ -0,0 +1,481 @@
+//! A single line text editor with readline style keys, and the caret that shows where it's editing.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::camera::TerminalCamera;
+use crate::components::{Position, Style, Visible};
+use crate::focus::{Focusable, FocusedEntity};
+use crate::render::{CellBuffer, TerminalRender};
+use crate::{CrosstermKeyEventWrapper, Cursor};
+
+// How many killed pieces of text are remembered
+const KILL_RING_SIZE: usize = 32;
+
+/// Sent when the user presses enter in the [`LineEditor`]
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct LineSubmitted(pub String);
+
+/// A single line text editor with readline (emacs) style keys, for in-game consoles and chat boxes.
+///
+/// The editor only listens to key events while it's active. It doesn't draw anything, use [`LineEditor::text`] and
+/// [`LineEditor::cursor_column`] to render it however you like, and a [`Caret`] to show where the cursor is.
+///
+/// | Keys                     | Action                             |
+/// |--------------------------|------------------------------------|
+/// | Left, Ctrl-b             | Move back a character              |
+/// | Right, Ctrl-f            | Move forward a character           |
+/// | Ctrl-Left, Alt-b         | Move back a word                   |
+/// | Ctrl-Right, Alt-f        | Move forward a word                |
+/// | Home, Ctrl-a             | Move to the start of the line      |
+/// | End, Ctrl-e              | Move to the end of the line        |
+/// | Backspace, Ctrl-h        | Delete the previous character      |
+/// | Delete, Ctrl-d           | Delete the next character          |
+/// | Ctrl-w, Alt-Backspace    | Kill the previous word             |
+/// | Alt-d                    | Kill the next word                 |
+/// | Ctrl-k                   | Kill to the end of the line        |
+/// | Ctrl-u                   | Kill to the start of the line      |
+/// | Ctrl-y                   | Yank (paste) the last killed text  |
+/// | Up, Ctrl-p               | Previous history entry             |
+/// | Down, Ctrl-n             | Next history entry                 |
+/// | Enter                    | Submit the line                    |
+#[derive(Default, Resource)]
+pub struct LineEditor {
+    active: bool,
+    text: String,
+    // Byte index into text, always on a grapheme boundary
+    cursor: usize,
+    history: Vec<String>,
+    // When browsing the history, the entry being shown and the line that was being edited before browsing started
+    history_index: Option<usize>,
+    saved_line: String,
+    kill_ring: Vec<String>,
+}
+
+impl LineEditor {
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn set_active(&mut self, active: bool) -> &mut Self {
+        self.active = active;
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replace the line and move the cursor to its end
+    pub fn set_text<T: ToString>(&mut self, text: T) -> &mut Self {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+        self.history_index = None;
+        self
+    }
+
+    pub fn clear(&mut self) -> &mut Self {
+        self.set_text("")
+    }
+
+    /// The position of the cursor in graphemes from the start of the line
+    pub fn cursor_column(&self) -> usize {
+        self.text[..self.cursor].graphemes(true).count()
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Add a line to the end of the history, skipping empty lines and repeats of the last entry
+    pub fn push_history<T: ToString>(&mut self, line: T) {
+        let line = line.to_string();
+        if !line.is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line);
+        }
+    }
+
+    pub fn kill_ring(&self) -> &[String] {
+        &self.kill_ring
+    }
+
+    /// Apply a key press to the line. Returns the line if the key submitted it
+    pub fn handle_key(&mut self, key: &KeyEvent) -> Option<String> {
+        if key.kind == KeyEventKind::Release {
+            return None;
+        }
+
+        // AltGr is reported as Ctrl+Alt on Windows, so characters typed with both are inserted rather than treated as
+        // shortcuts
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Enter => return Some(self.submit()),
+            KeyCode::Left if ctrl => self.cursor = self.word_start(),
+            KeyCode::Right if ctrl => self.cursor = self.word_end(),
+            KeyCode::Left => self.cursor = self.prev_boundary(),
+            KeyCode::Right => self.cursor = self.next_boundary(),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            KeyCode::Backspace if alt => self.kill(self.word_start(), self.cursor),
+            KeyCode::Backspace => self.delete(self.prev_boundary(), self.cursor),
+            KeyCode::Delete => self.delete(self.cursor, self.next_boundary()),
+            KeyCode::Up => self.history_prev(),
+            KeyCode::Down => self.history_next(),
+            KeyCode::Char(c) if ctrl && !alt => match c {
+                'a' => self.cursor = 0,
+                'e' => self.cursor = self.text.len(),
+                'b' => self.cursor = self.prev_boundary(),
+                'f' => self.cursor = self.next_boundary(),
+                'h' => self.delete(self.prev_boundary(), self.cursor),
+                'd' => self.delete(self.cursor, self.next_boundary()),
+                'w' => self.kill(self.word_start(), self.cursor),
+                'k' => self.kill(self.cursor, self.text.len()),
+                'u' => self.kill(0, self.cursor),
+                'y' => self.yank(),
+                'p' => self.history_prev(),
+                'n' => self.history_next(),
+                _ => {}
+            },
+            KeyCode::Char(c) if alt && !ctrl => match c {
+                'b' => self.cursor = self.word_start(),
+                'f' => self.cursor = self.word_end(),
+                'd' => self.kill(self.cursor, self.word_end()),
+                _ => {}
+            },
+            KeyCode::Char(c) => self.insert(c.encode_utf8(&mut [0u8; 4])),
+            _ => {}
+        }
+
+        None
+    }
+
+    fn submit(&mut self) -> String {
+        let line = std::mem::take(&mut self.text);
+        self.cursor = 0;
+        self.history_index = None;
+        self.push_history(&line);
+        line
+    }
+
+    fn insert(&mut self, s: &str) {
+        self.text.insert_str(self.cursor, s);
+        self.cursor += s.len();
+    }
+
+    fn delete(&mut self, start: usize, end: usize) {
+        self.text.replace_range(start..end, "");
+        self.cursor = start;
+    }
+
+    fn kill(&mut self, start: usize, end: usize) {
+        if start == end {
+            return;
+        }
+        self.kill_ring.push(self.text[start..end].to_string());
+        if self.kill_ring.len() > KILL_RING_SIZE {
+            self.kill_ring.remove(0);
+        }
+        self.delete(start, end);
+    }
+
+    fn yank(&mut self) {
+        if let Some(killed) = self.kill_ring.last().cloned() {
+            self.insert(&killed);
+        }
+    }
+
+    fn history_prev(&mut self) {
+        let index = match self.history_index {
+            Some(0) => return,
+            Some(index) => index - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.saved_line = self.text.clone();
+                self.history.len() - 1
+            }
+        };
+        self.history_index = Some(index);
+        self.text = self.history[index].clone();
+        self.cursor = self.text.len();
+    }
+
+    fn history_next(&mut self) {
+        let Some(index) = self.history_index else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.history_index = Some(index + 1);
+            self.text = self.history[index + 1].clone();
+        } else {
+            self.history_index = None;
+            self.text = std::mem::take(&mut self.saved_line);
+        }
+        self.cursor = self.text.len();
+    }
+
+    fn prev_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.cursor
+            + self.text[self.cursor..]
+                .graphemes(true)
+                .next()
+                .map_or(0, str::len)
+    }
+
+    /// The start of the word before the cursor, skipping any separators right before it
+    fn word_start(&self) -> usize {
+        let mut start = self.cursor;
+        let mut in_word = false;
+        for (i, grapheme) in self.text[..self.cursor].grapheme_indices(true).rev() {
+            if is_word(grapheme) {
+                in_word = true;
+            } else if in_word {
+                break;
+            }
+            start = i;
+        }
+        start
+    }
+
+    /// The end of the word after the cursor, skipping any separators right after it
+    fn word_end(&self) -> usize {
+        let mut end = self.cursor;
+        let mut in_word = false;
+        for grapheme in self.text[self.cursor..].graphemes(true) {
+            if is_word(grapheme) {
+                in_word = true;
+            } else if in_word {
+                break;
+            }
+            end += grapheme.len();
+        }
+        end
+    }
+}
+
+fn is_word(grapheme: &str) -> bool {
+    grapheme
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Feeds key presses to the line editor while it's active
+pub(crate) fn line_editor_input(
+    mut editor: ResMut<LineEditor>,
+    mut keys: EventReader<CrosstermKeyEventWrapper>,
+    mut submitted: EventWriter<LineSubmitted>,
+) {
+    if !editor.active {
+        keys.clear();
+        return;
+    }
+
+    for key in keys.read() {
+        if let Some(line) = editor.handle_key(&key.0) {
+            submitted.send(LineSubmitted(line));
+        }
+    }
+}
+
+/// How a [`Caret`] is shown
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CaretStyle {
+    /// The cell under the caret is drawn in reverse video
+    #[default]
+    Inverted,
+    /// The terminal's own cursor is moved to the caret, see [`Cursor`]
+    Cursor,
+}
+
+/// Shows where the [`LineEditor`]'s cursor is, for an editor whose text is drawn starting at `origin`. It's only shown
+/// while the editor is active, and if the entity is [`Focusable`], only while it has focus, so only one caret shows
+/// at a time. It blinks, but stays on while the user is typing. Spawn it with [`CaretBundle`]
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct Caret {
+    /// Where the first character of the line is, in world coordinates
+    pub origin: (i32, i32),
+    style: CaretStyle,
+    blink: Option<Duration>,
+    grapheme: String,
+    on: bool,
+    elapsed: Duration,
+}
+
+impl Caret {
+    pub fn new(x: i32, y: i32) -> Caret {
+        Caret {
+            origin: (x, y),
+            style: CaretStyle::default(),
+            blink: Some(Duration::from_millis(530)),
+            grapheme: " ".to_string(),
+            on: true,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn style(&self) -> CaretStyle {
+        self.style
+    }
+
+    pub fn set_style(&mut self, style: CaretStyle) -> &mut Self {
+        self.style = style;
+        self
+    }
+
+    pub fn blink(&self) -> Option<Duration> {
+        self.blink
+    }
+
+    /// How long the caret stays on, then off, as it blinks. `None` keeps it on. Defaults to 530ms, like most
+    /// terminals
+    pub fn set_blink(&mut self, blink: Option<Duration>) -> &mut Self {
+        self.blink = blink;
+        self
+    }
+}
+
+impl TerminalRender for Caret {
+    fn size(&self) -> (u16, u16) {
+        (1, 1)
+    }
+
+    fn render(&self, buffer: &mut CellBuffer) {
+        let style = Style::with_attrib(crossterm::style::Attribute::Reverse);
+        buffer.set(0, 0, &self.grapheme, style);
+    }
+}
+
+#[derive(Bundle, Debug)]
+pub struct CaretBundle {
+    pub caret: Caret,
+    pub position: Position,
+    pub visible: Visible,
+}
+
+impl CaretBundle {
+    /// A caret for text drawn starting at `x`,`y`, drawn at depth `z`, which should be above the text
+    pub fn new(x: i32, y: i32, z: i32) -> CaretBundle {
+        CaretBundle {
+            caret: Caret::new(x, y),
+            position: Position::new(x, y, z),
+            visible: Visible::invisible(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_style(mut self, style: CaretStyle) -> Self {
+        self.caret.style = style;
+        self
+    }
+
+    #[must_use]
+    pub fn with_blink(mut self, blink: Option<Duration>) -> Self {
+        self.caret.blink = blink;
+        self
+    }
+}
+
+/// Moves carets to the editor's cursor and blinks them
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_carets(
+    mut showing_cursor: Local<bool>,
+    time: Res<Time<Real>>,
+    editor: Res<LineEditor>,
+    focused: Res<FocusedEntity>,
+    mut cursor: ResMut<Cursor>,
+    camera: Query<&TerminalCamera>,
+    mut carets: Query<(
+        Entity,
+        &mut Caret,
+        &mut Position,
+        &mut Visible,
+        Option<&Focusable>,
+    )>,
+) {
+    let camera = camera.get_single().copied().unwrap_or_default();
+    let column = editor.cursor_column();
+    let grapheme = editor
+        .text()
+        .graphemes(true)
+        .nth(column)
+        .unwrap_or(" ")
+        .to_string();
+    let mut cursor_wanted = false;
+
+    for (entity, mut caret, mut position, mut visible, focusable) in &mut carets {
+        let active = editor.is_active() && !focusable.is_some_and(|_| !focused.is_focused(entity));
+        let (x, y) = (caret.origin.0 + column as i32, caret.origin.1);
+
+        // Blinking isn't drawn, so it doesn't count as a change
+        let blink = caret.blink;
+        let state = caret.bypass_change_detection();
+        let moved = position.x != x || position.y != y;
+        if moved || editor.is_changed() || !active {
+            // Stay on while typing, so the caret is easy to follow
+            state.on = true;
+            state.elapsed = Duration::ZERO;
+        } else if let Some(blink) = blink {
+            state.elapsed += time.delta();
+            if state.elapsed >= blink {
+                state.on = !state.on;
+                state.elapsed = Duration::ZERO;
+            }
+        }
+        let on = active && state.on;
+
+        if caret.grapheme != grapheme {
+            caret.grapheme = grapheme.clone();
+        }
+        if moved {
+            position.x = x;
+            position.y = y;
+        }
+        let shown = on && caret.style == CaretStyle::Inverted;
+        if visible.is_visible != shown {
+            visible.is_visible = shown;
+        }
+
+        if active && caret.style == CaretStyle::Cursor {
+            cursor_wanted = true;
+            cursor.x = x - camera.x;
+            cursor.y = y - camera.y;
+            cursor.hidden = !on;
+        }
+    }
+
+    // Hide the cursor again once no caret is using it
+    if *showing_cursor && !cursor_wanted {
+        cursor.hidden = true;
+    }
+    *showing_cursor = cursor_wanted;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn altgr_characters_are_typed() {
+        let mut editor = LineEditor::default();
+        editor.set_text("ab");
+        let altgr = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        editor.handle_key(&KeyEvent::new(KeyCode::Char('@'), altgr));
+        editor.handle_key(&KeyEvent::new(KeyCode::Char('b'), altgr));
+        assert_eq!(editor.text(), "ab@b");
+
+        // Ctrl on its own is still a shortcut
+        editor.handle_key(&KeyEvent::new(KeyCode::Char('a'), KeyModifiers::CONTROL));
+        assert_eq!(editor.cursor_column(), 0);
+    }
+}
@@ -0,0 +1,274 @@
+//! Rendering Markdown into a colored sprite, so help screens and the like can be written in Markdown.
+//!
+//! This covers the parts of Markdown that make sense in a terminal: headings, paragraphs, bulleted and numbered
+//! lists, fenced code blocks and horizontal rules, with `*emphasis*`, `**strong**` and `` `code` `` inside them.
+//! Anything else is shown as it's written.
+
+use crossterm::style::Attribute;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::components::{Color, ColoredSprite, Style};
+use crate::render::Cell;
+
+enum Block {
+    Heading(usize, String),
+    Paragraph(String),
+    // The marker, like "•" or "2.", how far the item is indented, and its text
+    Item(String, usize, String),
+    Code(Vec<String>),
+    Rule,
+}
+
+fn code_style() -> Style {
+    Style::with_fg(Color::DarkCyan)
+}
+
+/// Lay `text` out `width` columns wide
+pub(crate) fn render(text: &str, width: usize) -> ColoredSprite {
+    let width = width.max(1);
+    let mut rows: Vec<Vec<Cell>> = Vec::new();
+    let mut previous_was_item = false;
+
+    for block in parse(text) {
+        let is_item = matches!(block, Block::Item(..));
+        // Blocks are separated by a blank line, except for items of the same list
+        if !rows.is_empty() && !(is_item && previous_was_item) {
+            rows.push(Vec::new());
+        }
+        previous_was_item = is_item;
+
+        match block {
+            Block::Heading(level, text) => {
+                let mut style = Style::with_attrib(Attribute::Bold);
+                if level == 1 {
+                    style.attributes.set(Attribute::Underlined);
+                }
+                rows.extend(wrap(&inline(&text, style), width, &[], 0));
+            }
+            Block::Paragraph(text) => {
+                rows.extend(wrap(&inline(&text, Style::default()), width, &[], 0));
+            }
+            Block::Item(marker, indent, text) => {
+                let prefix = cells(
+                    &format!("{}{marker} ", " ".repeat(indent)),
+                    Style::default(),
+                );
+                let hanging = prefix.len();
+                rows.extend(wrap(
+                    &inline(&text, Style::default()),
+                    width,
+                    &prefix,
+                    hanging,
+                ));
+            }
+            Block::Code(lines) => {
+                for line in lines {
+                    rows.push(cells(&format!("  {line}"), code_style()));
+                }
+            }
+            Block::Rule => rows.push(cells(&"─".repeat(width), Style::default())),
+        }
+    }
+
+    ColoredSprite::new(Style::default(), rows)
+}
+
+fn parse(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<Vec<String>> = None;
+
+    let finish_paragraph = |paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>| {
+        if !paragraph.is_empty() {
+            blocks.push(Block::Paragraph(paragraph.join(" ")));
+            paragraph.clear();
+        }
+    };
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+
+        if let Some(lines) = &mut code {
+            if trimmed.starts_with("```") {
+                blocks.push(Block::Code(code.take().unwrap()));
+            } else {
+                lines.push(line.to_string());
+            }
+            continue;
+        }
+
+        if trimmed.starts_with("```") {
+            finish_paragraph(&mut paragraph, &mut blocks);
+            code = Some(Vec::new());
+        } else if trimmed.is_empty() {
+            finish_paragraph(&mut paragraph, &mut blocks);
+        } else if let Some(heading) = heading(trimmed) {
+            finish_paragraph(&mut paragraph, &mut blocks);
+            blocks.push(heading);
+        } else if is_rule(trimmed) {
+            finish_paragraph(&mut paragraph, &mut blocks);
+            blocks.push(Block::Rule);
+        } else if let Some(item) = item(line) {
+            finish_paragraph(&mut paragraph, &mut blocks);
+            blocks.push(item);
+        } else if let (true, Some(Block::Item(_, _, text))) =
+            (paragraph.is_empty(), blocks.last_mut())
+        {
+            // A line straight after a list item carries on the item
+            text.push(' ');
+            text.push_str(trimmed);
+        } else {
+            paragraph.push(trimmed);
+        }
+    }
+    finish_paragraph(&mut paragraph, &mut blocks);
+    // An unclosed code block runs to the end
+    if let Some(lines) = code {
+        blocks.push(Block::Code(lines));
+    }
+    blocks
+}
+
+fn heading(line: &str) -> Option<Block> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let text = line[level..].strip_prefix(' ')?;
+    (1..=6).contains(&level).then(|| {
+        Block::Heading(
+            level,
+            text.trim().trim_end_matches('#').trim_end().to_string(),
+        )
+    })
+}
+
+fn is_rule(line: &str) -> bool {
+    let line: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    line.len() >= 3
+        && ["-", "*", "_"]
+            .iter()
+            .any(|marker| line.chars().all(|c| c.to_string() == *marker))
+}
+
+fn item(line: &str) -> Option<Block> {
+    let indent = line.len() - line.trim_start().len();
+    let line = line.trim_start();
+    let (marker, text) = line.split_once(' ')?;
+    let marker = if ["-", "*", "+"].contains(&marker) {
+        "•".to_string()
+    } else {
+        let number = marker.strip_suffix(['.', ')'])?;
+        if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        format!("{number}.")
+    };
+    Some(Block::Item(marker, indent, text.trim().to_string()))
+}
+
+/// Turn inline markup into styled cells
+fn inline(text: &str, base: Style) -> Vec<Cell> {
+    let graphemes: Vec<&str> = text.graphemes(true).collect();
+    let mut result = Vec::new();
+    let (mut strong, mut emphasis, mut code) = (false, false, false);
+    let mut i = 0;
+
+    while i < graphemes.len() {
+        let grapheme = graphemes[i];
+        let next = graphemes.get(i + 1).copied();
+        let previous = i.checked_sub(1).map(|i| graphemes[i]);
+
+        if code {
+            if grapheme == "`" {
+                code = false;
+            } else {
+                result.push(cell(grapheme, code_style()));
+            }
+            i += 1;
+            continue;
+        }
+
+        match grapheme {
+            "\\" if next.is_some() => {
+                result.push(cell(next.unwrap(), style(base, strong, emphasis)));
+                i += 2;
+                continue;
+            }
+            "`" => code = true,
+            "*" | "_" if next == Some(grapheme) => {
+                strong = !strong;
+                i += 2;
+                continue;
+            }
+            // Underscores inside words, like in snake_case, are left alone
+            "_" if is_word(previous) && is_word(next) => {
+                result.push(cell(grapheme, style(base, strong, emphasis)));
+            }
+            "*" | "_" => emphasis = !emphasis,
+            _ => result.push(cell(grapheme, style(base, strong, emphasis))),
+        }
+        i += 1;
+    }
+    result
+}
+
+fn is_word(grapheme: Option<&str>) -> bool {
+    grapheme.is_some_and(|grapheme| grapheme.chars().all(char::is_alphanumeric))
+}
+
+fn style(base: Style, strong: bool, emphasis: bool) -> Style {
+    let mut style = base;
+    if strong {
+        style.attributes.set(Attribute::Bold);
+    }
+    if emphasis {
+        style.attributes.set(Attribute::Italic);
+    }
+    style
+}
+
+fn cell(grapheme: &str, style: Style) -> Cell {
+    Cell {
+        grapheme: grapheme.to_string(),
+        style,
+    }
+}
+
+fn cells(text: &str, style: Style) -> Vec<Cell> {
+    text.graphemes(true)
+        .map(|grapheme| cell(grapheme, style))
+        .collect()
+}
+
+/// Break styled text into rows at most `width` wide, breaking at spaces where it can. The first row starts with
+/// `prefix`, and the rest are indented by `indent`
+fn wrap(text: &[Cell], width: usize, prefix: &[Cell], indent: usize) -> Vec<Vec<Cell>> {
+    let indent = indent.min(width.saturating_sub(1));
+    let mut rows = Vec::new();
+    let mut row = prefix.to_vec();
+    let mut start = prefix.len();
+
+    for word in text.split(|cell| cell.grapheme == " ") {
+        if word.is_empty() {
+            continue;
+        }
+        let space = usize::from(row.len() > start);
+        if row.len() + space + word.len() > width && row.len() > start {
+            rows.push(std::mem::take(&mut row));
+            row = cells(&" ".repeat(indent), Style::default());
+            start = indent;
+        } else if space == 1 {
+            let style = row.last().map_or_else(Style::default, |cell| cell.style);
+            row.push(cell(" ", style));
+        }
+        // Words too long for a row of their own are broken wherever they have to be
+        for cell in word {
+            if row.len() >= width {
+                rows.push(std::mem::take(&mut row));
+                row = cells(&" ".repeat(indent), Style::default());
+                start = indent;
+            }
+            row.push(cell.clone());
+        }
+    }
+    rows.push(row);
+    rows
+}
@@ -0,0 +1,155 @@
+//! Small overview maps of the world.
+//!
+//! A [`Minimap`] condenses a colored sprite, usually one a [`RenderTarget`](crate::camera::RenderTarget) draws the
+//! world into, into a much smaller one. Each character of the minimap stands for a block of cells in the source,
+//! and shows the character and colors that block has the most of.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::components::{ColoredSprite, Colors, Style};
+use crate::render::Cell;
+
+/// Shows `source` shrunk down on the entity, one character for every `block_width` by `block_height` cells. The
+/// entity is given the `Handle<ColoredSprite>` it's drawn with, and needs a
+/// [`Position`](crate::components::Position) and [`Visible`](crate::components::Visible) like any sprite
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct Minimap {
+    pub source: Handle<ColoredSprite>,
+    pub block_width: u16,
+    pub block_height: u16,
+    /// How long to wait between updates. Zero updates the minimap every frame
+    pub interval: Duration,
+    since_update: Option<Duration>,
+}
+
+impl Minimap {
+    pub fn new(source: Handle<ColoredSprite>, block_width: u16, block_height: u16) -> Minimap {
+        Minimap {
+            source,
+            block_width,
+            block_height,
+            interval: Duration::ZERO,
+            since_update: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Update the minimap next frame, however long it's been since the last update
+    pub fn update_now(&mut self) {
+        self.since_update = None;
+    }
+}
+
+/// Shrink `source` down to one cell for every `block_width` by `block_height` cells. Each cell gets the grapheme
+/// most of its block has, in the foreground color that grapheme has most, on the block's most common background
+pub fn downsample(source: &ColoredSprite, block_width: u16, block_height: u16) -> ColoredSprite {
+    let (block_width, block_height) = (block_width.max(1) as usize, block_height.max(1) as usize);
+    let width = source.rows.iter().map(Vec::len).max().unwrap_or(0);
+    let height = source.rows.len();
+
+    let rows = (0..height.div_ceil(block_height))
+        .map(|row| {
+            (0..width.div_ceil(block_width))
+                .map(|column| {
+                    let cells: Vec<(&str, Style)> = (row * block_height
+                        ..((row + 1) * block_height).min(height))
+                        .flat_map(|y| {
+                            (column * block_width..((column + 1) * block_width).min(width))
+                                .map(move |x| cell_at(source, x, y))
+                        })
+                        .collect();
+                    majority_cell(&cells)
+                })
+                .collect()
+        })
+        .collect();
+    ColoredSprite::new(source.style, rows)
+}
+
+// The cell at `x`,`y`, with cells past the end of short rows blank in the sprite's style
+fn cell_at(sprite: &ColoredSprite, x: usize, y: usize) -> (&str, Style) {
+    sprite.rows[y].get(x).map_or((" ", sprite.style), |cell| {
+        (cell.grapheme.as_str(), cell.style)
+    })
+}
+
+fn majority_cell(cells: &[(&str, Style)]) -> Cell {
+    let grapheme = most_common(cells.iter().map(|(grapheme, _)| *grapheme)).unwrap_or(" ");
+    let foreground = most_common(
+        cells
+            .iter()
+            .filter(|(g, _)| *g == grapheme)
+            .map(|(_, style)| style.colors.foreground),
+    )
+    .flatten();
+    let background = most_common(cells.iter().map(|(_, style)| style.colors.background)).flatten();
+    Cell {
+        grapheme: grapheme.to_string(),
+        style: Style {
+            colors: Colors {
+                foreground,
+                background,
+            },
+            ..Default::default()
+        },
+    }
+}
+
+// The value that comes up most often, going with whichever came first on a tie. Blocks are small, so counting in a
+// list is quick enough
+fn most_common<T: PartialEq>(values: impl Iterator<Item = T>) -> Option<T> {
+    let mut counts: Vec<(T, usize)> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|(seen, _)| *seen == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+    let best = counts.iter().map(|(_, count)| *count).max()?;
+    counts
+        .into_iter()
+        .find(|(_, count)| *count == best)
+        .map(|(value, _)| value)
+}
+
+/// Shrinks the sources of minimaps into their sprites, each at its own rate
+pub(crate) fn update_minimaps(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut colored_sprites: ResMut<Assets<ColoredSprite>>,
+    mut minimaps: Query<(Entity, &mut Minimap, Option<&Handle<ColoredSprite>>)>,
+) {
+    for (entity, mut minimap, handle) in &mut minimaps {
+        // Timing isn't drawn, so it doesn't count as a change
+        let minimap = minimap.bypass_change_detection();
+        if let Some(since_update) = &mut minimap.since_update {
+            *since_update += time.delta();
+            if *since_update < minimap.interval {
+                continue;
+            }
+        }
+        let Some(source) = colored_sprites.get(&minimap.source) else {
+            continue;
+        };
+        minimap.since_update = Some(Duration::ZERO);
+        let sprite = downsample(source, minimap.block_width, minimap.block_height);
+
+        match handle {
+            Some(handle) => {
+                if colored_sprites.get(handle) != Some(&sprite) {
+                    colored_sprites.insert(handle, sprite);
+                }
+            }
+            None => {
+                commands.entity(entity).insert(colored_sprites.add(sprite));
+            }
+        }
+    }
+}
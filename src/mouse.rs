@@ -0,0 +1,134 @@
+//! Showing where the mouse is.
+//!
+//! The terminal's own pointer is drawn on top of the app without the app knowing about it, and many terminals hide
+//! it once the mouse is captured. Spawn a [`MouseCursorBundle`] to draw a cursor of the app's own instead, which
+//! follows the mouse above everything else.
+
+use bevy::prelude::*;
+
+use crate::camera::TerminalCamera;
+use crate::components::{BlendMode, Position, Style, Visible};
+use crate::render::{CellBuffer, TerminalRender};
+use crate::WorldMouseEvent;
+
+/// Drawn wherever the mouse was last seen in the viewport. It's hidden until the mouse first moves over the viewport,
+/// and only moves while the mouse is captured, see [`crate::CrosstermWindow::set_mouse_capture`]. Spawn it with
+/// [`MouseCursorBundle`]
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct MouseCursor {
+    grapheme: String,
+    style: Style,
+    // Where the mouse is, relative to the viewport, so the cursor stays put on screen while the camera moves
+    at: Option<(i32, i32)>,
+}
+
+impl Default for MouseCursor {
+    fn default() -> Self {
+        MouseCursor {
+            grapheme: "█".to_string(),
+            style: Style::default(),
+            at: None,
+        }
+    }
+}
+
+impl MouseCursor {
+    pub fn new(grapheme: &str, style: Style) -> MouseCursor {
+        MouseCursor {
+            grapheme: grapheme.to_string(),
+            style,
+            at: None,
+        }
+    }
+
+    pub fn grapheme(&self) -> &str {
+        &self.grapheme
+    }
+
+    pub fn set_grapheme(&mut self, grapheme: &str) -> &mut Self {
+        self.grapheme = grapheme.to_string();
+        self
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn set_style(&mut self, style: Style) -> &mut Self {
+        self.style = style;
+        self
+    }
+}
+
+impl TerminalRender for MouseCursor {
+    fn size(&self) -> (u16, u16) {
+        (1, 1)
+    }
+
+    fn render(&self, buffer: &mut CellBuffer) {
+        buffer.set(0, 0, &self.grapheme, self.style);
+    }
+}
+
+#[derive(Bundle, Debug)]
+pub struct MouseCursorBundle {
+    pub cursor: MouseCursor,
+    pub position: Position,
+    pub visible: Visible,
+    pub blend_mode: BlendMode,
+}
+
+impl Default for MouseCursorBundle {
+    /// Shows the cell under the mouse in reverse video
+    fn default() -> Self {
+        MouseCursorBundle {
+            cursor: MouseCursor::default(),
+            position: Position::new(0, 0, i32::MAX),
+            visible: Visible::invisible(),
+            blend_mode: BlendMode::Invert,
+        }
+    }
+}
+
+impl MouseCursorBundle {
+    /// A cursor drawn as `grapheme` in `style`, covering the cell under the mouse
+    pub fn new(grapheme: &str, style: Style) -> MouseCursorBundle {
+        MouseCursorBundle {
+            cursor: MouseCursor::new(grapheme, style),
+            blend_mode: BlendMode::Replace,
+            ..Default::default()
+        }
+    }
+}
+
+/// Moves mouse cursors to where the mouse is, above every other layer
+pub(crate) fn update_mouse_cursors(
+    mut mouse: EventReader<WorldMouseEvent>,
+    camera: Query<&TerminalCamera>,
+    mut cursors: Query<(&mut MouseCursor, &mut Position, &mut Visible)>,
+) {
+    let camera = camera.get_single().copied().unwrap_or_default();
+    let latest = mouse
+        .read()
+        .last()
+        .map(|event| camera.world_to_viewport(event.x, event.y));
+
+    for (mut cursor, mut position, mut visible) in &mut cursors {
+        if latest.is_some() && cursor.at != latest {
+            // Where it is isn't drawn, the position is
+            cursor.bypass_change_detection().at = latest;
+        }
+        let Some((x, y)) = cursor.at else {
+            continue;
+        };
+        let (x, y) = camera.viewport_to_world(x as u16, y as u16);
+        if position.x != x || position.y != y || position.z != i32::MAX {
+            position.x = x;
+            position.y = y;
+            position.z = i32::MAX;
+        }
+        if !visible.is_visible {
+            visible.is_visible = true;
+        }
+    }
+}
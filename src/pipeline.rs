@@ -0,0 +1,398 @@
+//! Pipelined rendering, where one frame is drawn and written to the terminal on a thread of its own while the next
+//! frame is simulated, like bevy's pipelined renderer.
+//!
+//! Once [`PipelinedRenderingPlugin`] is added, what it takes to draw the screen is copied into the
+//! [`CrosstermRenderApp`] sub-app at the end of every frame: the positions, visibility and blend modes of the
+//! sprites, the sprite and stylemap assets that changed, the camera, the theme and the window. The render app draws
+//! from those copies, so the main app can carry on changing positions and sprites for the next frame while it does.
+//! Only one frame is drawn at a time, the main app waits at the end of a frame if the last one isn't done yet.
+//!
+//! The [`RenderGraph`] moves into the render app when the app starts, and the screen is always composited there.
+//! Passes are run on the render app's world, which only has what's copied into it. Copy your own resources over
+//! every frame with [`ExtractResourcePlugin`]. Resources that come with passes, like [`PostProcessing`], move
+//! along with the graph, so set them up before the app runs.
+
+use std::marker::PhantomData;
+use std::sync::mpsc::{Receiver, SyncSender};
+use std::sync::Mutex;
+
+use bevy::app::{AppLabel, Main, SubApp};
+use bevy::ecs::event::ManualEventReader;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::backend::FrameInFlight;
+use crate::camera::TerminalCamera;
+use crate::components::{
+    BlendMode, EntitiesToRedraw, FrameOutput, Position, Sprite, StyleMap, Visible,
+};
+use crate::post_process::PostProcessing;
+use crate::render::CellBuffer;
+use crate::render_graph::{self, RenderGraph, RenderPass, SPRITES};
+use crate::theme::Theme;
+use crate::{CrosstermSet, CrosstermWindow};
+
+/// The sub-app that draws the screen with pipelined rendering, see the [module docs](self)
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, AppLabel)]
+pub struct CrosstermRenderApp;
+
+// Hands the render app over to the render thread and back
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, AppLabel)]
+struct RenderExtractApp;
+
+/// Draws the screen and writes it to the terminal on a thread of its own, see the [module docs](self)
+#[derive(Default)]
+pub struct PipelinedRenderingPlugin;
+
+impl Plugin for PipelinedRenderingPlugin {
+    fn build(&self, app: &mut App) {
+        let mut render_app = App::empty();
+        render_app.init_schedule(Main);
+        render_app
+            .init_resource::<EntitiesToRedraw>()
+            .init_resource::<FrameOutput>()
+            .init_resource::<ExtractedFrame>()
+            .init_resource::<ExtractState>()
+            .add_systems(Main, (render_graph::run_render_graph, write_frame).chain());
+
+        let mut extract_app = App::empty();
+        extract_app.init_schedule(Main);
+
+        app.init_resource::<PipelinedRendering>()
+            .add_systems(
+                PostUpdate,
+                mark_composite_point
+                    .in_set(CrosstermSet::Render)
+                    .after(render_graph::run_render_graph),
+            )
+            .insert_sub_app(CrosstermRenderApp, SubApp::new(render_app, extract));
+        app.insert_sub_app(RenderExtractApp, SubApp::new(extract_app, update_rendering));
+    }
+
+    fn cleanup(&self, app: &mut App) {
+        let Some(mut render_app) = app.remove_sub_app(CrosstermRenderApp) else {
+            return;
+        };
+
+        // The passes, and what they bring with them, have to be where they're run. The sprites are drawn from the
+        // copies in the render app
+        let mut graph = app
+            .world
+            .remove_resource::<RenderGraph>()
+            .unwrap_or_default();
+        graph
+            .replace_pass(SPRITES, ExtractedSpritePass)
+            .set_always_composite(true);
+        render_app.app.world.insert_resource(graph);
+        if let Some(post_processing) = app.world.remove_resource::<PostProcessing>() {
+            render_app.app.world.insert_resource(post_processing);
+        }
+
+        let (app_to_render, render_from_app) = std::sync::mpsc::sync_channel::<SubApp>(1);
+        let (render_to_app, app_from_render) = std::sync::mpsc::sync_channel::<SubApp>(1);
+        render_to_app.send(render_app).unwrap();
+        app.insert_resource(RenderAppChannels {
+            app_to_render,
+            app_from_render: Mutex::new(app_from_render),
+            in_render_thread: false,
+        });
+
+        std::thread::Builder::new()
+            .name("terminal render app".into())
+            .spawn(move || {
+                while let Ok(mut render_app) = render_from_app.recv() {
+                    render_app.run();
+                    if render_to_app.send(render_app).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Could not start the render thread");
+    }
+}
+
+/// Copies the resource `R` into the render app every frame it changes, for render passes to use with pipelined
+/// rendering. Add it after [`PipelinedRenderingPlugin`]
+pub struct ExtractResourcePlugin<R>(PhantomData<R>);
+
+impl<R> Default for ExtractResourcePlugin<R> {
+    fn default() -> Self {
+        ExtractResourcePlugin(PhantomData)
+    }
+}
+
+impl<R: Resource + Clone> Plugin for ExtractResourcePlugin<R> {
+    fn build(&self, app: &mut App) {
+        if let Ok(render_app) = app.get_sub_app_mut(CrosstermRenderApp) {
+            render_app
+                .world
+                .resource_mut::<ExtractState>()
+                .resources
+                .push(extract_resource::<R>);
+        }
+    }
+}
+
+fn extract_resource<R: Resource + Clone>(main_world: &World, render_world: &mut World) {
+    if render_world.contains_resource::<R>() && !main_world.is_resource_changed::<R>() {
+        return;
+    }
+    match main_world.get_resource::<R>() {
+        Some(resource) => render_world.insert_resource(resource.clone()),
+        None => {
+            render_world.remove_resource::<R>();
+        }
+    }
+}
+
+/// Where the output queued in the main app this frame is split, to put the composited screen in between
+#[derive(Resource, Default)]
+pub(crate) struct PipelinedRendering {
+    composite_at: usize,
+}
+
+/// Everything queued up to this point is written before the screen, and everything after, like
+/// [`TerminalPostRender`](crate::TerminalPostRender) output and the cursor, is written after it
+fn mark_composite_point(output: Res<FrameOutput>, mut pipelined: ResMut<PipelinedRendering>) {
+    pipelined.composite_at = output.0.len();
+}
+
+#[derive(Resource)]
+struct RenderAppChannels {
+    app_to_render: SyncSender<SubApp>,
+    app_from_render: Mutex<Receiver<SubApp>>,
+    in_render_thread: bool,
+}
+
+impl RenderAppChannels {
+    fn recv(&mut self) -> SubApp {
+        let render_app = self
+            .app_from_render
+            .get_mut()
+            .unwrap()
+            .recv()
+            .expect("The render thread stopped");
+        self.in_render_thread = false;
+        render_app
+    }
+
+    fn send(&mut self, render_app: SubApp) {
+        self.app_to_render.send(render_app).unwrap();
+        self.in_render_thread = true;
+    }
+}
+
+impl Drop for RenderAppChannels {
+    fn drop(&mut self) {
+        // Let the last frame finish before the app goes away
+        if self.in_render_thread {
+            let _ = self.app_from_render.get_mut().unwrap().recv();
+        }
+    }
+}
+
+/// Waits for the render app to finish the last frame, then copies this frame into it and sends it off to draw
+fn update_rendering(main_world: &mut World, _extract_app: &mut App) {
+    if !main_world.contains_resource::<RenderAppChannels>() {
+        return;
+    }
+    main_world.resource_scope(|world, mut channels: Mut<RenderAppChannels>| {
+        let mut render_app = channels.recv();
+        render_app.extract(world);
+        channels.send(render_app);
+    });
+}
+
+// A sprite as it was at the end of the frame being drawn
+struct ExtractedSprite {
+    position: Position,
+    visible: Visible,
+    sprite: AssetId<Sprite>,
+    stylemap: AssetId<StyleMap>,
+    blend_mode: BlendMode,
+}
+
+/// The frame the render app is drawing
+#[derive(Resource, Default)]
+struct ExtractedFrame {
+    sprites: Vec<ExtractedSprite>,
+    sprite_assets: HashMap<AssetId<Sprite>, Sprite>,
+    stylemap_assets: HashMap<AssetId<StyleMap>, StyleMap>,
+    camera: TerminalCamera,
+    theme: Theme,
+    // Output from the main app that goes after the composited screen
+    after: Vec<u8>,
+    in_flight: Option<FrameInFlight>,
+}
+
+type ExtractResourceFn = fn(&World, &mut World);
+
+/// What the render app keeps between frames to copy them over
+#[allow(clippy::type_complexity)]
+#[derive(Resource, Default)]
+struct ExtractState {
+    sprites: Option<
+        QueryState<(
+            &'static Position,
+            &'static Visible,
+            &'static Handle<Sprite>,
+            &'static Handle<StyleMap>,
+            Option<&'static BlendMode>,
+        )>,
+    >,
+    sprite_events: ManualEventReader<AssetEvent<Sprite>>,
+    stylemap_events: ManualEventReader<AssetEvent<StyleMap>>,
+    // Whether the assets have been copied over at least once
+    started: bool,
+    resources: Vec<ExtractResourceFn>,
+}
+
+/// Copy what's needed to draw the frame from the main app into the render app
+fn extract(main_world: &mut World, render_app: &mut App) {
+    let render_world = &mut render_app.world;
+    let Ok(window) = main_world
+        .query::<&CrosstermWindow>()
+        .get_single(main_world)
+    else {
+        return;
+    };
+    let window = window.detached();
+    let in_flight = window.backend.begin_frame();
+    let old_windows: Vec<Entity> = render_world
+        .query_filtered::<Entity, With<CrosstermWindow>>()
+        .iter(render_world)
+        .collect();
+    for entity in old_windows {
+        render_world.despawn(entity);
+    }
+    render_world.spawn(window);
+
+    render_world.resource_mut::<EntitiesToRedraw>().full_redraw =
+        main_world.resource::<EntitiesToRedraw>().full_redraw;
+    let composite_at =
+        std::mem::take(&mut main_world.resource_mut::<PipelinedRendering>().composite_at);
+    let mut output = std::mem::take(&mut main_world.resource_mut::<FrameOutput>().0);
+    let after = output.split_off(composite_at.min(output.len()));
+    render_world.resource_mut::<FrameOutput>().0.extend(output);
+
+    render_world.resource_scope(|render_world, mut state: Mut<ExtractState>| {
+        let state = &mut *state;
+        let mut frame = render_world.resource_mut::<ExtractedFrame>();
+        frame.after = after;
+        frame.in_flight = Some(in_flight);
+        frame.camera = main_world
+            .query::<&TerminalCamera>()
+            .get_single(main_world)
+            .copied()
+            .unwrap_or_default();
+        frame.theme = main_world.resource::<Theme>().clone();
+
+        extract_assets(
+            main_world,
+            &mut state.sprite_events,
+            &mut frame.sprite_assets,
+            state.started,
+        );
+        extract_assets(
+            main_world,
+            &mut state.stylemap_events,
+            &mut frame.stylemap_assets,
+            state.started,
+        );
+        state.started = true;
+
+        let query = state.sprites.get_or_insert_with(|| main_world.query());
+        frame.sprites.clear();
+        frame.sprites.extend(
+            query
+                .iter(main_world)
+                .filter(|(_, visible, ..)| visible.is_visible)
+                .map(
+                    |(position, visible, sprite, stylemap, blend_mode)| ExtractedSprite {
+                        position: position.clone(),
+                        visible: visible.clone(),
+                        sprite: sprite.id(),
+                        stylemap: stylemap.id(),
+                        blend_mode: blend_mode.copied().unwrap_or_default(),
+                    },
+                ),
+        );
+
+        for extract in &state.resources {
+            extract(main_world, render_world);
+        }
+    });
+}
+
+/// Bring the render app's copies of the assets up to date with the ones that changed
+fn extract_assets<A: Asset + Clone>(
+    main_world: &World,
+    events: &mut ManualEventReader<AssetEvent<A>>,
+    copies: &mut HashMap<AssetId<A>, A>,
+    started: bool,
+) {
+    let assets = main_world.resource::<Assets<A>>();
+    if !started {
+        copies.extend(assets.iter().map(|(id, asset)| (id, asset.clone())));
+    }
+    for event in events.read(main_world.resource::<Events<AssetEvent<A>>>()) {
+        match *event {
+            AssetEvent::Added { id }
+            | AssetEvent::Modified { id }
+            | AssetEvent::LoadedWithDependencies { id } => {
+                if let Some(asset) = assets.get(id) {
+                    copies.insert(id, asset.clone());
+                }
+            }
+            AssetEvent::Removed { id } => {
+                copies.remove(&id);
+            }
+            AssetEvent::Unused { .. } => {}
+        }
+    }
+}
+
+/// Draws the sprites from the render app's copies of them
+struct ExtractedSpritePass;
+
+impl RenderPass for ExtractedSpritePass {
+    fn run(&mut self, world: &mut World, buffer: &mut CellBuffer) {
+        let mut frame = world.resource_mut::<ExtractedFrame>();
+        frame.sprites.sort_by_key(|sprite| sprite.position.z);
+        for sprite in &frame.sprites {
+            if let (Some(asset), Some(stylemap)) = (
+                frame.sprite_assets.get(&sprite.sprite),
+                frame.stylemap_assets.get(&sprite.stylemap),
+            ) {
+                render_graph::draw_sprite(
+                    buffer,
+                    &frame.camera,
+                    &frame.theme,
+                    &sprite.position,
+                    &sprite.visible,
+                    asset,
+                    stylemap,
+                    sprite.blend_mode,
+                );
+            }
+        }
+    }
+}
+
+/// Write the frame's output, the screen in between what came before and after it in the main app
+fn write_frame(
+    mut output: ResMut<FrameOutput>,
+    mut frame: ResMut<ExtractedFrame>,
+    window: Query<&CrosstermWindow>,
+) {
+    let after = std::mem::take(&mut frame.after);
+    output.0.extend(after);
+    if let (Ok(window), false) = (window.get_single(), output.0.is_empty()) {
+        if let Err(error) = window.backend.write_frame(&output.0) {
+            warn!("Could not write the frame to the terminal: {error}");
+        }
+    }
+    output.0.clear();
+    // Anything waiting for this frame to get to the terminal can go ahead now
+    frame.in_flight = None;
+}
@@ -0,0 +1,231 @@
+//! Effects applied to the whole screen once everything has been drawn, like scanlines or a vignette.
+//!
+//! Add [`PostProcessPlugin`], then add effects to the [`PostProcessing`] resource. They run in the order they were
+//! added, as the last pass of the [`RenderGraph`], so the screen is composited while the plugin is in use. Effects
+//! only change colors that have an RGB value, so text in the terminal's default colors is left alone.
+
+use bevy::prelude::*;
+
+use crate::color::{color_to_rgb, mix};
+use crate::components::{Color, Style};
+use crate::render::CellBuffer;
+use crate::render_graph::{RenderGraph, RenderPass};
+
+/// The name of the render pass that applies the [`PostProcessing`] effects
+pub const POST_PROCESS: &str = "post_process";
+
+/// Something done to the finished screen. Closures taking a `&mut CellBuffer` are effects too
+pub trait PostEffect: Send + Sync + 'static {
+    /// Change `buffer`, which holds the whole viewport
+    fn apply(&mut self, buffer: &mut CellBuffer);
+}
+
+impl<F: FnMut(&mut CellBuffer) + Send + Sync + 'static> PostEffect for F {
+    fn apply(&mut self, buffer: &mut CellBuffer) {
+        self(buffer)
+    }
+}
+
+/// The effects applied to the screen, in order, e.g. `post_processing.add_effect(Scanlines::default())`
+#[derive(Resource, Default)]
+pub struct PostProcessing {
+    effects: Vec<Box<dyn PostEffect>>,
+}
+
+impl PostProcessing {
+    pub fn add_effect(&mut self, effect: impl PostEffect) -> &mut Self {
+        self.effects.push(Box::new(effect));
+        self
+    }
+
+    /// Remove every effect
+    pub fn clear(&mut self) -> &mut Self {
+        self.effects.clear();
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+}
+
+/// Adds [`PostProcessing`] and the [`POST_PROCESS`] pass at the end of the render graph
+#[derive(Default)]
+pub struct PostProcessPlugin;
+
+impl Plugin for PostProcessPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PostProcessing>()
+            .init_resource::<RenderGraph>();
+        app.world
+            .resource_mut::<RenderGraph>()
+            .add_pass(POST_PROCESS, PostProcessPass);
+    }
+}
+
+struct PostProcessPass;
+
+impl RenderPass for PostProcessPass {
+    fn run(&mut self, world: &mut World, buffer: &mut CellBuffer) {
+        let Some(mut post_processing) = world.get_resource_mut::<PostProcessing>() else {
+            return;
+        };
+        for effect in &mut post_processing.effects {
+            effect.apply(buffer);
+        }
+    }
+}
+
+// Change the RGB value of every color in `style` that has one
+fn map_colors(style: &mut Style, mut f: impl FnMut((u8, u8, u8)) -> (u8, u8, u8)) {
+    let mut map = |color: &mut Option<Color>| {
+        if let Some(rgb) = color.and_then(color_to_rgb) {
+            let (r, g, b) = f(rgb);
+            *color = Some(Color::Rgb { r, g, b });
+        }
+    };
+    map(&mut style.colors.foreground);
+    map(&mut style.colors.background);
+    map(&mut style.underline_color);
+}
+
+// Move every color in `style` `amount` of the way to black
+fn darken(style: &mut Style, amount: f32) {
+    let keep = 1.0 - amount.clamp(0.0, 1.0);
+    let channel = |channel: u8| (channel as f32 * keep).round() as u8;
+    map_colors(style, |(r, g, b)| (channel(r), channel(g), channel(b)));
+}
+
+/// Takes the color out of the screen
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Grayscale;
+
+impl PostEffect for Grayscale {
+    fn apply(&mut self, buffer: &mut CellBuffer) {
+        buffer.for_each_mut(|_, _, cell| {
+            map_colors(&mut cell.style, |(r, g, b)| {
+                let luma = (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32).round() as u8;
+                (luma, luma, luma)
+            });
+        });
+    }
+}
+
+/// Darkens every few rows, like the lines on an old CRT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scanlines {
+    /// How many rows apart the dark rows are
+    pub spacing: u16,
+    /// How much darker the dark rows are, from 0 to 1
+    pub strength: f32,
+}
+
+impl Default for Scanlines {
+    fn default() -> Self {
+        Scanlines {
+            spacing: 2,
+            strength: 0.3,
+        }
+    }
+}
+
+impl PostEffect for Scanlines {
+    fn apply(&mut self, buffer: &mut CellBuffer) {
+        let spacing = self.spacing.max(1);
+        buffer.for_each_mut(|_, y, cell| {
+            if y % spacing == spacing - 1 {
+                darken(&mut cell.style, self.strength);
+            }
+        });
+    }
+}
+
+/// Darkens the screen towards its edges and corners, like the curved glass of a CRT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vignette {
+    /// How dark the corners get, from 0 to 1
+    pub strength: f32,
+    /// How far from the middle the darkening starts, from 0 at the middle to 1 at the corners
+    pub radius: f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Vignette {
+            strength: 0.6,
+            radius: 0.5,
+        }
+    }
+}
+
+impl PostEffect for Vignette {
+    fn apply(&mut self, buffer: &mut CellBuffer) {
+        let half_width = buffer.width() as f32 / 2.0;
+        let half_height = buffer.height() as f32 / 2.0;
+        let radius = self.radius.clamp(0.0, 0.99);
+        buffer.for_each_mut(|x, y, cell| {
+            // How far the middle of the cell is from the middle of the screen, where the corners are 1
+            let dx = (x as f32 + 0.5 - half_width) / half_width.max(1.0);
+            let dy = (y as f32 + 0.5 - half_height) / half_height.max(1.0);
+            let distance = ((dx * dx + dy * dy) / 2.0).sqrt();
+            let amount = ((distance - radius) / (1.0 - radius)).clamp(0.0, 1.0);
+            darken(&mut cell.style, amount * self.strength);
+        });
+    }
+}
+
+/// Adjusts the colors of the whole screen, e.g. to make it look warmer, or washed out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGrade {
+    /// Multiplies every channel. Above 1 is brighter
+    pub brightness: f32,
+    /// Pushes channels away from the middle. Above 1 has more contrast, below 1 less
+    pub contrast: f32,
+    /// 0 is gray, 1 leaves colors as they are and above 1 makes them more vivid
+    pub saturation: f32,
+    /// A color to mix in, and how much of it, from 0 to 1
+    pub tint: Option<(Color, f32)>,
+}
+
+impl Default for ColorGrade {
+    fn default() -> Self {
+        ColorGrade {
+            brightness: 1.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            tint: None,
+        }
+    }
+}
+
+impl ColorGrade {
+    fn grade(&self, (r, g, b): (u8, u8, u8)) -> (u8, u8, u8) {
+        let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+        let channel = |channel: u8| {
+            let channel = luma + (channel as f32 - luma) * self.saturation;
+            let channel = (channel - 127.5) * self.contrast + 127.5;
+            (channel * self.brightness).round().clamp(0.0, 255.0) as u8
+        };
+        let graded = Color::Rgb {
+            r: channel(r),
+            g: channel(g),
+            b: channel(b),
+        };
+        let graded = match self.tint {
+            Some((tint, amount)) => mix(graded, tint, amount),
+            None => graded,
+        };
+        color_to_rgb(graded).unwrap_or((r, g, b))
+    }
+}
+
+impl PostEffect for ColorGrade {
+    fn apply(&mut self, buffer: &mut CellBuffer) {
+        let grade = *self;
+        buffer.for_each_mut(|_, _, cell| map_colors(&mut cell.style, |rgb| grade.grade(rgb)));
+    }
+}
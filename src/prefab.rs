@@ -0,0 +1,249 @@
+//! Groups of sprites authored as one asset, like a ship with turrets or a screen of UI.
+//!
+//! A prefab is a RON file with a `.prefab` extension, listing its parts. Each part is a sprite, from a file or
+//! written inline, with an optional stylemap and its place relative to the prefab:
+//!
+//! ```ron
+//! (
+//!     parts: [
+//!         (sprite: File("ship/hull.txt"), stylemap: File("ship/hull.stylemap")),
+//!         (name: "turret", sprite: Text("<o>"), x: 2, y: -1, z: 1, transparent: true),
+//!     ],
+//! )
+//! ```
+//!
+//! Paths are asset paths. Spawn a prefab with [`SpawnPrefabExt::spawn_prefab`]: each part becomes a child of the
+//! spawned entity, and follows it around as it moves.
+
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use bevy_asset::io::Reader;
+use bevy_asset::{AssetLoader, AsyncReadExt, LoadContext};
+use ron::extensions::Extensions;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::components::{Position, Sprite, SpriteBundle, StyleMap, Visible};
+use crate::layout::{Length, RelativePosition};
+
+/// Sprites that are spawned together, see the [module docs](self)
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct Prefab {
+    pub parts: Vec<PrefabPart>,
+}
+
+/// One sprite of a [`Prefab`]
+#[derive(Debug, Clone)]
+pub struct PrefabPart {
+    pub name: Option<String>,
+    pub sprite: Handle<Sprite>,
+    pub stylemap: Handle<StyleMap>,
+    /// Where the part goes, relative to the entity the prefab is spawned on. The z is added to that entity's
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub visible: Visible,
+}
+
+/// Where a part's sprite comes from
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PrefabSprite {
+    /// A sprite file, as an asset path
+    File(String),
+    /// The sprite's text itself
+    Text(String),
+}
+
+/// Where a part's stylemap comes from
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PrefabStyleMap {
+    /// A stylemap file, as an asset path
+    File(String),
+    Inline(StyleMap),
+}
+
+/// A part as it's written in a prefab file
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct PrefabPartFile {
+    #[serde(default)]
+    name: Option<String>,
+    sprite: PrefabSprite,
+    #[serde(default)]
+    stylemap: Option<PrefabStyleMap>,
+    #[serde(default)]
+    x: i32,
+    #[serde(default)]
+    y: i32,
+    #[serde(default)]
+    z: i32,
+    #[serde(default)]
+    transparent: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct PrefabFile {
+    parts: Vec<PrefabPartFile>,
+}
+
+#[derive(Error, Debug)]
+pub enum LoadPrefabError {
+    #[error("error deserializing prefab from ron data at {}:{line}:{column}: {error}", path.display())]
+    Deserialize {
+        path: std::path::PathBuf,
+        line: usize,
+        column: usize,
+        error: ron::error::Error,
+    },
+    #[error("io error")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Default)]
+pub struct PrefabLoader;
+
+impl AssetLoader for PrefabLoader {
+    type Asset = Prefab;
+    type Settings = ();
+    type Error = LoadPrefabError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, LoadPrefabError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            // So names can be written without `Some(..)`
+            let options = ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+            let file = options.from_bytes::<PrefabFile>(&bytes).map_err(|err| {
+                LoadPrefabError::Deserialize {
+                    path: load_context.path().to_path_buf(),
+                    line: err.position.line,
+                    column: err.position.col,
+                    error: err.code,
+                }
+            })?;
+
+            // Inline sprites and stylemaps are sub-assets of the prefab, labelled by the part they're for
+            let parts = file
+                .parts
+                .into_iter()
+                .enumerate()
+                .map(|(i, part)| {
+                    let sprite = match part.sprite {
+                        PrefabSprite::File(path) => load_context.load(path),
+                        PrefabSprite::Text(text) => {
+                            load_context.add_labeled_asset(format!("part{i}"), Sprite::new(text))
+                        }
+                    };
+                    let stylemap = match part.stylemap {
+                        Some(PrefabStyleMap::File(path)) => load_context.load(path),
+                        Some(PrefabStyleMap::Inline(stylemap)) => {
+                            load_context.add_labeled_asset(format!("part{i}.stylemap"), stylemap)
+                        }
+                        None => load_context
+                            .add_labeled_asset(format!("part{i}.stylemap"), StyleMap::default()),
+                    };
+                    PrefabPart {
+                        name: part.name,
+                        sprite,
+                        stylemap,
+                        x: part.x,
+                        y: part.y,
+                        z: part.z,
+                        visible: if part.transparent {
+                            Visible::transparent()
+                        } else {
+                            Visible::default()
+                        },
+                    }
+                })
+                .collect();
+            Ok(Prefab { parts })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["prefab"]
+    }
+}
+
+/// Spawns the parts of a prefab as children of the entity, once the prefab has loaded. Added by
+/// [`SpawnPrefabExt::spawn_prefab`]
+#[derive(Component, Debug, Clone)]
+pub struct PrefabInstance {
+    prefab: Handle<Prefab>,
+    spawned: bool,
+}
+
+impl PrefabInstance {
+    pub fn new(prefab: Handle<Prefab>) -> PrefabInstance {
+        PrefabInstance {
+            prefab,
+            spawned: false,
+        }
+    }
+
+    pub fn prefab(&self) -> &Handle<Prefab> {
+        &self.prefab
+    }
+
+    /// Whether the parts have been spawned yet
+    pub fn is_spawned(&self) -> bool {
+        self.spawned
+    }
+}
+
+pub trait SpawnPrefabExt {
+    /// Spawn an entity for the prefab at 0,0. Insert a [`Position`] to put it somewhere else, and move it to move
+    /// every part
+    fn spawn_prefab(&mut self, prefab: Handle<Prefab>) -> EntityCommands<'_>;
+}
+
+impl SpawnPrefabExt for Commands<'_, '_> {
+    fn spawn_prefab(&mut self, prefab: Handle<Prefab>) -> EntityCommands<'_> {
+        self.spawn((PrefabInstance::new(prefab), Position::default()))
+    }
+}
+
+/// Spawns the parts of prefabs that have loaded
+pub(crate) fn spawn_prefabs(
+    mut commands: Commands,
+    prefabs: Res<Assets<Prefab>>,
+    mut instances: Query<(Entity, &mut PrefabInstance, &Position)>,
+) {
+    for (entity, mut instance, position) in &mut instances {
+        if instance.spawned {
+            continue;
+        }
+        let Some(prefab) = prefabs.get(&instance.prefab) else {
+            continue;
+        };
+        instance.spawned = true;
+
+        commands.entity(entity).with_children(|parent| {
+            for part in &prefab.parts {
+                let mut child = parent.spawn((
+                    SpriteBundle {
+                        sprite: part.sprite.clone(),
+                        stylemap: part.stylemap.clone(),
+                        position: Position::new(
+                            position.x + part.x,
+                            position.y + part.y,
+                            position.z + part.z,
+                        ),
+                        visible: part.visible.clone(),
+                    },
+                    // Keeps the part in place as the prefab moves
+                    RelativePosition::new(Length::Cells(part.x), Length::Cells(part.y)),
+                ));
+                if let Some(name) = &part.name {
+                    child.insert(Name::new(name.clone()));
+                }
+            }
+        });
+    }
+}
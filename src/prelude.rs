@@ -1,7 +1,62 @@
-pub use crate::{CrosstermPlugin, CrosstermWindow, CrosstermWindowSettings, Cursor};
+pub use crate::{
+    AppExitError, ColorChoice, CrosstermDefaultPlugins, CrosstermPlugin, CrosstermSet,
+    CrosstermWindow, CrosstermWindowSettings, Cursor, ForceRedraw, IdlePacing, TerminalCommands,
+    TerminalPostRender, UnhandledKeySequence, Viewport, WorldMouseEvent,
+};
+
+pub use crate::animation::{
+    AnimationController, AnimationFinished, AnimationFrameReached, ColorAnimation, ColorEffect,
+    ColorTarget, PaletteCycle, SpriteAnimation,
+};
+#[cfg(feature = "syntect")]
+pub use crate::asset_loaders::SyntaxLoaderSettings;
+pub use crate::asset_loaders::{
+    Align, ControlCharacters, MarkdownLoaderSettings, SpriteLoaderSettings, StyleMapLoaderSettings,
+    Tabs,
+};
+pub use crate::backend::{CrosstermBackend, SelectedBackend, TerminalBackend, TestBackend};
+pub use crate::camera::{RenderTarget, RenderTargetRefresh, TerminalCamera};
+pub use crate::capabilities::TerminalCapabilities;
+pub use crate::capture::{Screen, ScreenCapture, ScreenCapturePlugin};
+pub use crate::color::{ColorDepth, Dither};
+pub use crate::config::{ThemeConfig, WindowConfig};
+pub use crate::debug::{
+    DebugAction, DebugKeys, DebugOverlay, FpsCounter, FpsCounterBundle, FrameStepper,
+};
+pub use crate::focus::{FocusGained, FocusLost, FocusNavigation, Focusable, FocusedEntity};
+pub use crate::headless::Headless;
+#[cfg(feature = "image")]
+pub use crate::image::{ImageMode, ImageSprite};
+pub use crate::input::{
+    on_any_key, on_key_press, on_mouse_click, on_resize, ActionState, InputBinding, InputMap,
+    InputMapPlugin, KeyChord, KeyModifiersState, KeySequenceEvent, KeySequences,
+};
+pub use crate::layout::{Anchor, Anchored, LayoutSettings, Length, RelativePosition, Size};
+pub use crate::line_editor::{Caret, CaretBundle, CaretStyle, LineEditor, LineSubmitted};
+pub use crate::minimap::Minimap;
+pub use crate::mouse::{MouseCursor, MouseCursorBundle};
+pub use crate::pipeline::{CrosstermRenderApp, ExtractResourcePlugin, PipelinedRenderingPlugin};
+pub use crate::post_process::{
+    ColorGrade, Grayscale, PostEffect, PostProcessPlugin, PostProcessing, Scanlines, Vignette,
+};
+pub use crate::prefab::{Prefab, PrefabInstance, PrefabPart, SpawnPrefabExt};
+pub use crate::render::{Cell, CellBuffer, TerminalRender, TerminalRenderPlugin};
+pub use crate::render_graph::{RenderGraph, RenderPass};
+pub use crate::screen_stack::{on_top_screen, ScreenStack, StackedScreen};
+pub use crate::selection::{MouseSelection, SelectionHighlight, SelectionRect};
+pub use crate::state::{DespawnOnStateExit, DespawnOnStateExitPlugin};
+pub use crate::suspend::{ProcessExited, RawTerminalGuard, TerminalSuspend};
+pub use crate::text::{measure_text, Text, TextBundle, Wrap};
+pub use crate::theme::{Theme, ThemeColors};
+pub use crate::ttyrec::TtyrecBackend;
+pub use crate::widgets::{
+    Edge, NinePatch, NinePatchBundle, StatusBar, StatusBarBundle, TextLog, TextLogBundle, Toast,
+    ToastBundle, ToastSettings,
+};
 
 pub use crate::components::{
-    Color, Colors, Position, Sprite, SpriteBundle, Style, StyleMap, Visible,
+    BlendMode, Color, ColoredSprite, Colors, Corner, Position, SpanRange, Sprite, SpriteBundle,
+    Style, StyleMap, StyleMapBuilder, StyleSpan, Visible,
 };
 
 // Re-export crossterm structs for easier access
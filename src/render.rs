@@ -0,0 +1,217 @@
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::components::{Sprite, Style, StyleMap};
+
+/// A single terminal cell
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cell {
+    pub grapheme: String,
+    pub style: Style,
+}
+
+/// A rectangle of cells for a [`TerminalRender`] component to draw into. Coordinates are relative to the top left
+/// corner, and anything drawn outside of the rectangle is dropped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CellBuffer {
+    width: u16,
+    height: u16,
+    style: Style,
+    cells: Vec<Option<Cell>>,
+}
+
+impl CellBuffer {
+    pub fn new(width: u16, height: u16) -> CellBuffer {
+        CellBuffer {
+            width,
+            height,
+            style: Style::default(),
+            cells: vec![None; width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// The style of cells nothing was drawn in
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn set_style(&mut self, style: Style) -> &mut Self {
+        self.style = style;
+        self
+    }
+
+    pub fn get(&self, x: u16, y: u16) -> Option<&Cell> {
+        self.index(x, y).and_then(|i| self.cells[i].as_ref())
+    }
+
+    /// Put a single grapheme in a cell
+    pub fn set(&mut self, x: u16, y: u16, grapheme: &str, style: Style) -> &mut Self {
+        if let Some(i) = self.index(x, y) {
+            self.cells[i] = Some(Cell {
+                grapheme: grapheme.to_string(),
+                style,
+            });
+        }
+        self
+    }
+
+    /// Write a line of text starting at `x`,`y`, one grapheme per cell. Returns how many cells were written before
+    /// the edge of the buffer
+    pub fn print(&mut self, x: u16, y: u16, text: &str, style: Style) -> u16 {
+        let mut written = 0;
+        for (column, grapheme) in (x..self.width).zip(text.graphemes(true)) {
+            self.set(column, y, grapheme, style);
+            written += 1;
+        }
+        written
+    }
+
+    /// Change every cell in turn, given its position. Cells nothing was drawn in are filled with a blank in the
+    /// buffer's style first
+    pub fn for_each_mut(&mut self, mut f: impl FnMut(u16, u16, &mut Cell)) -> &mut Self {
+        let width = self.width.max(1) as usize;
+        for (i, cell) in self.cells.iter_mut().enumerate() {
+            let cell = cell.get_or_insert_with(|| Cell {
+                grapheme: " ".to_string(),
+                style: self.style,
+            });
+            f((i % width) as u16, (i / width) as u16, cell);
+        }
+        self
+    }
+
+    /// Empty every cell
+    pub fn clear(&mut self) -> &mut Self {
+        self.cells.fill(None);
+        self
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y as usize * self.width as usize + x as usize)
+    }
+
+    /// The sprite text and the style of every cell. Empty cells are blank, with the buffer's style
+    pub(crate) fn to_sprite(&self) -> (String, Vec<Vec<Style>>) {
+        let mut data = String::new();
+        let mut map = Vec::with_capacity(self.height as usize);
+        for (y, row) in self.cells.chunks(self.width.max(1) as usize).enumerate() {
+            if y > 0 {
+                data.push('\n');
+            }
+            let mut styles = Vec::with_capacity(row.len());
+            for cell in row {
+                match cell {
+                    Some(cell) => {
+                        data.push_str(&cell.grapheme);
+                        styles.push(Style {
+                            colors: cell.style.colors.with_default(self.style.colors),
+                            ..cell.style
+                        });
+                    }
+                    None => {
+                        data.push(' ');
+                        styles.push(self.style);
+                    }
+                }
+            }
+            map.push(styles);
+        }
+        (data, map)
+    }
+}
+
+/// A component that draws itself, instead of being given a sprite and stylemap.
+///
+/// Add [`TerminalRenderPlugin`] for each implementing type, then spawn the component along with a
+/// [`Position`](crate::components::Position) and [`Visible`](crate::components::Visible). It's drawn again whenever
+/// it changes, and otherwise behaves like any sprite: it's layered by its position's z and moves with the camera.
+pub trait TerminalRender: Component {
+    /// The size of the region to draw into, as (width, height)
+    fn size(&self) -> (u16, u16);
+
+    /// Draw into `buffer`, which starts out empty and has the size returned by [`TerminalRender::size`]
+    fn render(&self, buffer: &mut CellBuffer);
+}
+
+/// Draws components of type `T` with their [`TerminalRender`] implementation
+pub struct TerminalRenderPlugin<T>(PhantomData<T>);
+
+impl<T> Default for TerminalRenderPlugin<T> {
+    fn default() -> Self {
+        TerminalRenderPlugin(PhantomData)
+    }
+}
+
+impl<T: TerminalRender> Plugin for TerminalRenderPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            render_component::<T>.in_set(crate::CrosstermSet::Extract),
+        );
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn render_component<T: TerminalRender>(
+    mut commands: Commands,
+    mut sprites: ResMut<Assets<Sprite>>,
+    mut stylemaps: ResMut<Assets<StyleMap>>,
+    components: Query<
+        (
+            Entity,
+            &T,
+            Option<&Handle<Sprite>>,
+            Option<&Handle<StyleMap>>,
+        ),
+        Changed<T>,
+    >,
+) {
+    for (entity, component, sprite, stylemap) in &components {
+        let (width, height) = component.size();
+        let mut buffer = CellBuffer::new(width, height);
+        component.render(&mut buffer);
+        let (data, map) = buffer.to_sprite();
+        let new_stylemap = StyleMap::new(buffer.style(), map);
+
+        // Only touch the assets if something changed, since that gets the entity redrawn
+        match sprite {
+            Some(handle) => {
+                if sprites
+                    .get(handle)
+                    .is_some_and(|sprite| sprite.data() != data)
+                {
+                    sprites.get_mut(handle).unwrap().update(data);
+                }
+            }
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(sprites.add(Sprite::new(data)));
+            }
+        }
+        match stylemap {
+            Some(handle) => {
+                if stylemaps
+                    .get(handle)
+                    .is_some_and(|old| *old != new_stylemap)
+                {
+                    *stylemaps.get_mut(handle).unwrap() = new_stylemap;
+                }
+            }
+            None => {
+                commands.entity(entity).insert(stylemaps.add(new_stylemap));
+            }
+        }
+    }
+}
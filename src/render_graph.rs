@@ -0,0 +1,326 @@
+//! Drawing the screen as a series of passes over one buffer, so effects like lighting and post-processing can be
+//! layered on top of the sprites.
+//!
+//! Normally the plugin draws each sprite that changed straight to the terminal. Once the [`RenderGraph`] has a pass
+//! of its own, the whole viewport is composited into a [`CellBuffer`] instead. The built-in [`SPRITES`] pass draws
+//! every visible sprite, the other passes run in order over the result, and then only the cells that changed since
+//! the last frame are written to the terminal.
+
+use std::borrow::Cow;
+
+use bevy::prelude::*;
+use crossterm::QueueableCommand;
+
+use crate::camera::TerminalCamera;
+use crate::components::{
+    BlendMode, EntitiesToRedraw, FrameOutput, Position, Sprite, Style, StyleMap, Visible,
+};
+use crate::render::{Cell, CellBuffer};
+use crate::systems::{self, TerminalState};
+use crate::theme::Theme;
+use crate::CrosstermWindow;
+
+/// The name of the built-in pass that draws every visible sprite, from back to front
+pub const SPRITES: &str = "sprites";
+
+/// One step of drawing the screen. Closures taking `(&mut World, &mut CellBuffer)` are passes too
+pub trait RenderPass: Send + Sync + 'static {
+    /// Draw into `buffer`, which is the size of the viewport and holds whatever the passes before this one drew.
+    /// Cells nothing has drawn in are blank, in the window's colors
+    fn run(&mut self, world: &mut World, buffer: &mut CellBuffer);
+}
+
+impl<F: FnMut(&mut World, &mut CellBuffer) + Send + Sync + 'static> RenderPass for F {
+    fn run(&mut self, world: &mut World, buffer: &mut CellBuffer) {
+        self(world, buffer)
+    }
+}
+
+/// The passes that draw the screen, in the order they run. It starts out with just [`SPRITES`], e.g.
+/// `app.world.resource_mut::<RenderGraph>().add_pass_after(SPRITES, "lighting", lighting)`
+#[derive(Resource)]
+pub struct RenderGraph {
+    passes: Vec<(Cow<'static, str>, Box<dyn RenderPass>)>,
+    // What was written to the terminal last frame, to only write what changed
+    previous: Option<CellBuffer>,
+    // Composite even with just the sprites pass, for when nothing else draws them
+    always_composite: bool,
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        RenderGraph {
+            passes: vec![(SPRITES.into(), Box::<SpritePass>::default())],
+            previous: None,
+            always_composite: false,
+        }
+    }
+}
+
+impl RenderGraph {
+    /// Add a pass that runs after all the others
+    pub fn add_pass(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        pass: impl RenderPass,
+    ) -> &mut Self {
+        self.passes.push((name.into(), Box::new(pass)));
+        self
+    }
+
+    /// Add a pass that runs right before the pass called `before`. Panics if there isn't one
+    pub fn add_pass_before(
+        &mut self,
+        before: &str,
+        name: impl Into<Cow<'static, str>>,
+        pass: impl RenderPass,
+    ) -> &mut Self {
+        let index = self.index(before);
+        self.passes.insert(index, (name.into(), Box::new(pass)));
+        self
+    }
+
+    /// Add a pass that runs right after the pass called `after`. Panics if there isn't one
+    pub fn add_pass_after(
+        &mut self,
+        after: &str,
+        name: impl Into<Cow<'static, str>>,
+        pass: impl RenderPass,
+    ) -> &mut Self {
+        let index = self.index(after) + 1;
+        self.passes.insert(index, (name.into(), Box::new(pass)));
+        self
+    }
+
+    /// Take out the pass called `name`, if there is one
+    pub fn remove_pass(&mut self, name: &str) -> Option<Box<dyn RenderPass>> {
+        let index = self.passes.iter().position(|(pass, _)| pass == name)?;
+        Some(self.passes.remove(index).1)
+    }
+
+    /// The names of the passes, in the order they run
+    pub fn pass_names(&self) -> impl Iterator<Item = &str> {
+        self.passes.iter().map(|(name, _)| name.as_ref())
+    }
+
+    /// Whether the screen is composited by running the passes, rather than drawn sprite by sprite. This is the case
+    /// once the graph is anything other than the [`SPRITES`] pass on its own
+    pub fn is_compositing(&self) -> bool {
+        self.always_composite || !(self.passes.len() == 1 && self.passes[0].0 == SPRITES)
+    }
+
+    pub(crate) fn set_always_composite(&mut self, always: bool) -> &mut Self {
+        self.always_composite = always;
+        self
+    }
+
+    /// Swap the pass called `name` for `pass`, if there is one
+    pub(crate) fn replace_pass(&mut self, name: &str, pass: impl RenderPass) -> &mut Self {
+        if let Some((_, old)) = self.passes.iter_mut().find(|(pass, _)| pass == name) {
+            *old = Box::new(pass);
+        }
+        self
+    }
+
+    fn index(&self, name: &str) -> usize {
+        self.passes
+            .iter()
+            .position(|(pass, _)| pass == name)
+            .unwrap_or_else(|| panic!("There's no render pass called {name:?}"))
+    }
+}
+
+#[allow(clippy::type_complexity)]
+#[derive(Default)]
+struct SpritePass {
+    query: Option<
+        QueryState<(
+            &'static Position,
+            &'static Handle<StyleMap>,
+            &'static Visible,
+            &'static Handle<Sprite>,
+            Option<&'static BlendMode>,
+        )>,
+    >,
+}
+
+impl RenderPass for SpritePass {
+    fn run(&mut self, world: &mut World, buffer: &mut CellBuffer) {
+        let camera = world
+            .query::<&TerminalCamera>()
+            .get_single(world)
+            .copied()
+            .unwrap_or_default();
+        let query = self.query.get_or_insert_with(|| world.query());
+
+        let sprites = world.resource::<Assets<Sprite>>();
+        let stylemaps = world.resource::<Assets<StyleMap>>();
+        let theme = world.resource::<Theme>();
+        let mut entities: Vec<_> = query
+            .iter(world)
+            .filter(|(_, _, visible, ..)| visible.is_visible)
+            .collect();
+        entities.sort_by_key(|(position, ..)| position.z);
+
+        for (position, stylemap, visible, sprite, blend_mode) in entities {
+            if let (Some(sprite), Some(stylemap)) = (sprites.get(sprite), stylemaps.get(stylemap)) {
+                let blend_mode = blend_mode.copied().unwrap_or_default();
+                draw_sprite(
+                    buffer, &camera, theme, position, visible, sprite, stylemap, blend_mode,
+                );
+            }
+        }
+    }
+}
+
+/// Draw one sprite into `buffer`, as seen through `camera`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_sprite(
+    buffer: &mut CellBuffer,
+    camera: &TerminalCamera,
+    theme: &Theme,
+    position: &Position,
+    visible: &Visible,
+    sprite: &Sprite,
+    stylemap: &StyleMap,
+    blend_mode: BlendMode,
+) {
+    let stylemap = stylemap.resolve_pairs(theme);
+    let stylemap = stylemap.resolve_spans(sprite);
+    let base_style = stylemap.base_style(theme);
+    let base_style = Style {
+        colors: base_style.colors.with_default(buffer.style().colors),
+        ..base_style
+    };
+    let (left, top) = camera.world_to_viewport(position.x, position.y);
+
+    for (y, line) in sprite.graphemes().iter().enumerate() {
+        let row = top + y as i32;
+        if row < 0 || row >= buffer.height() as i32 {
+            continue;
+        }
+        for x in 0..sprite.width() {
+            let column = left + x as i32;
+            if column < 0 || column >= buffer.width() as i32 {
+                continue;
+            }
+            // Lines shorter than the sprite are padded out with spaces
+            let grapheme = line
+                .get(x)
+                .map_or(" ", |grapheme| sprite.grapheme(grapheme));
+            let style = stylemap.style_at(x, y).copied();
+            let hidden = sprite.is_masked(x, y).unwrap_or_else(|| {
+                visible.is_transparent
+                    && style.is_none()
+                    && (x >= line.len() || visible.is_transparent_char(grapheme))
+            });
+            if hidden {
+                continue;
+            }
+
+            let (column, row) = (column as u16, row as u16);
+            let (grapheme, style) = systems::blend(
+                blend_mode,
+                grapheme,
+                &style.unwrap_or(base_style),
+                &base_style,
+                buffer.get(column, row),
+            );
+            let grapheme = grapheme.to_string();
+            buffer.set(column, row, &grapheme, style);
+        }
+    }
+}
+
+/// Runs the render graph and writes whatever changed, if the graph is compositing the screen
+pub(crate) fn run_render_graph(world: &mut World) {
+    // With pipelined rendering, the graph runs in the render app instead
+    if !world.contains_resource::<RenderGraph>() {
+        return;
+    }
+    let Ok(window) = world.query::<&CrosstermWindow>().get_single(world) else {
+        return;
+    };
+    if window.is_too_small() {
+        return;
+    }
+    let view = window.viewport();
+    let colors = window.colors;
+    let full_redraw = world.resource::<EntitiesToRedraw>().full_redraw;
+
+    world.resource_scope(|world, mut graph: Mut<RenderGraph>| {
+        if !graph.is_compositing() {
+            graph.previous = None;
+            return;
+        }
+
+        let mut buffer = CellBuffer::new(view.width, view.height);
+        buffer.set_style(Style {
+            colors,
+            ..Default::default()
+        });
+        for (_, pass) in &mut graph.passes {
+            pass.run(world, &mut buffer);
+        }
+
+        // The whole screen was just cleared, so every cell has to be written again
+        let previous = graph.previous.take().filter(|previous| {
+            !full_redraw
+                && (previous.width(), previous.height()) == (buffer.width(), buffer.height())
+        });
+        let mut output = Vec::new();
+        let window = world.query::<&CrosstermWindow>().single(world);
+        write_changes(&mut output, window, previous.as_ref(), &buffer).unwrap();
+        world.resource_mut::<FrameOutput>().0.extend(output);
+        graph.previous = Some(buffer);
+    });
+}
+
+/// Write the cells of `buffer` that differ from `previous`, or all of them if there's nothing to compare with
+fn write_changes(
+    term: &mut Vec<u8>,
+    window: &CrosstermWindow,
+    previous: Option<&CellBuffer>,
+    buffer: &CellBuffer,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let view = window.viewport();
+    let blank = Cell {
+        grapheme: " ".to_string(),
+        style: buffer.style(),
+    };
+    let resolve = |cell: Option<&Cell>| {
+        let cell = cell.unwrap_or(&blank);
+        Cell {
+            grapheme: cell.grapheme.clone(),
+            style: Style {
+                colors: cell.style.colors.with_default(buffer.style().colors),
+                ..cell.style
+            },
+        }
+    };
+
+    let mut terminal = TerminalState::default();
+    for y in 0..buffer.height() {
+        for x in 0..buffer.width() {
+            let cell = resolve(buffer.get(x, y));
+            if previous.is_some_and(|previous| resolve(previous.get(x, y)) == cell) {
+                continue;
+            }
+
+            if terminal.style.is_none() {
+                term.queue(crossterm::cursor::Hide)?;
+            }
+            terminal.move_to(term, view.x + x, view.y + y)?;
+            match &mut terminal.style {
+                Some(style) => systems::change_style_if_needed(term, window, style, &cell.style)?,
+                None => {
+                    systems::set_style(term, window, &cell.style)?;
+                    terminal.style = Some(cell.style);
+                }
+            }
+            term.queue(crossterm::style::Print(&cell.grapheme))?;
+            terminal.printed(window.width, &cell.grapheme);
+        }
+    }
+    Ok(())
+}
@@ -1,156 +1,553 @@
+use crate::backend::{CrosstermBackend, SelectedBackend, SharedBackend, TerminalBackend};
+use crate::camera::TerminalCamera;
+use crate::capabilities::TerminalCapabilities;
+use crate::color::ColorDepth;
+use crate::components::EntitiesToRedraw;
+use crate::config::{ConfigError, WindowConfig};
+use crate::debug::FrameStepper;
+use crate::headless::Headless;
+use crate::input::KeyModifiersState;
+use crate::theme::Theme;
 use crate::{
-    CrosstermKeyEventWrapper, CrosstermMouseEventWrapper, CrosstermWindow, CrosstermWindowSettings,
+    AppExitError, CrosstermKeyEventWrapper, CrosstermMouseEventWrapper, CrosstermWindow,
+    CrosstermWindowSettings, ForceRedraw, UnhandledKeySequence, WorldMouseEvent,
 };
-use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use bevy::window::{PrimaryWindow, WindowCreated, WindowResized};
-use bevy_app::{App, AppExit};
+use bevy::time::TimeUpdateStrategy;
+use bevy::window::{PrimaryWindow, RequestRedraw, WindowCreated, WindowResized};
+use bevy_app::{App, AppExit, PluginsState};
 use bevy_ecs::entity::Entity;
-use bevy_ecs::event::Events;
+use bevy_ecs::event::{Events, ManualEventReader};
 use crossterm::{
-    event::{KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags},
-    queue, ExecutableCommand, QueueableCommand,
+    event::{PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags},
+    queue, QueueableCommand,
 };
 
 impl CrosstermWindow {
-    /// Creates a new `CrosstermWindow` and prepares crossterm for rendering.
-    fn new(settings: &CrosstermWindowSettings) -> Self {
-        crossterm::terminal::enable_raw_mode().expect("Could not enable crossterm raw mode");
+    /// Creates a new `CrosstermWindow` and prepares the terminal for rendering.
+    fn new(settings: &CrosstermWindowSettings, mut backend: Box<dyn TerminalBackend>) -> Self {
+        let raw_mode = settings.raw_mode;
+        if raw_mode {
+            backend
+                .enable_raw_mode()
+                .expect("Could not enable crossterm raw mode");
+        }
 
-        let mut term = std::io::stdout();
+        // Without raw mode there's no keyboard input to enhance
+        let supports_keyboard_enhancement = raw_mode && backend.supports_keyboard_enhancement();
 
-        let supports_keyboard_enhancement = matches!(
-            crossterm::terminal::supports_keyboard_enhancement(),
-            Ok(true)
-        );
+        if raw_mode && !supports_keyboard_enhancement {
+            // TODO: Make it work without kitty too.
+            panic!("To simulate bevy key presses, we need the kitty comprehensive keyboard handling extension to terminal. See here: https://sw.kovidgoyal.net/kitty/keyboard-protocol/");
+        }
+
+        let (width, height) = backend
+            .size()
+            .expect("Could not read current terminal size");
+
+        let window = Self {
+            height,
+            width,
+            colors: settings.colors,
+            title: settings.title.clone(),
+            min_size: settings.min_size,
+            virtual_size: settings.virtual_size,
+            letterbox_style: settings.letterbox_style,
+            supports_keyboard_enhancement,
+            keyboard_enhancement_flags: settings.keyboard_enhancement_flags,
+            mouse_capture: settings.mouse_capture,
+            focus_reporting: settings.focus_reporting,
+            alternate_screen: settings.alternate_screen,
+            raw_mode,
+            monochrome: !settings.color_choice.use_color(),
+            styled_underlines: false,
+            native_blink: false,
+            software_blink: settings.software_blink,
+            blink_hidden: false,
+            torn_down: false,
+            backend: SharedBackend::new(backend),
+        };
+        window.enter_terminal();
+        window
+    }
+
+    /// A copy of the window for drawing from another thread. It shares the terminal, but leaves restoring it to the
+    /// window it was copied from
+    pub(crate) fn detached(&self) -> CrosstermWindow {
+        CrosstermWindow {
+            height: self.height,
+            width: self.width,
+            colors: self.colors,
+            title: self.title.clone(),
+            min_size: self.min_size,
+            virtual_size: self.virtual_size,
+            letterbox_style: self.letterbox_style,
+            supports_keyboard_enhancement: self.supports_keyboard_enhancement,
+            keyboard_enhancement_flags: self.keyboard_enhancement_flags,
+            mouse_capture: self.mouse_capture,
+            focus_reporting: self.focus_reporting,
+            alternate_screen: self.alternate_screen,
+            raw_mode: self.raw_mode,
+            monochrome: self.monochrome,
+            styled_underlines: self.styled_underlines,
+            native_blink: self.native_blink,
+            software_blink: self.software_blink,
+            blink_hidden: self.blink_hidden,
+            torn_down: true,
+            backend: self.backend.detached(),
+        }
+    }
 
-        if supports_keyboard_enhancement {
+    /// Switch the terminal over to the game: the alternate screen, keyboard enhancements, mouse capture and so on.
+    /// Raw mode needs to be enabled already, if it's used
+    pub(crate) fn enter_terminal(&self) {
+        let mut term = Vec::new();
+
+        if self.supports_keyboard_enhancement {
             queue!(
                 term,
-                PushKeyboardEnhancementFlags(
-                    KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
-                        | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
-                        | KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS
-                        | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
-                )
+                PushKeyboardEnhancementFlags(self.keyboard_enhancement_flags)
             )
             .expect("Push keyboard enhancement flags");
-        } else {
-            // TODO: Make it work without kitty too.
-            panic!("To simulate bevy key presses, we need the kitty comprehensive keyboard handling extension to terminal. See here: https://sw.kovidgoyal.net/kitty/keyboard-protocol/");
+        }
+        if self.mouse_capture() {
+            queue!(term, crossterm::event::EnableMouseCapture).expect("Could not capture mouse");
+        }
+        if self.focus_reporting && self.raw_mode {
+            queue!(term, crossterm::event::EnableFocusChange)
+                .expect("Could not enable focus reporting");
+        }
+        if self.alternate_screen {
+            queue!(term, crossterm::terminal::EnterAlternateScreen)
+                .expect("Could not enter alternate terminal");
         }
         queue!(
             term,
-            crossterm::terminal::EnterAlternateScreen,
-            crossterm::event::EnableMouseCapture,
-            crossterm::event::EnableFocusChange,
             crossterm::terminal::Clear(crossterm::terminal::ClearType::All,),
         )
         .expect("Could not queue commands");
 
-        let title = if let Some(title) = &settings.title {
+        if let Some(title) = &self.title {
             term.queue(crossterm::terminal::SetTitle(title))
                 .expect("Could not set terminal title");
-            Some(title.clone())
-        } else {
-            None
-        };
+        }
 
-        let colors = settings.colors;
-        term.queue(crossterm::style::SetColors(colors.to_crossterm()))
+        term.queue(crossterm::style::SetColors(self.to_crossterm(self.colors)))
             .expect("Could not set window colors");
 
-        term.flush().expect("Could not initialize terminal");
+        let mut backend = self.backend.lock();
+        backend
+            .write_all(&term)
+            .and_then(|_| backend.flush())
+            .expect("Could not initialize terminal");
+    }
 
-        let (width, height) =
-            crossterm::terminal::size().expect("Could not read current terminal size");
+    /// Undo everything [`CrosstermWindow::enter_terminal`] did, except for leaving the alternate screen
+    pub(crate) fn leave_terminal(&self) {
+        if self.backend.is_hung_up() {
+            return;
+        }
+        // Frames still on their way to the terminal have to get there first
+        self.backend.finish_frames();
+        self.restore(|term| {
+            if self.supports_keyboard_enhancement {
+                queue!(term, PopKeyboardEnhancementFlags)?;
+            }
+            if self.focus_reporting && self.raw_mode {
+                queue!(term, crossterm::event::DisableFocusChange)?;
+            }
+            queue!(
+                term,
+                crossterm::event::DisableMouseCapture,
+                crossterm::cursor::Show,
+            )
+        });
+    }
 
-        Self {
-            height,
-            width,
-            colors,
-            title,
-            supports_keyboard_enhancement,
+    /// Give the terminal back to the shell. Only the first call does anything
+    fn teardown(&mut self, leave_alternate_screen: bool) {
+        if self.torn_down {
+            return;
+        }
+        self.torn_down = true;
+        // There's no terminal left to give back, and writing to it would fail
+        if self.backend.is_hung_up() {
+            return;
+        }
+
+        // While a `RawTerminalGuard` holds the terminal, whatever `enter_terminal` set up has been undone already
+        let paused = self.backend.is_paused();
+        if !paused {
+            self.leave_terminal();
+        }
+        if !self.alternate_screen && !paused {
+            // Put the shell prompt below the last frame rather than on top of it
+            self.restore(|term| {
+                queue!(
+                    term,
+                    crossterm::cursor::MoveTo(0, self.height.saturating_sub(1)),
+                    crossterm::style::ResetColor,
+                    crossterm::style::Print("\r\n"),
+                )
+            });
+        }
+        self.leave_alternate_screen(leave_alternate_screen);
+        if self.backend.is_hung_up() {
+            return;
+        }
+        if self.raw_mode {
+            if let Err(error) = self.backend.lock().disable_raw_mode() {
+                bevy::log::warn!("Could not disable raw mode: {error}");
+            }
+        }
+    }
+
+    /// Switch back to the main screen if we're on the alternate one, and flush any queued output
+    pub(crate) fn leave_alternate_screen(&self, leave: bool) {
+        if self.backend.is_hung_up() {
+            return;
+        }
+        self.restore(|term| {
+            if leave && self.alternate_screen {
+                queue!(term, crossterm::terminal::LeaveAlternateScreen)?;
+            }
+            Ok(())
+        });
+    }
+
+    // Restoring the terminal is best effort, since it also happens on drop, where panicking again would abort. A
+    // terminal that can't be written to is treated as hung up, so nothing else tries to write to it
+    fn restore(&self, commands: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>) {
+        let mut term = Vec::new();
+        let result = commands(&mut term).and_then(|_| {
+            let mut backend = self.backend.lock();
+            backend.write_all(&term).and_then(|_| backend.flush())
+        });
+        if let Err(error) = result {
+            bevy::log::warn!("Could not restore the terminal: {error}");
+            self.backend.hang_up();
         }
     }
 }
 
 // Ensure teardown even if we encounter a panic
+// We do __NOT__ want to leave the alternate screen after a panic, because that would wipe out the panic message
 impl Drop for CrosstermWindow {
     fn drop(&mut self) {
-        let mut term = std::io::stdout();
-        if self.supports_keyboard_enhancement {
-            queue!(term, PopKeyboardEnhancementFlags).expect("Pop keyboard enhancement flags");
-        }
-        queue!(
-            term,
-            crossterm::event::DisableMouseCapture,
-            crossterm::event::DisableFocusChange,
-            crossterm::cursor::Show,
-        )
-        .expect("Could not queue commands");
-        term.flush().expect("Could not reset terminal");
+        self.teardown(false);
+    }
+}
 
-        crossterm::terminal::disable_raw_mode().expect("Could not disable raw mode");
+/// Input state the runner keeps between ticks
+struct InputState {
+    /// A dead key (a lone combining mark) waiting for the character it should be combined with
+    dead_key: Option<crossterm::event::KeyEvent>,
+    /// Whether the terminal has focus, according to the last focus event
+    focused: bool,
+    /// Whether any terminal events were read during the last tick
+    had_events: bool,
+    /// Set by a signal handler when the process is asked to terminate or the terminal hangs up
+    terminate: Arc<AtomicBool>,
+    /// Set by a signal handler when the terminal hangs up
+    hangup: Arc<AtomicBool>,
+    /// Where the mouse was last seen, in terminal cells, for the delta of the next `CursorMoved`
+    cursor: Option<bevy::math::Vec2>,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        InputState {
+            dead_key: None,
+            focused: true,
+            had_events: false,
+            terminate: Arc::new(AtomicBool::new(false)),
+            hangup: Arc::new(AtomicBool::new(false)),
+            cursor: None,
+        }
     }
 }
 
 pub fn crossterm_runner(mut app: App) {
-    let bevy_window = setup_window(&mut app);
-
-    // There should only be one ScheduleRunnerPlugin, but if there isn't, add one
-    // (also there might be a better way to do this)
-    let settings = app.get_added_plugins::<bevy_app::ScheduleRunnerPlugin>();
-    let settings = if settings.is_empty() {
-        app.add_plugins(bevy_app::ScheduleRunnerPlugin::run_loop(
-            std::time::Duration::from_millis(50),
-        ));
-        app.get_added_plugins::<bevy_app::ScheduleRunnerPlugin>()[0]
-    } else {
-        settings[0]
-    };
-    let mut modifiers = crossterm::event::KeyModifiers::empty();
+    // Let plugins finish setting up, the same as bevy's own runners do
+    if app.plugins_state() != PluginsState::Cleaned {
+        while app.plugins_state() == PluginsState::Adding {
+            bevy::tasks::tick_global_task_pools_on_main_thread();
+        }
+        app.finish();
+        app.cleanup();
+    }
+
+    let (bevy_window, config_error) = setup_window(&mut app);
+
+    // There should only be one ScheduleRunnerPlugin, but if there isn't, run as if there was one at 20 frames a
+    // second. It's too late to add plugins by now
+    let run_mode = app
+        .get_added_plugins::<bevy_app::ScheduleRunnerPlugin>()
+        .first()
+        .map_or(
+            bevy::app::RunMode::Loop {
+                wait: Some(std::time::Duration::from_millis(50)),
+            },
+            |settings| settings.run_mode,
+        );
+    let mut input = InputState::default();
+    let raw_mode = app.world.resource::<CrosstermWindowSettings>().raw_mode();
+    let frame_rate = app.world.resource::<CrosstermWindowSettings>().frame_rate();
+    register_termination_signals(&input.terminate, &input.hangup, !raw_mode);
 
-    match settings.run_mode {
+    let exit = match run_mode {
         bevy::app::RunMode::Once => {
             app.update();
+            exit_requested(&app.world).unwrap_or(Exit::Success)
         }
         bevy::app::RunMode::Loop { wait } => {
+            let wait = frame_rate
+                .map(|fps| std::time::Duration::from_secs(1) / fps)
+                .or(wait);
             // Run the main loop, and delay if we need to
             let mut start_time = std::time::Instant::now();
-            while tick(&mut app, bevy_window, &mut modifiers).is_ok() {
+            let mut last_activity = start_time;
+            let mut redraw_requests = ManualEventReader::<RequestRedraw>::default();
+            loop {
+                if let Some(mut headless) = app.world.get_resource_mut::<Headless>() {
+                    headless.start_frame();
+                }
+                if let Err(exit) = tick(&mut app, bevy_window, &mut input) {
+                    break exit;
+                }
+                // Headless runs don't wait for anything, their clock doesn't depend on how long frames take
+                if let Some(headless) = app.world.get_resource::<Headless>() {
+                    if headless.finished() {
+                        break Exit::Success;
+                    }
+                    continue;
+                }
                 let end_time = std::time::Instant::now();
 
-                if let Some(wait) = wait {
+                let playing_audio = playing_audio(&app.world);
+                let using_gamepad = using_gamepad(&app.world);
+                let redraw_requested = redraw_requested(&app.world, &mut redraw_requests);
+                if input.had_events || drew_anything(&app.world) || playing_audio || using_gamepad {
+                    last_activity = end_time;
+                }
+
+                // Slow down while we're in the background or nothing is happening, if the app asked for it. Sounds
+                // start on the frame after they're spawned, so the background doesn't count while any are playing.
+                // Gamepads don't need the terminal to have focus, so neither does it count while one is in use. A
+                // `RequestRedraw` gets just the next frame run at the normal rate, like it does with winit
+                let window_settings = app.world.resource::<CrosstermWindowSettings>();
+                let slow_wait = if !input.focused && !playing_audio && !using_gamepad {
+                    window_settings.unfocused_wait()
+                } else {
+                    None
+                }
+                .or_else(|| {
+                    window_settings
+                        .idle_pacing()
+                        .filter(|idle| end_time - last_activity >= idle.after)
+                        .map(|idle| idle.wait)
+                })
+                .filter(|_| !redraw_requested);
+
+                if let Some(wait) = slow_wait.or(wait) {
                     let exe_time = end_time - start_time;
                     if exe_time < wait {
                         let delay = wait - exe_time;
-                        // dbg!(delay);
-                        std::thread::sleep(delay);
+                        if slow_wait.is_some() && raw_mode {
+                            // Wake up as soon as something happens, so input (or regaining focus) takes effect
+                            // straight away
+                            let _ = with_backend(&app.world, bevy_window, |backend| {
+                                backend.poll(delay)
+                            });
+                        } else {
+                            std::thread::sleep(delay);
+                        }
                     }
                 }
 
-                start_time = end_time;
+                start_time = std::time::Instant::now();
             }
-
-            // Cleanup and teardown
-            // Most teardown is done by the drop implementation of CrosstermWindow, which will run even if we encounter
-            // a panic (provided we do not run in panic="abort" mode)
-            // We do __NOT__ want to leave the alternate screen after a panic, because that would wipe out the panic
-            // message
-            let mut term = std::io::stdout();
-            term.execute(crossterm::terminal::LeaveAlternateScreen)
-                .expect("Could not leave alternate terminal");
         }
+    };
+
+    // Cleanup and teardown
+    // If we get here there was no panic, so leave the alternate screen too. The drop implementation of
+    // CrosstermWindow covers panics (provided we do not run in panic="abort" mode)
+    if let Some(mut window) = app.world.get_mut::<CrosstermWindow>(bevy_window) {
+        window.teardown(true);
     }
+    // Only now can a broken config be seen. Nothing is logged without a LogPlugin, and it'd be drawn over anyway
+    if let Some(error) = config_error {
+        eprintln!("Ignored the config file: {error}");
+    }
+
+    if let Exit::Error(code) = exit {
+        // Exiting the process skips destructors, so drop the app first
+        drop(app);
+        std::process::exit(code.get().into());
+    }
+}
+
+/// Have SIGTERM and SIGHUP ask the app to exit instead of killing it, so the terminal still gets restored. A second
+/// signal kills the app straight away, in case it's stuck. Without raw mode Ctrl-C sends SIGINT rather than a key
+/// event, so that's handled the same way. SIGHUP also sets `hangup`, as there's no terminal left to restore
+#[cfg(unix)]
+fn register_termination_signals(
+    terminate: &Arc<AtomicBool>,
+    hangup: &Arc<AtomicBool>,
+    interrupt: bool,
+) {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+    let signals = [SIGTERM, SIGHUP]
+        .into_iter()
+        .chain(interrupt.then_some(SIGINT));
+    for signal in signals {
+        signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(terminate))
+            .expect("Could not register signal handler");
+        signal_hook::flag::register(signal, Arc::clone(terminate))
+            .expect("Could not register signal handler");
+    }
+    signal_hook::flag::register(SIGHUP, Arc::clone(hangup))
+        .expect("Could not register signal handler");
+}
+
+#[cfg(not(unix))]
+fn register_termination_signals(
+    _terminate: &Arc<AtomicBool>,
+    _hangup: &Arc<AtomicBool>,
+    _interrupt: bool,
+) {
+}
+
+/// How the app asked to exit
+enum Exit {
+    Success,
+    Error(std::num::NonZeroU8),
+}
+
+/// Check whether anything asked the app to exit during the last update. Errors take precedence
+fn exit_requested(world: &bevy_ecs::world::World) -> Option<Exit> {
+    let errors = world.resource::<Events<AppExitError>>();
+    if let Some(error) = errors.get_reader().read(errors).next() {
+        return Some(Exit::Error(error.0));
+    }
+
+    let app_exit_events = world.resource::<Events<AppExit>>();
+    let mut app_exit_reader = app_exit_events.get_reader();
+    app_exit_reader
+        .read(app_exit_events)
+        .next()
+        .map(|_| Exit::Success)
+}
+
+/// Returns true if the last frame had to draw or clear anything
+fn drew_anything(world: &bevy_ecs::world::World) -> bool {
+    let redraw = world.resource::<EntitiesToRedraw>();
+    redraw.full_redraw || !redraw.to_draw.is_empty() || !redraw.to_clear.is_empty()
+}
+
+/// Returns true if anything sent a `RequestRedraw` since the last check
+fn redraw_requested(
+    world: &bevy_ecs::world::World,
+    reader: &mut ManualEventReader<RequestRedraw>,
+) -> bool {
+    world
+        .get_resource::<Events<RequestRedraw>>()
+        .is_some_and(|events| reader.read(events).count() > 0)
+}
+
+#[cfg(feature = "audio")]
+fn playing_audio(world: &bevy_ecs::world::World) -> bool {
+    world
+        .get_resource::<crate::audio::AudioActivity>()
+        .is_some_and(|activity| activity.busy)
 }
 
-/// Setup the crossterm window, so it is available to the rest of the app
-fn setup_window(app: &mut App) -> Entity {
+#[cfg(not(feature = "audio"))]
+fn playing_audio(_world: &bevy_ecs::world::World) -> bool {
+    false
+}
+
+#[cfg(feature = "gamepad")]
+fn using_gamepad(world: &bevy_ecs::world::World) -> bool {
+    world
+        .get_resource::<crate::gamepad::GamepadActivity>()
+        .is_some_and(|activity| activity.busy)
+}
+
+#[cfg(not(feature = "gamepad"))]
+fn using_gamepad(_world: &bevy_ecs::world::World) -> bool {
+    false
+}
+
+/// Setup the crossterm window, so it is available to the rest of the app. Also returns what was wrong with the
+/// config file, if it couldn't be used
+fn setup_window(app: &mut App) -> (Entity, Option<ConfigError>) {
     app.init_resource::<CrosstermWindowSettings>();
+    let (config, config_error) = match load_config(app) {
+        Ok(config) => (config, None),
+        Err(error) => (None, Some(error)),
+    };
 
+    let headless = app.world.get_resource::<Headless>().cloned();
+    let backend: Box<dyn TerminalBackend> = match &headless {
+        Some(headless) => Box::new(headless.backend().clone()),
+        None => app
+            .world
+            .remove_resource::<SelectedBackend>()
+            .map_or_else(|| Box::new(CrosstermBackend) as _, |selected| selected.0),
+    };
     let window_settings = app.world.resource::<CrosstermWindowSettings>();
-    let window = CrosstermWindow::new(window_settings);
+    let mut window = CrosstermWindow::new(window_settings, backend);
+    let color_depth = window_settings.color_depth();
+
+    let capabilities = if let Some(headless) = &headless {
+        // A headless run has to come out the same everywhere, so nothing is detected and time is fake
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(headless.frame_time()));
+        TerminalCapabilities {
+            keyboard_enhancement: window.supports_keyboard_enhancement,
+            background: None,
+            color_depth: ColorDepth::TrueColor,
+            styled_underlines: true,
+            blink: true,
+        }
+    } else {
+        // Find out what we can about the terminal while nothing else is reading from it
+        TerminalCapabilities {
+            keyboard_enhancement: window.supports_keyboard_enhancement,
+            background: window
+                .raw_mode()
+                .then(|| window.backend.lock().query_background())
+                .flatten(),
+            color_depth: color_depth.unwrap_or_else(ColorDepth::from_env),
+            styled_underlines: crate::capabilities::detect_styled_underlines(
+                window.supports_keyboard_enhancement,
+            ),
+            blink: crate::capabilities::detect_blink(),
+        }
+    };
+    window.styled_underlines = capabilities.styled_underlines;
+    window.native_blink = capabilities.blink;
+    // Stick with the default theme unless it would be hard to read
+    if capabilities.is_light() == Some(true) && *app.world.resource::<Theme>() == Theme::dark() {
+        app.world.insert_resource(Theme::light());
+    }
+    if let Some(config) = &config {
+        config.apply_theme(&mut app.world.resource_mut::<Theme>());
+    }
+    app.world.insert_resource(capabilities);
+
+    // Only start writing on another thread once nothing else needs to read from the terminal
+    let render_thread = app
+        .world
+        .resource::<CrosstermWindowSettings>()
+        .render_thread();
+    if render_thread && headless.is_none() {
+        window.backend.start_render_thread();
+    }
 
     // Insert our window entity so that other parts of our app can use them
     let bevy_window = app.world.spawn(window).insert(PrimaryWindow).id();
@@ -160,35 +557,96 @@ fn setup_window(app: &mut App) -> Entity {
         window: bevy_window,
     });
 
-    bevy_window
+    (bevy_window, config_error)
 }
 
-/// A single game update
-fn tick(app: &mut App, bevy_window: Entity, modifiers: &mut crossterm::event::KeyModifiers) -> Result<(), AppExit> {
-    crossterm_events(&mut app.world, bevy_window, modifiers);
+/// Read the config file, if there is one, and apply its window settings
+fn load_config(app: &mut App) -> Result<Option<WindowConfig>, ConfigError> {
+    let Some(path) = app
+        .world
+        .resource::<CrosstermWindowSettings>()
+        .config_file()
+        .map(|path| path.to_path_buf())
+    else {
+        return Ok(None);
+    };
+    match WindowConfig::load(path) {
+        Ok(config) => {
+            config.apply(&mut app.world.resource_mut::<CrosstermWindowSettings>());
+            Ok(Some(config))
+        }
+        // Players don't have to write one
+        Err(ConfigError::Io { error, .. }) if error.kind() == std::io::ErrorKind::NotFound => {
+            Ok(None)
+        }
+        Err(error) => Err(error),
+    }
+}
 
-    // Yield execution to the rest of bevy and it's scheduler
-    app.update();
+/// A single game update
+fn tick(app: &mut App, bevy_window: Entity, input: &mut InputState) -> Result<(), Exit> {
+    crossterm_events(&mut app.world, bevy_window, input);
 
-    // After all the other systems have updated, check if there are any AppExit events and
-    // handle them
-    {
-        let app_exit_events = app.world.resource::<Events<AppExit>>();
-        let mut app_exit_reader = app_exit_events.get_reader();
-        if app_exit_reader.read(app_exit_events).next().is_some() {
-            // We're breaking out, the app requested an exit
-            return Err(AppExit);
-        };
+    // Yield execution to the rest of bevy and it's scheduler, unless the frame stepper is holding the app back
+    let held = app
+        .world
+        .get_resource_mut::<FrameStepper>()
+        .is_some_and(|mut stepper| stepper.hold());
+    if !held {
+        app.update();
     }
 
-    Ok(())
+    // After all the other systems have updated, check if the app requested an exit
+    match exit_requested(&app.world) {
+        Some(exit) => Err(exit),
+        None => Ok(()),
+    }
 }
 
 /// Check if any events are immediately available and if so, read them and republish
-fn crossterm_events(world: &mut bevy_ecs::world::World, bevy_window: Entity, modifiers: &mut crossterm::event::KeyModifiers) {
-    while let Ok(available) = crossterm::event::poll(std::time::Duration::from_secs(0)) {
+fn crossterm_events(
+    world: &mut bevy_ecs::world::World,
+    bevy_window: Entity,
+    input: &mut InputState,
+) {
+    input.had_events = false;
+
+    if input.hangup.load(Ordering::Relaxed) {
+        hang_up(world, bevy_window);
+    }
+    if input.terminate.load(Ordering::Relaxed) {
+        request_exit(world, bevy_window);
+    }
+
+    // There's nothing left to read once the terminal is gone. Writing the last frame may have been what found that
+    // out, so the app still needs asking to exit
+    let window = world.get::<CrosstermWindow>(bevy_window).unwrap();
+    if window.backend.is_hung_up() {
+        request_exit(world, bevy_window);
+        return;
+    }
+
+    // Reading events needs raw mode, otherwise we'd be eating whatever is typed at the terminal. Resizes are the
+    // only thing we need to know about, so look for those directly
+    if !window.raw_mode() {
+        match with_backend(world, bevy_window, |backend| backend.size()) {
+            Ok((width, height)) => resize_window(world, bevy_window, width, height),
+            Err(error) => input_error(world, bevy_window, &error),
+        }
+        return;
+    }
+
+    while let Some(available) = poll_input(world, bevy_window) {
         if available {
-            match crossterm::event::read().unwrap() {
+            input.had_events = true;
+            let event = match with_backend(world, bevy_window, |backend| backend.read()) {
+                Ok(event) => event,
+                Err(error) => {
+                    input_error(world, bevy_window, &error);
+                    break;
+                }
+            };
+            match event {
                 // Republish keyboard events in bevy
                 crossterm::event::Event::Key(key_event) => {
                     // If the key event is for C-c, submit a AppExit event so the application
@@ -199,24 +657,26 @@ fn crossterm_events(world: &mut bevy_ecs::world::World, bevy_window: Entity, mod
                     {
                         world.send_event(AppExit);
                     }
+
+                    // Keys meant for the debug keys or the frame stepper don't reach the app
+                    if crate::debug::intercept_key(world, &key_event) {
+                        continue;
+                    }
+
+                    // Different terminals report dead keys differently, so make sure the character that comes out
+                    // the other end is the composed one
+                    let key_event = match compose_dead_key(&mut input.dead_key, key_event) {
+                        DeadKey::Key(key_event) => key_event,
+                        DeadKey::Pending => continue,
+                        DeadKey::Unhandled(keys, key_event) => {
+                            world.send_event(UnhandledKeySequence(keys));
+                            key_event
+                        }
+                    };
                     // let mut input = world.resource_mut::<bevy::input::ButtonInput<bevy::input::keyboard::KeyCode>>();
                     // apply_key_event_to_bevy(&key_event, &mut input);
                     if let Some((bevy_event, mods)) = key_event_to_bevy(&key_event, bevy_window) {
-                        // dbg!(mods, *modifiers);
-                        if mods != *modifiers {
-                            let delta = mods.symmetric_difference(*modifiers);
-                            for flag in delta {
-                                let state = if mods.contains(flag) {
-                                    // This flag has been added.
-                                    bevy::input::ButtonState::Pressed
-                                } else { // modifiers.contains(flag)
-                                    // This flag has been removed.
-                                    bevy::input::ButtonState::Released
-                                };
-                                world.send_event(modifier_to_bevy(crossterm_modifier_to_bevy_key(flag), state, bevy_window));
-                            }
-                            *modifiers = mods;
-                        }
+                        update_modifiers(world, bevy_window, mods);
                         world.send_event(bevy_event);
                     }
                     world.send_event(CrosstermKeyEventWrapper(key_event));
@@ -224,37 +684,54 @@ fn crossterm_events(world: &mut bevy_ecs::world::World, bevy_window: Entity, mod
 
                 // Republish mouse events in bevy
                 crossterm::event::Event::Mouse(mouse_event) => {
+                    if let Some(world_event) = mouse_event_to_world(world, bevy_window, mouse_event)
+                    {
+                        world.send_event(world_event);
+                    }
+                    // Also publish movement the way bevy does, in cells rather than pixels
+                    use crossterm::event::MouseEventKind;
+                    if let MouseEventKind::Moved | MouseEventKind::Drag(_) = mouse_event.kind {
+                        let position = bevy::math::Vec2::new(
+                            mouse_event.column as f32,
+                            mouse_event.row as f32,
+                        );
+                        world.send_event(bevy::window::CursorMoved {
+                            window: bevy_window,
+                            position,
+                            delta: input.cursor.map(|previous| position - previous),
+                        });
+                        input.cursor = Some(position);
+                    }
                     world.send_event(CrosstermMouseEventWrapper(mouse_event));
                 }
 
                 // Send a bevy window resized event if the terminal is resized, and also change the persisted window state
                 crossterm::event::Event::Resize(width, height) => {
-                    // Update the window resource and publish an event for the window being resized
-                    world.send_event(WindowResized {
-                        window: bevy_window,
-                        width: width as f32,
-                        height: height as f32,
-                    });
-
-                    let mut window_component =
-                        world.get_mut::<CrosstermWindow>(bevy_window).unwrap();
-
-                    window_component.height = height;
-                    window_component.width = width;
+                    resize_window(world, bevy_window, width, height);
                 }
 
                 // Send a bevy window focused event
                 crossterm::event::Event::FocusGained => {
+                    input.focused = true;
                     world.send_event(bevy::window::WindowFocused {
                         window: bevy_window,
                         focused: true,
                     });
+                    // Some terminals and multiplexers damage the alternate screen while we're in the
+                    // background, so repaint everything to be safe
+                    world.send_event(ForceRedraw);
                 }
                 crossterm::event::Event::FocusLost => {
+                    input.focused = false;
+                    // The mouse is somewhere else now, so the next move has no delta
+                    input.cursor = None;
                     world.send_event(bevy::window::WindowFocused {
                         window: bevy_window,
                         focused: false,
                     });
+                    // We won't hear about modifiers being let go of while we're in the background, so release them
+                    // now rather than leave them stuck
+                    update_modifiers(world, bevy_window, crossterm::event::KeyModifiers::empty());
                 }
 
                 // Ignore bracketed paste. It's not well supported on windows.
@@ -267,6 +744,189 @@ fn crossterm_events(world: &mut bevy_ecs::world::World, bevy_window: Entity, mod
     }
 }
 
+/// Check whether an event is waiting to be read, or `None` if the terminal couldn't be read from
+fn poll_input(world: &mut bevy_ecs::world::World, bevy_window: Entity) -> Option<bool> {
+    with_backend(world, bevy_window, |backend| {
+        backend.poll(std::time::Duration::from_secs(0))
+    })
+    .map_err(|error| input_error(world, bevy_window, &error))
+    .ok()
+}
+
+/// Reading failed. Being interrupted by a signal is fine, the next tick tries again, but anything else means the
+/// terminal has gone away
+fn input_error(world: &mut bevy_ecs::world::World, bevy_window: Entity, error: &std::io::Error) {
+    if error.kind() != std::io::ErrorKind::Interrupted {
+        hang_up(world, bevy_window);
+    }
+}
+
+/// The terminal has gone away, e.g. because its window was closed. Stop writing to it, and ask the app to exit
+fn hang_up(world: &mut bevy_ecs::world::World, bevy_window: Entity) {
+    let window = world.get::<CrosstermWindow>(bevy_window).unwrap();
+    if window.backend.is_hung_up() {
+        return;
+    }
+    window.backend.hang_up();
+    request_exit(world, bevy_window);
+}
+
+/// Close the window and exit, the same as if the app had asked to
+fn request_exit(world: &mut bevy_ecs::world::World, bevy_window: Entity) {
+    world.send_event(bevy::window::WindowCloseRequested {
+        window: bevy_window,
+    });
+    world.send_event(AppExit);
+}
+
+/// Use the window's backend for something that doesn't need the world
+fn with_backend<R>(
+    world: &bevy_ecs::world::World,
+    bevy_window: Entity,
+    f: impl FnOnce(&mut dyn TerminalBackend) -> R,
+) -> R {
+    let window = world.get::<CrosstermWindow>(bevy_window).unwrap();
+    let mut backend = window.backend.lock();
+    f(backend.as_mut())
+}
+
+/// Update the window's size and publish an event for the window being resized, if the size changed
+pub(crate) fn resize_window(
+    world: &mut bevy_ecs::world::World,
+    bevy_window: Entity,
+    width: u16,
+    height: u16,
+) {
+    let mut window_component = world.get_mut::<CrosstermWindow>(bevy_window).unwrap();
+    if window_component.terminal_size() == (width, height) {
+        return;
+    }
+    window_component.height = height;
+    window_component.width = width;
+
+    world.send_event(WindowResized {
+        window: bevy_window,
+        width: width as f32,
+        height: height as f32,
+    });
+}
+
+#[derive(Debug, PartialEq)]
+enum DeadKey {
+    /// The key to send, either untouched or composed with a dead key
+    Key(crossterm::event::KeyEvent),
+    /// The key is a dead key (or its release) and nothing should be sent yet
+    Pending,
+    /// The dead key couldn't be combined with the key after it. Both are included in the sequence, and the key after
+    /// it is still sent normally
+    Unhandled(Vec<crossterm::event::KeyEvent>, crossterm::event::KeyEvent),
+}
+
+/// Combine a dead key with the key that follows it
+fn compose_dead_key(
+    dead_key: &mut Option<crossterm::event::KeyEvent>,
+    key_event: crossterm::event::KeyEvent,
+) -> DeadKey {
+    use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
+    use unicode_normalization::char::{compose, is_combining_mark};
+
+    // Holding a modifier for the next character, and releases of other keys, go by without using up the dead key
+    if matches!(key_event.code, KeyCode::Modifier(_))
+        || (key_event.kind != KeyEventKind::Press && !matches!(key_event.code, KeyCode::Char(_)))
+    {
+        return DeadKey::Key(key_event);
+    }
+    let KeyCode::Char(c) = key_event.code else {
+        return match dead_key.take() {
+            Some(dead) => DeadKey::Unhandled(vec![dead, key_event], key_event),
+            None => DeadKey::Key(key_event),
+        };
+    };
+
+    if is_combining_mark(c) {
+        // Swallow the release of the dead key, and hold on to the press until the next character
+        if key_event.kind == KeyEventKind::Press {
+            *dead_key = Some(key_event);
+        }
+        return DeadKey::Pending;
+    }
+
+    let Some(dead) = *dead_key else {
+        return DeadKey::Key(key_event);
+    };
+    // Releases of other keys can arrive before the character the dead key applies to
+    if key_event.kind == KeyEventKind::Release {
+        return DeadKey::Key(key_event);
+    }
+    *dead_key = None;
+
+    let KeyCode::Char(mark) = dead.code else {
+        return DeadKey::Key(key_event);
+    };
+    // Without the kitty protocol's alternate keys, shifted letters arrive lower case with the shift modifier
+    let mut uppercase = c.to_uppercase();
+    let c = match (uppercase.next(), uppercase.next()) {
+        (Some(upper), None) if key_event.modifiers.contains(KeyModifiers::SHIFT) => upper,
+        _ => c,
+    };
+    match compose(c, mark) {
+        Some(composed) => DeadKey::Key(crossterm::event::KeyEvent {
+            code: KeyCode::Char(composed),
+            ..key_event
+        }),
+        None => DeadKey::Unhandled(vec![dead, key_event], key_event),
+    }
+}
+
+/// Translate the terminal cell of a mouse event into world coordinates
+fn mouse_event_to_world(
+    world: &mut bevy_ecs::world::World,
+    bevy_window: Entity,
+    event: crossterm::event::MouseEvent,
+) -> Option<WorldMouseEvent> {
+    let window = world.get::<CrosstermWindow>(bevy_window)?;
+    let (x, y) = window.terminal_to_viewport(event.column, event.row)?;
+
+    let camera = world
+        .query::<&TerminalCamera>()
+        .get_single(world)
+        .ok()
+        .copied()
+        .unwrap_or_default();
+    let (x, y) = camera.viewport_to_world(x, y);
+
+    Some(WorldMouseEvent { event, x, y })
+}
+
+/// Store the currently held modifiers and send bevy key events for any that were pressed or released since last time
+pub(crate) fn update_modifiers(
+    world: &mut bevy_ecs::world::World,
+    bevy_window: Entity,
+    mods: crossterm::event::KeyModifiers,
+) {
+    let previous = world.resource::<KeyModifiersState>().modifiers();
+    if mods == previous {
+        return;
+    }
+
+    let delta = mods.symmetric_difference(previous);
+    for flag in delta {
+        let state = if mods.contains(flag) {
+            // This flag has been added.
+            bevy::input::ButtonState::Pressed
+        } else {
+            // This flag has been removed.
+            bevy::input::ButtonState::Released
+        };
+        world.send_event(modifier_to_bevy(
+            crossterm_modifier_to_bevy_key(flag),
+            state,
+            bevy_window,
+        ));
+    }
+    world.resource_mut::<KeyModifiersState>().set(mods);
+}
+
 fn crossterm_modifier_to_bevy_key(modifier: crossterm::event::KeyModifiers) -> bevy::input::keyboard::Key {
     let mut i = modifier.into_iter();
     let modifier = i.next().expect("mod");
@@ -773,3 +1433,79 @@ fn to_bevy_key(key_code: &crossterm::event::KeyCode) -> Option<bevy::input::keyb
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+    const ACUTE: char = '\u{301}';
+
+    fn press(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn dead_keys_compose_with_the_next_key() {
+        let release = |c| {
+            KeyEvent::new_with_kind(KeyCode::Char(c), KeyModifiers::NONE, KeyEventKind::Release)
+        };
+        let shifted = KeyEvent::new(KeyCode::Char('e'), KeyModifiers::SHIFT);
+        let cases = [
+            (
+                "vowel",
+                vec![press(ACUTE), press('e')],
+                DeadKey::Key(press('é')),
+            ),
+            (
+                "shifted vowel",
+                vec![press(ACUTE), shifted],
+                DeadKey::Key(KeyEvent::new(KeyCode::Char('É'), KeyModifiers::SHIFT)),
+            ),
+            (
+                "release in between",
+                vec![press(ACUTE), release(ACUTE), release('x'), press('a')],
+                DeadKey::Key(press('á')),
+            ),
+            (
+                "space",
+                vec![press(ACUTE), press(' ')],
+                DeadKey::Unhandled(vec![press(ACUTE), press(' ')], press(' ')),
+            ),
+            (
+                "not composable",
+                vec![press(ACUTE), press('x')],
+                DeadKey::Unhandled(vec![press(ACUTE), press('x')], press('x')),
+            ),
+            (
+                "not a character",
+                vec![press(ACUTE), KeyEvent::from(KeyCode::Enter)],
+                DeadKey::Unhandled(
+                    vec![press(ACUTE), KeyEvent::from(KeyCode::Enter)],
+                    KeyEvent::from(KeyCode::Enter),
+                ),
+            ),
+            // The second dead key replaces the first, and waits for a character of its own
+            (
+                "repeated",
+                vec![press('\u{300}'), press(ACUTE)],
+                DeadKey::Pending,
+            ),
+            (
+                "after a repeated one",
+                vec![press('\u{300}'), press(ACUTE), press('o')],
+                DeadKey::Key(press('ó')),
+            ),
+            ("no dead key", vec![press('e')], DeadKey::Key(press('e'))),
+        ];
+
+        for (name, keys, expected) in cases {
+            let mut dead_key = None;
+            let result = keys
+                .into_iter()
+                .map(|key| compose_dead_key(&mut dead_key, key))
+                .last();
+            assert_eq!(result, Some(expected), "{name}");
+        }
+    }
+}
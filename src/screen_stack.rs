@@ -0,0 +1,162 @@
+//! Screens stacked on top of each other, like gameplay, then a pause menu over it, then a settings menu over that.
+//!
+//! A screen is an entity with a [`StackedScreen`], and everything that's part of it is its children. Spawn one, then
+//! [`ScreenStack::push`] it to show it over the screens already there. Screens below an opaque screen are hidden
+//! until it's popped, and popping a screen despawns it along with its children.
+//!
+//! Only the top screen should react to input. Run its input systems with the [`on_top_screen`] condition, e.g.
+//! `app.add_systems(Update, pause_menu_input.run_if(on_top_screen("pause")))`.
+
+use std::borrow::Cow;
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+use crate::components::Visible;
+
+/// Makes the entity and its children a screen for the [`ScreenStack`]
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct StackedScreen {
+    name: Cow<'static, str>,
+    /// Whether the screens below are hidden while this one is showing. Screens that only cover part of the terminal,
+    /// like a pause menu over gameplay, should leave this off
+    pub opaque: bool,
+}
+
+impl StackedScreen {
+    pub fn new(name: impl Into<Cow<'static, str>>) -> StackedScreen {
+        StackedScreen {
+            name: name.into(),
+            opaque: false,
+        }
+    }
+
+    #[must_use]
+    pub fn with_opaque(mut self, opaque: bool) -> Self {
+        self.opaque = opaque;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StackChange {
+    Push(Entity),
+    Pop,
+}
+
+/// The screens that are showing, from the bottom up. Changes are made at the end of the frame, once the entities of
+/// screens spawned this frame exist
+#[derive(Resource, Debug, Default)]
+pub struct ScreenStack {
+    screens: Vec<Entity>,
+    changes: Vec<StackChange>,
+    // Entities that were visible before a screen above hid them
+    hidden: HashSet<Entity>,
+}
+
+impl ScreenStack {
+    /// Show `screen`, an entity with a [`StackedScreen`], on top of the others
+    pub fn push(&mut self, screen: Entity) {
+        self.changes.push(StackChange::Push(screen));
+    }
+
+    /// Remove the top screen and despawn it
+    pub fn pop(&mut self) {
+        self.changes.push(StackChange::Pop);
+    }
+
+    /// Swap the top screen for another one, e.g. to go from one menu to the next
+    pub fn replace(&mut self, screen: Entity) {
+        self.pop();
+        self.push(screen);
+    }
+
+    /// The screen on top, which should get the input
+    pub fn top(&self) -> Option<Entity> {
+        self.screens.last().copied()
+    }
+
+    /// The screens, from the bottom up
+    pub fn screens(&self) -> &[Entity] {
+        &self.screens
+    }
+
+    pub fn len(&self) -> usize {
+        self.screens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.screens.is_empty()
+    }
+}
+
+/// A run condition that's true while the screen named `name` is on top of the [`ScreenStack`]
+pub fn on_top_screen(
+    name: impl Into<Cow<'static, str>>,
+) -> impl FnMut(Res<ScreenStack>, Query<&StackedScreen>) -> bool + Clone {
+    let name = name.into();
+    move |stack: Res<ScreenStack>, screens: Query<&StackedScreen>| {
+        stack
+            .top()
+            .and_then(|top| screens.get(top).ok())
+            .is_some_and(|screen| screen.name == name)
+    }
+}
+
+/// Pushes and pops screens, then hides the ones that are covered and shows the rest. Covered screens are hidden again
+/// every frame, so children spawned into them later are hidden too
+pub(crate) fn update_screen_stack(
+    mut commands: Commands,
+    mut stack: ResMut<ScreenStack>,
+    screens: Query<&StackedScreen>,
+    children: Query<&Children>,
+    mut visible: Query<&mut Visible>,
+) {
+    let stack = &mut *stack;
+
+    for change in std::mem::take(&mut stack.changes) {
+        match change {
+            StackChange::Push(screen) => {
+                if screens.contains(screen) {
+                    stack.screens.push(screen);
+                } else {
+                    warn!("Can't push {screen:?} onto the screen stack, it isn't a StackedScreen");
+                }
+            }
+            StackChange::Pop => {
+                if let Some(screen) = stack.screens.pop() {
+                    commands.entity(screen).despawn_recursive();
+                }
+            }
+        }
+    }
+    // Screens despawned some other way are gone from the stack too
+    stack.screens.retain(|screen| screens.contains(*screen));
+    stack.hidden.retain(|entity| visible.contains(*entity));
+
+    // Everything below the topmost opaque screen is covered
+    let covered = stack
+        .screens
+        .iter()
+        .rposition(|screen| screens.get(*screen).is_ok_and(|screen| screen.opaque))
+        .unwrap_or(0);
+    for (i, screen) in stack.screens.iter().enumerate() {
+        for entity in std::iter::once(*screen).chain(children.iter_descendants(*screen)) {
+            let Ok(mut visible) = visible.get_mut(entity) else {
+                continue;
+            };
+            if i < covered {
+                if visible.is_visible {
+                    visible.is_visible = false;
+                    stack.hidden.insert(entity);
+                }
+            } else if stack.hidden.remove(&entity) {
+                visible.is_visible = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+use crossterm::event::{MouseButton, MouseEventKind};
+
+use crate::components::{Position, Sprite, SpriteBundle, Style, StyleMap, Visible};
+use crate::WorldMouseEvent;
+
+/// A rectangle of cells in world coordinates, including both corners it was made from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectionRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl SelectionRect {
+    /// The smallest rectangle containing both cells
+    pub fn from_corners(a: (i32, i32), b: (i32, i32)) -> SelectionRect {
+        SelectionRect {
+            x: a.0.min(b.0),
+            y: a.1.min(b.1),
+            width: a.0.abs_diff(b.0) + 1,
+            height: a.1.abs_diff(b.1) + 1,
+        }
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && ((x - self.x) as u32) < self.width
+            && ((y - self.y) as u32) < self.height
+    }
+}
+
+/// Sent while the user drags the mouse with a button held. Positions are in world coordinates (see
+/// [`WorldMouseEvent`]). A click without any movement doesn't start a selection.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseSelection {
+    /// The mouse moved with a button held down. `start` is where the button was pressed
+    Started {
+        button: MouseButton,
+        start: (i32, i32),
+    },
+    /// The mouse moved again while still held
+    Dragged {
+        button: MouseButton,
+        start: (i32, i32),
+        current: (i32, i32),
+    },
+    /// The button was let go of, finishing the selection
+    Finished {
+        button: MouseButton,
+        rect: SelectionRect,
+    },
+}
+
+/// When set, an outline of the current selection is drawn with this style above everything else
+#[derive(Resource, Debug, Default, Clone, Copy)]
+pub struct SelectionHighlight(pub Option<Style>);
+
+#[derive(Default)]
+pub(crate) struct DragState {
+    // The button that's held, where it was pressed and where it is now
+    pressed: Option<(MouseButton, (i32, i32))>,
+    current: Option<(i32, i32)>,
+}
+
+/// Turns mouse presses, drags, and releases into selection events
+pub(crate) fn track_mouse_selection(
+    mut state: Local<DragState>,
+    mut mouse: EventReader<WorldMouseEvent>,
+    mut selections: EventWriter<MouseSelection>,
+) {
+    for event in mouse.read() {
+        let cell = (event.x, event.y);
+        match event.event.kind {
+            MouseEventKind::Down(button) => {
+                state.pressed = Some((button, cell));
+                state.current = None;
+            }
+            MouseEventKind::Drag(button) => {
+                let Some((held, start)) = state.pressed else {
+                    continue;
+                };
+                if held != button || state.current == Some(cell) {
+                    continue;
+                }
+                if state.current.is_none() {
+                    selections.send(MouseSelection::Started { button, start });
+                }
+                state.current = Some(cell);
+                selections.send(MouseSelection::Dragged {
+                    button,
+                    start,
+                    current: cell,
+                });
+            }
+            MouseEventKind::Up(button) => {
+                if let (Some((held, start)), Some(current)) = (state.pressed, state.current) {
+                    if held == button {
+                        selections.send(MouseSelection::Finished {
+                            button,
+                            rect: SelectionRect::from_corners(start, current),
+                        });
+                    }
+                }
+                state.pressed = None;
+                state.current = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn outline(width: usize, height: usize) -> String {
+    match (width, height) {
+        (1, 1) => "□".to_string(),
+        (width, 1) => "─".repeat(width),
+        (1, height) => vec!["│"; height].join("\n"),
+        (width, height) => {
+            let mut lines = Vec::with_capacity(height);
+            lines.push(format!("┌{}┐", "─".repeat(width - 2)));
+            for _ in 2..height {
+                lines.push(format!("│{}│", " ".repeat(width - 2)));
+            }
+            lines.push(format!("└{}┘", "─".repeat(width - 2)));
+            lines.join("\n")
+        }
+    }
+}
+
+/// Keeps an outline entity over the current selection if highlighting is enabled
+pub(crate) fn update_selection_highlight(
+    mut commands: Commands,
+    mut overlay: Local<Option<(Entity, Handle<Sprite>)>>,
+    highlight: Res<SelectionHighlight>,
+    mut selections: EventReader<MouseSelection>,
+    mut sprites: ResMut<Assets<Sprite>>,
+    mut stylemaps: ResMut<Assets<StyleMap>>,
+    mut positions: Query<&mut Position>,
+) {
+    for selection in selections.read() {
+        match *selection {
+            MouseSelection::Started { .. } => {}
+            MouseSelection::Dragged { start, current, .. } => {
+                let Some(style) = highlight.0 else {
+                    continue;
+                };
+                let rect = SelectionRect::from_corners(start, current);
+                let data = outline(rect.width as usize, rect.height as usize);
+                let position = Position::new(rect.x, rect.y, i32::MAX);
+
+                match &*overlay {
+                    Some((entity, sprite)) => {
+                        if let Some(sprite) = sprites.get_mut(sprite) {
+                            sprite.update(data);
+                        }
+                        if let Ok(mut pos) = positions.get_mut(*entity) {
+                            *pos = position;
+                        }
+                    }
+                    None => {
+                        let sprite = sprites.add(Sprite::new(data));
+                        let entity = commands
+                            .spawn(SpriteBundle {
+                                sprite: sprite.clone(),
+                                position,
+                                stylemap: stylemaps.add(StyleMap::new(style, vec![])),
+                                visible: Visible::transparent(),
+                            })
+                            .id();
+                        *overlay = Some((entity, sprite));
+                    }
+                }
+            }
+            MouseSelection::Finished { .. } => {
+                if let Some((entity, _)) = overlay.take() {
+                    commands.entity(entity).despawn();
+                }
+            }
+        }
+    }
+}
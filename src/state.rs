@@ -0,0 +1,43 @@
+//! Tidying up after app states.
+
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+
+/// Despawns the entity, along with its children, when the app leaves state `S`. Saves writing a teardown system for
+/// every [`OnExit`] schedule. Needs a [`DespawnOnStateExitPlugin`] for `S`, added after the state is
+#[derive(Component, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DespawnOnStateExit<S: States>(pub S);
+
+/// Despawns entities with a [`DespawnOnStateExit<S>`] when their state is left. Add one for each states type
+pub struct DespawnOnStateExitPlugin<S>(PhantomData<S>);
+
+impl<S> Default for DespawnOnStateExitPlugin<S> {
+    fn default() -> Self {
+        DespawnOnStateExitPlugin(PhantomData)
+    }
+}
+
+impl<S: States> Plugin for DespawnOnStateExitPlugin<S> {
+    fn build(&self, app: &mut App) {
+        // After the transition, so OnExit systems still see the entities
+        app.add_systems(
+            StateTransition,
+            despawn_on_state_exit::<S>.after(apply_state_transition::<S>),
+        );
+    }
+}
+
+fn despawn_on_state_exit<S: States>(
+    mut commands: Commands,
+    mut transitions: EventReader<StateTransitionEvent<S>>,
+    entities: Query<(Entity, &DespawnOnStateExit<S>)>,
+) {
+    for transition in transitions.read() {
+        for (entity, scope) in &entities {
+            if scope.0 == transition.before {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
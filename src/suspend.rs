@@ -0,0 +1,171 @@
+//! Handing the terminal over to other programs and crates for a while, and taking it back afterwards.
+
+use std::process::ExitStatus;
+
+use bevy::ecs::system::Command;
+use bevy::prelude::*;
+
+use crate::{CrosstermWindow, ForceRedraw};
+
+/// Sent once a process run with [`TerminalSuspend::process`] has finished
+#[derive(Event, Debug)]
+pub struct ProcessExited(pub std::io::Result<ExitStatus>);
+
+/// A command that hands the terminal back to the shell while something else runs, for example the user's `$EDITOR`.
+///
+/// While suspended the terminal is in its normal (cooked) mode on the main screen. Afterwards the game's terminal state
+/// is restored and everything is redrawn. The app doesn't update while suspended.
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_crossterm::prelude::*;
+/// fn edit_notes(mut commands: Commands) {
+///     let mut editor = std::process::Command::new(std::env::var("EDITOR").unwrap_or("vi".into()));
+///     editor.arg("notes.txt");
+///     commands.add(TerminalSuspend::process(editor));
+/// }
+/// ```
+pub struct TerminalSuspend {
+    action: Box<dyn FnOnce(&mut World) + Send>,
+}
+
+impl TerminalSuspend {
+    /// Run the closure with the terminal suspended. It has access to the world so it can store whatever it produced
+    pub fn new(action: impl FnOnce(&mut World) + Send + 'static) -> TerminalSuspend {
+        TerminalSuspend {
+            action: Box::new(action),
+        }
+    }
+
+    /// Run a child process with the terminal suspended and wait for it to exit. A [`ProcessExited`] event is sent with
+    /// its exit status
+    pub fn process(mut command: std::process::Command) -> TerminalSuspend {
+        TerminalSuspend::new(move |world| {
+            let status = command.status();
+            world.send_event(ProcessExited(status));
+        })
+    }
+}
+
+impl Command for TerminalSuspend {
+    fn apply(self, world: &mut World) {
+        let mut windows = world.query::<(Entity, &CrosstermWindow)>();
+        let Ok((_, window)) = windows.get_single(world) else {
+            // Nothing to suspend
+            (self.action)(world);
+            return;
+        };
+
+        window.leave_terminal();
+        window.leave_alternate_screen(true);
+        let raw_mode = window.raw_mode();
+        if raw_mode {
+            window
+                .backend
+                .lock()
+                .disable_raw_mode()
+                .expect("Could not disable raw mode");
+        }
+
+        (self.action)(world);
+
+        let (bevy_window, window) = windows.single(world);
+        if raw_mode {
+            window
+                .backend
+                .lock()
+                .enable_raw_mode()
+                .expect("Could not enable crossterm raw mode");
+        }
+        window.enter_terminal();
+        catch_up(world, bevy_window);
+
+        // Any keys held when we left were released without us seeing it
+        crate::runner::update_modifiers(
+            world,
+            bevy_window,
+            crossterm::event::KeyModifiers::empty(),
+        );
+    }
+}
+
+/// Lets other crates write to the terminal directly, like an embedded ratatui screen or a progress bar library.
+///
+/// While the guard is held nothing is drawn, and the terminal is the other crate's to write to: normally that's
+/// stdout. It's left in raw mode, on the alternate screen if the app uses it, with mouse capture and the rest of the
+/// app's terminal setup undone. Input is still read by the app, so keep handling it in systems. Once the guard is
+/// released, the app's terminal setup is restored and everything is redrawn.
+///
+/// [`acquire`](RawTerminalGuard::acquire) and [`release`](RawTerminalGuard::release) take effect at the end of the
+/// frame, once the frame has been written. Only write to the terminal while [`is_held`](RawTerminalGuard::is_held):
+///
+/// ```no_run
+/// # use std::io::Write;
+/// # use bevy::prelude::*;
+/// # use bevy_crossterm::prelude::*;
+/// fn draw_progress(guard: Res<RawTerminalGuard>) {
+///     if guard.is_held() {
+///         print!("\r\x1b[2Kloading...");
+///         std::io::stdout().flush().unwrap();
+///     }
+/// }
+/// ```
+#[derive(Resource, Debug, Default)]
+pub struct RawTerminalGuard {
+    held: bool,
+    wanted: bool,
+}
+
+impl RawTerminalGuard {
+    /// Stop drawing and hand the terminal over, from the end of this frame
+    pub fn acquire(&mut self) {
+        self.wanted = true;
+    }
+
+    /// Take the terminal back and redraw everything, at the end of this frame
+    pub fn release(&mut self) {
+        self.wanted = false;
+    }
+
+    /// Whether the terminal can be written to
+    pub fn is_held(&self) -> bool {
+        self.held
+    }
+}
+
+/// Hands the terminal over to or takes it back from whoever holds the [`RawTerminalGuard`]
+pub(crate) fn update_raw_terminal_guard(world: &mut World) {
+    let guard = world.resource::<RawTerminalGuard>();
+    if guard.held == guard.wanted {
+        return;
+    }
+    let held = guard.wanted;
+    world.resource_mut::<RawTerminalGuard>().held = held;
+
+    let mut windows = world.query::<(Entity, &CrosstermWindow)>();
+    let Ok((bevy_window, window)) = windows.get_single(world) else {
+        return;
+    };
+    if held {
+        // Waits for the frames on their way to the terminal, so the other crate's output comes after them
+        window.leave_terminal();
+        window.backend.set_paused(true);
+    } else {
+        window.backend.set_paused(false);
+        window.enter_terminal();
+        catch_up(world, bevy_window);
+    }
+}
+
+/// Catch up on what happened while the terminal was someone else's, and redraw over whatever they left on it
+fn catch_up(world: &mut World, bevy_window: Entity) {
+    // The terminal might have been resized while we weren't listening
+    let window = world.get::<CrosstermWindow>(bevy_window).unwrap();
+    let (width, height) = window
+        .backend
+        .lock()
+        .size()
+        .expect("Could not read current terminal size");
+    crate::runner::resize_window(world, bevy_window, width, height);
+    world.send_event(ForceRedraw);
+}
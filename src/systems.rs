@@ -1,15 +1,27 @@
-use std::{convert::TryInto, io::Write};
+use std::convert::TryInto;
 
-use crate::components::{self, Style};
+use crate::camera::TerminalCamera;
+use crate::capture::Screen;
+use crate::components::{self, BlendMode, Color, Style};
 use crate::components::{
-    Colors, Position, PreviousEntityDetails, PreviousWindowColors, Sprite, StyleMap,
+    ColoredSprite, Colors, FrameOutput, Position, PreviousEntityDetails, PreviousWindowColors,
+    Sprite, StyleMap,
 };
-use crate::{CrosstermWindow, Cursor};
+use crate::pipeline::PipelinedRendering;
+use crate::render::Cell;
+use crate::render_graph::RenderGraph;
+use crate::theme::Theme;
+use crate::{CrosstermWindow, Cursor, ForceRedraw, TerminalCommands};
 
 use bevy::prelude::*;
+use bevy::tasks::{ComputeTaskPool, TaskPool};
+use bevy::utils::{HashMap, HashSet};
 use bevy::window::WindowResized;
 use bevy_asset::{AssetEvent, Assets, Handle};
+use crossterm::style::{Attribute, Attributes};
 use crossterm::{queue, QueueableCommand};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 /// Records the initial position/size for every new entity
 pub(crate) fn add_previous_position(
@@ -69,13 +81,24 @@ pub(crate) fn add_previous_position(
     }
 }
 
-/// Records the current position for every known entity
+/// Records the current position for every known entity that moved or changed its sprite. Entities that stayed put
+/// already have the right details, so a scene that isn't changing costs next to nothing
+#[allow(clippy::type_complexity)]
 pub(crate) fn update_previous_position(
     mut previous_details: ResMut<PreviousEntityDetails>,
     frames: Res<Assets<Sprite>>,
-    mut positions: Query<(Entity, &Position, &Handle<Sprite>, &components::Visible)>,
+    mut sprite_events: EventReader<AssetEvent<Sprite>>,
+    changed: Query<
+        (Entity, &Position, &Handle<Sprite>),
+        (
+            With<components::Visible>,
+            Or<(Changed<Position>, Changed<Handle<Sprite>>)>,
+        ),
+    >,
+    all: Query<(Entity, &Position, &Handle<Sprite>), With<components::Visible>>,
+    mut removed: RemovedComponents<Handle<Sprite>>,
 ) {
-    for (entity, new_pos, sprite, _) in &mut positions {
+    let mut record = |entity: Entity, new_pos: &Position, sprite: &Handle<Sprite>| {
         if let Some(sprite) = frames.get(sprite) {
             let prev_pos = components::PreviousPosition {
                 x: new_pos.x,
@@ -91,6 +114,31 @@ pub(crate) fn update_previous_position(
                 *value = (prev_pos, prev_size);
             }
         }
+    };
+
+    for (entity, new_pos, sprite) in &changed {
+        record(entity, new_pos, sprite);
+    }
+
+    // Sprites that changed shape resize every entity using them. Only look for those entities if there are any
+    let modified: HashSet<AssetId<Sprite>> = sprite_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+    if !modified.is_empty() {
+        for (entity, new_pos, sprite) in &all {
+            if modified.contains(&sprite.id()) {
+                record(entity, new_pos, sprite);
+            }
+        }
+    }
+
+    // Despawned entities have been cleared from the screen by now, so they can be forgotten
+    for entity in removed.read() {
+        previous_details.0.remove(&entity);
     }
 }
 
@@ -106,21 +154,11 @@ fn changed_assets<T: bevy_asset::Asset>(
 
     for evt in asset_events.get_reader().read(asset_events) {
         match evt {
-            AssetEvent::LoadedWithDependencies { id } => {
-                for cmp in assets.iter() {
-                    if &cmp.0 == id {
-                        created.insert(*id);
-                        break;
-                    }
-                }
+            AssetEvent::LoadedWithDependencies { id } if assets.contains(*id) => {
+                created.insert(*id);
             }
-            AssetEvent::Modified { id } => {
-                for cmp in assets.iter() {
-                    if &cmp.0 == id {
-                        changed.insert(*id);
-                        break;
-                    }
-                }
+            AssetEvent::Modified { id } if assets.contains(*id) => {
+                changed.insert(*id);
             }
             _ => {}
         }
@@ -129,13 +167,20 @@ fn changed_assets<T: bevy_asset::Asset>(
     (created, changed)
 }
 
+// The fewest rects handed to each task when looking for overlaps. Below this, spreading the work out costs more than
+// it saves
+const MIN_OVERLAP_CHUNK: usize = 64;
+
 /// Calculates which entities need to be redrawn
 pub(crate) fn calculate_entities_to_redraw(
+    mut was_too_small: Local<bool>,
     mut prev_colors: ResMut<PreviousWindowColors>,
     mut entities: ResMut<components::EntitiesToRedraw>,
     previous_details: Res<PreviousEntityDetails>,
     window: Query<&CrosstermWindow>,
     resize_events: Res<Events<WindowResized>>,
+    mut force_redraw: EventReader<ForceRedraw>,
+    camera: Query<(), Changed<TerminalCamera>>,
     sprites: Res<Assets<Sprite>>,
     stylemaps: Res<Assets<StyleMap>>,
     sprite_asset_events: Res<Events<AssetEvent<Sprite>>>,
@@ -148,14 +193,22 @@ pub(crate) fn calculate_entities_to_redraw(
         &components::Visible,
     )>,
     mut removed: RemovedComponents<Handle<Sprite>>,
+    // Entities without a sprite, like the parent of a group of sprites, aren't drawn
     changed: Query<
         Entity,
-        Or<(
-            Changed<Position>,
-            Changed<Handle<StyleMap>>,
-            Changed<components::Visible>,
-            Changed<Handle<Sprite>>,
-        )>,
+        (
+            Or<(
+                Changed<Position>,
+                Changed<Handle<StyleMap>>,
+                Changed<components::Visible>,
+                Changed<Handle<Sprite>>,
+                Changed<BlendMode>,
+            )>,
+            With<Position>,
+            With<Handle<StyleMap>>,
+            With<components::Visible>,
+            With<Handle<Sprite>>,
+        ),
     >,
     added: Query<
         Entity,
@@ -180,8 +233,27 @@ pub(crate) fn calculate_entities_to_redraw(
 
     let mut draw_set = bevy::utils::HashSet::default();
 
-    // If a resize happened the whole screen is invalidated
-    if !resize_events.get_reader().is_empty(&resize_events) || window.colors != prev_colors.0 {
+    // Any forced redraw requests are handled together, there's no point in redrawing twice. Moving the camera
+    // shifts everything on screen, so that needs a full redraw as well
+    let mut forced = !force_redraw.is_empty() || !camera.is_empty();
+    force_redraw.clear();
+
+    // Nothing gets drawn while the terminal is too small, and once it's big enough again everything has to be
+    // drawn over the top of the message that was shown in the meantime
+    if window.is_too_small() {
+        *was_too_small = true;
+        return;
+    }
+    if *was_too_small {
+        *was_too_small = false;
+        forced = true;
+    }
+
+    // If a resize happened (or someone asked for it) the whole screen is invalidated
+    if forced
+        || !resize_events.get_reader().is_empty(&resize_events)
+        || window.colors != prev_colors.0
+    {
         // We need a full redraw, so flag a full update and bail early
         // No need to do fancy update calculations
         entities.full_redraw = true;
@@ -205,8 +277,12 @@ pub(crate) fn calculate_entities_to_redraw(
         changed_assets(&stylemap_asset_events, &stylemaps);
 
     // Collect all the entities that changed this update, either because their asset did,
-    // or their components did
-    for (entity, style_hnd, sprite_hnd, _, _) in all.iter() {
+    // or their components did. Most frames no assets change, and there's no need to look at every entity
+    let any_assets_changed = !created_sprite_assets.is_empty()
+        || !changed_sprite_assets.is_empty()
+        || !created_stylemap_assets.is_empty()
+        || !changed_stylemap_assets.is_empty();
+    for (entity, style_hnd, sprite_hnd, _, _) in all.iter().filter(|_| any_assets_changed) {
         if changed_sprite_assets.contains(&sprite_hnd.id())
             || changed_stylemap_assets.contains(&style_hnd.id())
         {
@@ -231,9 +307,13 @@ pub(crate) fn calculate_entities_to_redraw(
     // Find all entities that either became invisible, or changed their size or moved. (cleared is good enough for now)
     // Figure out what their previous bounding box is and query all current positions to see what sprites are under it
     // Add the collided entities to draw_set
-    let mut collided_entities = Vec::new();
+    // Only entities that were already on screen can uncover anything, so if none of them changed there's no need to
+    // look at where everything is
     let mut bboxes = Vec::new();
-    for (entity, _, sprite, pos, _) in all.iter() {
+    let any_uncovered = changed
+        .iter()
+        .any(|entity| previous_details.0.contains_key(&entity));
+    for (entity, _, sprite, pos, _) in all.iter().filter(|_| any_uncovered) {
         let sprite_data = sprites.get(sprite);
         if sprite_data.is_none() {
             continue;
@@ -252,58 +332,53 @@ pub(crate) fn calculate_entities_to_redraw(
     }
 
     let broccoli = broccoli::new(&mut bboxes);
-    for ent in changed.iter() {
-        let prev_data = previous_details.0.get(&ent);
-        if prev_data.is_none() {
-            continue;
-        }
-        let (prev_pos, prev_size) = prev_data.unwrap();
-        let blank_bb = broccoli::rect(
-            prev_pos.x,
-            prev_pos.x + prev_size.width as i32,
-            prev_pos.y,
-            prev_pos.y + prev_size.height as i32,
-        );
-        // dbg!("checking for collision", ent, prev_pos);
-        broccoli.for_all_intersect_rect(&blank_bb, |bb| {
-            if ent == bb.inner {
-                return;
-            }
-            // dbg!("Found Entity: ", bb.inner);
-            if !draw_set.contains(&bb.inner) {
-                draw_set.insert(bb.inner);
-                collided_entities.push(bb.inner);
+    let broccoli = &broccoli;
+
+    // Work through the overlaps a wave at a time: whatever the changed entities uncovered, then whatever those
+    // overlapped last frame, and so on. Every rect in a wave can be checked on its own, so big waves are split across
+    // the compute task pool
+    let pool = ComputeTaskPool::get_or_init(TaskPool::default);
+    let mut wave: Vec<Entity> = changed.iter().collect();
+    while !wave.is_empty() {
+        let blank_bbs: Vec<_> = wave
+            .iter()
+            .filter_map(|ent| {
+                let (prev_pos, prev_size) = previous_details.0.get(ent)?;
+                let blank_bb = broccoli::rect(
+                    prev_pos.x,
+                    prev_pos.x + prev_size.width as i32,
+                    prev_pos.y,
+                    prev_pos.y + prev_size.height as i32,
+                );
+                Some((*ent, blank_bb))
+            })
+            .collect();
+
+        let chunk_size = blank_bbs
+            .len()
+            .div_ceil(pool.thread_num().max(1))
+            .max(MIN_OVERLAP_CHUNK);
+        let overlapping = pool.scope(|scope| {
+            for chunk in blank_bbs.chunks(chunk_size) {
+                scope.spawn(async move {
+                    let mut overlapping = Vec::new();
+                    for (ent, blank_bb) in chunk {
+                        broccoli.for_all_intersect_rect(blank_bb, |bb| {
+                            if *ent != bb.inner {
+                                overlapping.push(bb.inner);
+                            }
+                        });
+                    }
+                    overlapping
+                });
             }
         });
-    }
 
-    let mut cur_index = 0;
-    while cur_index < collided_entities.len() {
-        let ent = collided_entities[cur_index];
-        cur_index += 1;
-
-        let prev_data = previous_details.0.get(&ent);
-        if prev_data.is_none() {
-            continue;
-        }
-        let (prev_pos, prev_size) = prev_data.unwrap();
-        let blank_bb = broccoli::rect(
-            prev_pos.x,
-            prev_pos.x + prev_size.width as i32,
-            prev_pos.y,
-            prev_pos.y + prev_size.height as i32,
-        );
-        // dbg!("checking for collision", ent, prev_pos);
-        broccoli.for_all_intersect_rect(&blank_bb, |bb| {
-            if ent == bb.inner {
-                return;
-            }
-            // dbg!("Found Entity: ", bb.inner);
-            if !draw_set.contains(&bb.inner) {
-                draw_set.insert(bb.inner);
-                collided_entities.push(bb.inner);
-            }
-        });
+        wave = overlapping
+            .into_iter()
+            .flatten()
+            .filter(|ent| draw_set.insert(*ent))
+            .collect();
     }
 
     entities.to_clear.extend(removed.read());
@@ -317,45 +392,204 @@ pub(crate) fn calculate_entities_to_redraw(
     entities.to_draw.sort_by_key(|item| item.z);
 }
 
+/// What the terminal's style and cursor position are partway through writing a frame, once they're known, so
+/// they're only changed when they have to be
+#[derive(Debug, Default)]
+pub(crate) struct TerminalState {
+    pub(crate) style: Option<Style>,
+    pub(crate) cursor: Option<(u16, u16)>,
+}
+
+impl TerminalState {
+    /// Move the cursor to `x`,`y`. Nothing is sent if it's already there, and moving forwards along a row is
+    /// shorter than giving the whole position
+    pub(crate) fn move_to(&mut self, term: &mut Vec<u8>, x: u16, y: u16) -> std::io::Result<()> {
+        match self.cursor {
+            Some(cursor) if cursor == (x, y) => {}
+            Some((column, row)) if row == y && column < x => {
+                term.queue(crossterm::cursor::MoveRight(x - column))?;
+            }
+            _ => {
+                term.queue(crossterm::cursor::MoveTo(x, y))?;
+            }
+        }
+        self.cursor = Some((x, y));
+        Ok(())
+    }
+
+    /// Keep track of the cursor moving past text that was just printed on a terminal `width` columns wide. Wide
+    /// graphemes and the last column leave it somewhere that depends on the terminal, so the next move goes to the
+    /// whole position
+    pub(crate) fn printed(&mut self, width: u16, text: &str) {
+        let cells = text.graphemes(true).count();
+        self.cursor = self.cursor.and_then(|(x, y)| {
+            let x = x as usize + cells;
+            (text.width() == cells && x < width as usize).then_some((x as u16, y))
+        });
+    }
+}
+
+/// Switch the terminal to `style` from scratch
+pub(crate) fn set_style(
+    term: &mut Vec<u8>,
+    window: &CrosstermWindow,
+    style: &Style,
+) -> Result<(), Box<dyn std::error::Error>> {
+    queue!(
+        term,
+        crossterm::style::SetAttribute(crossterm::style::Attribute::Reset),
+        crossterm::style::SetAttributes(window.terminal_attributes(style.attributes)),
+        crossterm::style::SetColors(window.to_crossterm(style.colors))
+    )?;
+    if let Some(color) = window.underline_color(style.underline_color) {
+        term.queue(crossterm::style::SetUnderlineColor(color))?;
+    }
+    Ok(())
+}
+
 /// Helper function for `draw_entity` which determines whether the style on the terminal should be
-/// changed
-fn change_style_if_needed(
-    term: &mut std::io::StdoutLock,
+/// changed. `previous_style` holds what's actually on the terminal, so colors the new style leaves unset carry over
+pub(crate) fn change_style_if_needed(
+    term: &mut Vec<u8>,
+    window: &CrosstermWindow,
     previous_style: &mut Style,
     current_style: &Style,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if current_style.attributes != previous_style.attributes {
-        term.queue(crossterm::style::SetAttributes(current_style.attributes))?;
-        previous_style.attributes = current_style.attributes;
-    }
-    if current_style.colors != previous_style.colors {
-        term.queue(crossterm::style::SetColors(
-            current_style.colors.to_crossterm(),
-        ))?;
-        previous_style.colors = current_style.colors;
+    let style = Style {
+        colors: current_style.colors.with_default(previous_style.colors),
+        ..*current_style
+    };
+    // Reset is how styles say they have no attributes, rather than one to send
+    let terminal_attributes = |attributes| {
+        let mut attributes = window.terminal_attributes(attributes);
+        attributes.unset(Attribute::Reset);
+        attributes
+    };
+    let attributes = terminal_attributes(style.attributes);
+    let previous_attributes = terminal_attributes(previous_style.attributes);
+    if style.attributes != previous_style.attributes
+        && attributes & previous_attributes != previous_attributes
+    {
+        // Attributes can only be turned off all at once, which also resets the colors
+        set_style(term, window, &style)?;
+    } else {
+        // Attributes that are only being added can be turned on without touching the rest
+        if style.attributes != previous_style.attributes && attributes != previous_attributes {
+            term.queue(crossterm::style::SetAttributes(
+                attributes ^ previous_attributes,
+            ))?;
+        }
+        // Only send the colors that changed
+        let colors = window.to_crossterm(style.colors);
+        let previous_colors = window.to_crossterm(previous_style.colors);
+        match (
+            colors.foreground != previous_colors.foreground,
+            colors.background != previous_colors.background,
+        ) {
+            (true, true) => {
+                term.queue(crossterm::style::SetColors(colors))?;
+            }
+            (true, false) => {
+                if let Some(color) = colors.foreground {
+                    term.queue(crossterm::style::SetForegroundColor(color))?;
+                }
+            }
+            (false, true) => {
+                if let Some(color) = colors.background {
+                    term.queue(crossterm::style::SetBackgroundColor(color))?;
+                }
+            }
+            (false, false) => {}
+        }
+        // Terminals that can't color underlines are never sent one, not even to reset it
+        let underline_color = window.underline_color(style.underline_color);
+        if underline_color != window.underline_color(previous_style.underline_color) {
+            term.queue(crossterm::style::SetUnderlineColor(
+                underline_color.unwrap_or(Color::Reset),
+            ))?;
+        }
     }
+    *previous_style = style;
     Ok(())
 }
 
+/// Work out what to draw in a cell for a sprite with a [`BlendMode`], given what's already `below` it on the
+/// screen. `previous_style` is what's on the terminal, which fills in the colors `style` leaves unset. Every mode
+/// gives the same result when drawn again over its own output, so redrawing a blended sprite in place is safe
+pub(crate) fn blend<'a>(
+    mode: BlendMode,
+    grapheme: &'a str,
+    style: &Style,
+    previous_style: &Style,
+    below: Option<&'a Cell>,
+) -> (&'a str, Style) {
+    let style = Style {
+        colors: style.colors.with_default(previous_style.colors),
+        ..*style
+    };
+    let Some(below) = below else {
+        return (grapheme, style);
+    };
+    // Colors the terminal was left to choose are its defaults
+    let below_style = Style {
+        colors: below.style.colors.with_default(Colors::term_colors()),
+        ..below.style
+    };
+    match mode {
+        BlendMode::Replace => (grapheme, style),
+        BlendMode::Foreground => (
+            grapheme,
+            Style {
+                colors: Colors {
+                    background: below_style.colors.background,
+                    ..style.colors
+                },
+                ..style
+            },
+        ),
+        BlendMode::Background => (
+            &below.grapheme,
+            Style {
+                colors: Colors {
+                    background: style.colors.background,
+                    ..below_style.colors
+                },
+                ..below_style
+            },
+        ),
+        BlendMode::Invert => {
+            let mut style = below_style;
+            style.attributes.set(Attribute::Reverse);
+            (&below.grapheme, style)
+        }
+    }
+}
+
 fn draw_entity(
     entity: Entity,
-    term: &mut std::io::StdoutLock,
+    term: &mut Vec<u8>,
     window: &CrosstermWindow,
+    camera: &TerminalCamera,
     sprites: &Res<Assets<Sprite>>,
     stylemaps: &Res<Assets<StyleMap>>,
+    theme: &Theme,
+    screen: &Screen,
+    terminal: &mut TerminalState,
     all: &Query<(
         Entity,
         &Position,
         &Handle<StyleMap>,
         &components::Visible,
         &Handle<Sprite>,
+        Option<&BlendMode>,
     )>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let entity_data = all.get(entity);
     if entity_data.is_err() {
         return Ok(());
     }
-    let (_, pos, style, draw, sprite) = entity_data.unwrap();
+    let (_, pos, style, draw, sprite, blend_mode) = entity_data.unwrap();
+    let blend_mode = blend_mode.copied().unwrap_or_default();
 
     // If the entity isn't visible, skip it
     if !draw.is_visible {
@@ -369,10 +603,15 @@ fn draw_entity(
     }
     let sprite = sprite.unwrap();
 
+    // Everything from here on is relative to the viewport
+    let (x, y) = camera.world_to_viewport(pos.x, pos.y);
+    let pos = Position::new(x, y, pos.z);
+
     // If the entity's not on the screen, skip it
-    if pos.y >= window.height.into()
+    let view = window.viewport();
+    if pos.y >= view.height.into()
         || pos.y + sprite.height() as i32 <= 0
-        || pos.x >= window.width.into()
+        || pos.x >= view.width.into()
         || pos.x + sprite.width() as i32 <= 0
     {
         return Ok(());
@@ -383,17 +622,17 @@ fn draw_entity(
         // The stylemap asset hasn't loaded yet, this isn't a problem
         return Ok(());
     }
-    let stylemap = stylemap.unwrap();
-    let sprite_colors = stylemap.style.colors.with_default(window.colors);
-
-    queue!(
-        term,
-        crossterm::style::SetAttribute(crossterm::style::Attribute::Reset),
-        crossterm::style::SetAttributes(stylemap.style.attributes),
-        crossterm::style::SetColors(sprite_colors.to_crossterm())
-    )?;
-
-    let mut previous_style = stylemap.style;
+    let stylemap = stylemap.unwrap().resolve_pairs(theme);
+    let stylemap = stylemap.resolve_spans(sprite);
+    let base_style = stylemap.base_style(theme);
+    let mut previous_style = Style {
+        colors: base_style.colors.with_default(window.colors),
+        ..base_style
+    };
+    // Sprites next to each other often share a style, which the terminal has already
+    if terminal.style != Some(previous_style) {
+        set_style(term, window, &previous_style)?;
+    }
 
     for (line_num, line) in sprite.graphemes().iter().enumerate() {
         let line_offset: i32 = line_num.try_into()?;
@@ -405,22 +644,19 @@ fn draw_entity(
 
         // If this line is off the bottom of the screen, break out since no lines can ever
         // be on the screen ever again
-        if pos.y + line_offset >= window.height.into() {
+        if pos.y + line_offset >= view.height.into() {
             break;
         }
 
         // Calculate the beginning and end of string sprite, to not render things off screen
         let start: i32 = std::cmp::max(0, pos.x);
-        let end: i32 = std::cmp::min(window.width as i32, pos.x + line.len() as i32);
+        let end: i32 = std::cmp::min(view.width as i32, pos.x + line.len() as i32);
 
         let start_idx: usize = (start - pos.x).try_into()?;
         let end_idx: usize = (end - pos.x).try_into()?;
 
-        term.queue(crossterm::cursor::MoveTo(
-            start.try_into()?,
-            (pos.y + line_offset).try_into()?,
-        ))?;
-
+        // The cursor is only moved right before a cell is drawn, so hidden cells are skipped over all at once
+        let row = (pos.y + line_offset + view.y as i32) as u16;
         let graphemes = &line[start_idx..end_idx];
         if !graphemes.is_empty() {
             // Go through each grapheme one by one to make sure we have the correct style and color
@@ -428,54 +664,100 @@ fn draw_entity(
             for (i, grapheme) in graphemes.iter().enumerate() {
                 let idx = start_idx + i;
 
-                // If the grapheme is a transparent space with no style, skip rendering it
-                if draw.is_transparent
-                    && stylemap.style_at(idx, line_num).is_none()
-                    && sprite.grapheme(grapheme) == " "
-                {
-                    term.queue(crossterm::cursor::MoveRight(1))?;
+                // If the sprite's mask hides the grapheme, or it's the transparent character with no style, skip
+                // rendering it
+                let hidden = sprite.is_masked(idx, line_num).unwrap_or_else(|| {
+                    draw.is_transparent
+                        && stylemap.style_at(idx, line_num).is_none()
+                        && draw.is_transparent_char(sprite.grapheme(grapheme))
+                });
+                if hidden {
                     continue;
                 }
 
                 // Get the style we need to render this grapheme with
-                let grapheme_style = stylemap.style_for(idx, line_num);
-                change_style_if_needed(term, &mut previous_style, &grapheme_style)?;
+                let grapheme_style = stylemap
+                    .style_at(idx, line_num)
+                    .copied()
+                    .unwrap_or(base_style);
+                let column = (start + view.x as i32) as u16 + i as u16;
+                terminal.move_to(term, column, row)?;
+                if blend_mode != BlendMode::Replace {
+                    let (grapheme, grapheme_style) = blend(
+                        blend_mode,
+                        sprite.grapheme(grapheme),
+                        &grapheme_style,
+                        &previous_style,
+                        screen.get(column, row),
+                    );
+                    change_style_if_needed(term, window, &mut previous_style, &grapheme_style)?;
+                    term.queue(crossterm::style::Print(grapheme))?;
+                    terminal.printed(window.width, grapheme);
+                    continue;
+                }
+                change_style_if_needed(term, window, &mut previous_style, &grapheme_style)?;
 
-                term.queue(crossterm::style::Print(&sprite.grapheme(grapheme)))?;
+                let grapheme = sprite.grapheme(grapheme);
+                term.queue(crossterm::style::Print(grapheme))?;
+                terminal.printed(window.width, grapheme);
             }
         }
 
         // Lines don't have to go to the end of the sprite. Pad them out so the sprite is rectangular
-        if end < window.width as i32 && line.len() < sprite.width() {
+        if end < view.width as i32 && line.len() < sprite.width() {
             let unaccounted = sprite.width() - line.len();
-            let blank_length = std::cmp::min(unaccounted, (window.width as i32 - end) as usize);
+            let blank_length = std::cmp::min(unaccounted, (view.width as i32 - end) as usize);
             let blank_str = str::repeat(" ", blank_length);
             for (i, space) in blank_str.chars().enumerate() {
                 let idx = end_idx + i;
 
-                // If the filler space is transparent and has no style, skip it
-                if draw.is_transparent && stylemap.style_at(idx, line_num).is_none() {
-                    term.queue(crossterm::cursor::MoveRight(1))?;
+                // If the filler space is masked, or transparent and has no style, skip it
+                let hidden = sprite.is_masked(idx, line_num).unwrap_or_else(|| {
+                    draw.is_transparent && stylemap.style_at(idx, line_num).is_none()
+                });
+                if hidden {
                     continue;
                 }
 
                 // Get the style we need to render this space with
-                let grapheme_style = stylemap.style_for(idx, line_num);
-                change_style_if_needed(term, &mut previous_style, &grapheme_style)?;
+                let grapheme_style = stylemap
+                    .style_at(idx, line_num)
+                    .copied()
+                    .unwrap_or(base_style);
+                let column = (end + view.x as i32) as u16 + i as u16;
+                terminal.move_to(term, column, row)?;
+                if blend_mode != BlendMode::Replace {
+                    let (grapheme, grapheme_style) = blend(
+                        blend_mode,
+                        " ",
+                        &grapheme_style,
+                        &previous_style,
+                        screen.get(column, row),
+                    );
+                    change_style_if_needed(term, window, &mut previous_style, &grapheme_style)?;
+                    term.queue(crossterm::style::Print(grapheme))?;
+                    terminal.printed(window.width, grapheme);
+                    continue;
+                }
+                change_style_if_needed(term, window, &mut previous_style, &grapheme_style)?;
 
                 term.queue(crossterm::style::Print(space))?;
+                terminal.printed(window.width, " ");
             }
         }
     }
 
+    terminal.style = Some(previous_style);
     Ok(())
 }
 
 fn clear_entity(
     entity: Entity,
-    term: &mut std::io::StdoutLock,
+    term: &mut Vec<u8>,
     window: &CrosstermWindow,
+    camera: &TerminalCamera,
     previous_details: &PreviousEntityDetails,
+    terminal: &mut TerminalState,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let prev_details = previous_details.0.get(&entity);
     if prev_details.is_none() {
@@ -484,6 +766,13 @@ fn clear_entity(
         return Ok(());
     }
     let (prev_pos, prev_size) = prev_details.unwrap();
+    let (x, y) = camera.world_to_viewport(prev_pos.x, prev_pos.y);
+    let prev_pos = components::PreviousPosition {
+        x,
+        y,
+        z: prev_pos.z,
+    };
+    let view = window.viewport();
 
     for height in 0..prev_size.height {
         let y = prev_pos.y + height as i32;
@@ -492,95 +781,510 @@ fn clear_entity(
             continue;
         }
 
-        if prev_pos.y >= window.height.into()
+        if y >= view.height.into()
             || prev_pos.y + prev_size.height as i32 <= 0
-            || prev_pos.x >= window.width.into()
+            || prev_pos.x >= view.width.into()
             || prev_pos.x + prev_size.width as i32 <= 0
         {
             break;
         }
 
         let x_start: i32 = std::cmp::max(0, prev_pos.x);
-        let x_end: i32 = std::cmp::min(window.width as i32, prev_pos.x + prev_size.width as i32);
+        let x_end: i32 = std::cmp::min(view.width as i32, prev_pos.x + prev_size.width as i32);
 
         let actual_width = x_end - x_start;
         let blank_string = " ".repeat(actual_width as usize);
 
-        let x = x_start.try_into()?;
-        let y = y.try_into()?;
+        let x = (x_start + view.x as i32).try_into()?;
+        let y = (y + view.y as i32).try_into()?;
+
+        let cleared = Style::new(Colors::term_colors(), Attributes::default());
+        if terminal.style != Some(cleared) {
+            queue!(
+                term,
+                crossterm::style::SetAttribute(crossterm::style::Attribute::Reset,),
+                crossterm::style::SetColors(Colors::term_colors().to_crossterm(),),
+            )?;
+            terminal.style = Some(cleared);
+        }
+        terminal.move_to(term, x, y)?;
+        term.queue(crossterm::style::Print(&blank_string))?;
+        terminal.printed(window.width, &blank_string);
+    }
 
-        queue!(
-            term,
-            crossterm::style::SetAttribute(crossterm::style::Attribute::Reset,),
-            crossterm::style::SetColors(Colors::term_colors().to_crossterm(),),
-            crossterm::cursor::MoveTo(x, y),
-            crossterm::style::Print(blank_string)
-        )?;
+    Ok(())
+}
+
+/// Fill the parts of the terminal outside of the viewport with the letterbox style
+fn draw_letterbox(
+    term: &mut Vec<u8>,
+    window: &CrosstermWindow,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let view = window.viewport();
+    if view.width == window.width && view.height == window.height {
+        return Ok(());
     }
 
+    let style = window.letterbox_style;
+    queue!(
+        term,
+        crossterm::style::SetAttribute(crossterm::style::Attribute::Reset),
+        crossterm::style::SetAttributes(style.attributes),
+        crossterm::style::SetColors(window.to_crossterm(style.colors.with_default(window.colors)))
+    )?;
+
+    let full_row = " ".repeat(window.width as usize);
+    let left = " ".repeat(view.x as usize);
+    let right = " ".repeat((window.width - view.x - view.width) as usize);
+    for y in 0..window.height {
+        if y < view.y || y >= view.y + view.height {
+            queue!(
+                term,
+                crossterm::cursor::MoveTo(0, y),
+                crossterm::style::Print(&full_row)
+            )?;
+        } else {
+            queue!(
+                term,
+                crossterm::cursor::MoveTo(0, y),
+                crossterm::style::Print(&left),
+                crossterm::cursor::MoveTo(view.x + view.width, y),
+                crossterm::style::Print(&right)
+            )?;
+        }
+    }
+
+    queue!(
+        term,
+        crossterm::style::SetAttribute(crossterm::style::Attribute::Reset),
+        crossterm::style::SetColors(window.to_crossterm(window.colors))
+    )?;
+
+    Ok(())
+}
+
+/// Clear the screen and show a centered message asking for a bigger terminal
+fn draw_too_small_message(
+    term: &mut Vec<u8>,
+    window: &CrosstermWindow,
+) -> Result<(), Box<dyn std::error::Error>> {
+    queue!(
+        term,
+        crossterm::cursor::Hide,
+        crossterm::style::SetAttribute(crossterm::style::Attribute::Reset),
+        crossterm::style::SetColors(window.to_crossterm(window.colors)),
+        crossterm::terminal::Clear(crossterm::terminal::ClearType::All)
+    )?;
+
+    let (min_width, min_height) = window.min_size.unwrap_or_default();
+    let message = format!(
+        "Please enlarge your terminal to {}x{}",
+        min_width, min_height
+    );
+    let message: String = message.chars().take(window.width as usize).collect();
+    let x = (window.width / 2).saturating_sub(message.chars().count() as u16 / 2);
+
+    queue!(
+        term,
+        crossterm::cursor::MoveTo(x, window.height / 2),
+        crossterm::style::Print(message)
+    )?;
+
     Ok(())
 }
 
 /// Draw any entity that needs to be drawn
 pub(crate) fn crossterm_render(
+    mut too_small_message: Local<Option<(u16, u16)>>,
+    mut screen: Local<Screen>,
+    graph: Option<Res<RenderGraph>>,
+    mut output: ResMut<FrameOutput>,
     changed_entities: Res<components::EntitiesToRedraw>,
     window: Query<&CrosstermWindow>,
-    cursor: Res<Cursor>,
+    camera: Query<&TerminalCamera>,
     previous_details: Res<PreviousEntityDetails>,
     sprites: Res<Assets<Sprite>>,
     stylemaps: Res<Assets<StyleMap>>,
+    theme: Res<Theme>,
     all: Query<(
         Entity,
         &Position,
         &Handle<StyleMap>,
         &components::Visible,
         &Handle<Sprite>,
+        Option<&BlendMode>,
     )>,
 ) {
     let window = window.single();
-    let stdout = std::io::stdout();
-    let mut term = stdout.lock();
+    let camera = camera.get_single().copied().unwrap_or_default();
+    let term = &mut output.0;
+    // Without a graph here, it's run by the pipelined render app
+    let compositing = graph.is_none_or(|graph| graph.is_compositing());
+
+    // Rendering is paused while the terminal is too small. Only draw the message when the size changes
+    if window.is_too_small() {
+        if *too_small_message != Some((window.width, window.height)) {
+            draw_too_small_message(term, window).unwrap();
+            *too_small_message = Some((window.width, window.height));
+        }
+        return;
+    }
+    *too_small_message = None;
 
     // If we're gonna be drawing stuff, hide the cursor so it doesn't jump all over the place
     if !changed_entities.to_draw.is_empty() {
         term.queue(crossterm::cursor::Hide).unwrap();
     }
 
+    let mut terminal = TerminalState::default();
+
     // If a resize happened, clear the screen and go from there
     if changed_entities.full_redraw {
         queue!(
             term,
             crossterm::style::SetAttribute(crossterm::style::Attribute::Reset),
+            crossterm::style::SetColors(window.to_crossterm(window.colors)),
             crossterm::terminal::Clear(crossterm::terminal::ClearType::All)
         )
         .unwrap();
-    } else {
+        draw_letterbox(term, window).unwrap();
+        terminal.style = Some(Style::new(window.colors, Attributes::default()));
+    } else if !compositing {
         // No need to clear individual entities if we just cleared the whole screen anyways.
         // Blank out all the previous locations of sprites that changed either their position or their size
         for entity in &changed_entities.to_clear {
-            clear_entity(*entity, &mut term, window, &previous_details).unwrap();
+            clear_entity(
+                *entity,
+                term,
+                window,
+                &camera,
+                &previous_details,
+                &mut terminal,
+            )
+            .unwrap();
         }
     }
 
+    // When the render graph composites the screen, it draws everything itself
+    if compositing {
+        return;
+    }
+
+    // Blended sprites need to know what's beneath them, so keep track of what's on the terminal. Output is only
+    // read back in right before it's needed
+    let (width, height) = window.terminal_size();
+    if (screen.width(), screen.height()) != (width, height) {
+        screen.resize(width, height);
+    }
+    let mut read_back = 0;
+
     // Redraw all the changed sprites, either because they moved, or because they changed their shape
     for entity in &changed_entities.to_draw {
-        draw_entity(entity.entity, &mut term, window, &sprites, &stylemaps, &all).unwrap();
+        let blended = all.get(entity.entity).is_ok_and(|(.., blend_mode)| {
+            blend_mode.is_some_and(|mode| *mode != BlendMode::Replace)
+        });
+        if blended {
+            screen.write(&term[read_back..]);
+            read_back = term.len();
+        }
+        draw_entity(
+            entity.entity,
+            term,
+            window,
+            &camera,
+            &sprites,
+            &stylemaps,
+            &theme,
+            &screen,
+            &mut terminal,
+            &all,
+        )
+        .unwrap();
+    }
+    screen.write(&term[read_back..]);
+}
+
+/// Gives entities with a [`ColoredSprite`] the sprite and stylemap it splits into, and keeps those up to date
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_colored_sprites(
+    mut parts: Local<HashMap<AssetId<ColoredSprite>, (Handle<Sprite>, Handle<StyleMap>)>>,
+    mut commands: Commands,
+    mut asset_events: EventReader<AssetEvent<ColoredSprite>>,
+    colored_sprites: Res<Assets<ColoredSprite>>,
+    mut sprites: ResMut<Assets<Sprite>>,
+    mut stylemaps: ResMut<Assets<StyleMap>>,
+    entities: Query<(
+        Entity,
+        Ref<Handle<ColoredSprite>>,
+        Option<&Handle<Sprite>>,
+        Option<&Handle<StyleMap>>,
+    )>,
+) {
+    let mut loaded = HashSet::new();
+    for event in asset_events.read() {
+        match event {
+            AssetEvent::Added { id } | AssetEvent::LoadedWithDependencies { id } => {
+                loaded.insert(*id);
+            }
+            // Update the generated assets in place, which redraws every entity using them
+            AssetEvent::Modified { id } => {
+                if let (Some(colored), Some((sprite, stylemap))) =
+                    (colored_sprites.get(*id), parts.get(id))
+                {
+                    let (new_sprite, new_stylemap) = colored.to_parts();
+                    if let Some(sprite) = sprites.get_mut(sprite) {
+                        *sprite = new_sprite;
+                    }
+                    if let Some(stylemap) = stylemaps.get_mut(stylemap) {
+                        *stylemap = new_stylemap;
+                    }
+                }
+            }
+            AssetEvent::Removed { id } | AssetEvent::Unused { id } => {
+                parts.remove(id);
+            }
+        }
+    }
+
+    for (entity, handle, sprite, stylemap) in &entities {
+        if !handle.is_changed() && !loaded.contains(&handle.id()) {
+            continue;
+        }
+        // Entities whose colored sprite is still loading are taken care of once it has
+        let Some(colored) = colored_sprites.get(&*handle) else {
+            continue;
+        };
+        let (sprite_handle, stylemap_handle) = parts.entry(handle.id()).or_insert_with(|| {
+            let (sprite, stylemap) = colored.to_parts();
+            (sprites.add(sprite), stylemaps.add(stylemap))
+        });
+        if sprite != Some(sprite_handle) || stylemap != Some(stylemap_handle) {
+            commands
+                .entity(entity)
+                .insert((sprite_handle.clone(), stylemap_handle.clone()));
+        }
+    }
+}
+
+/// Blinks text on terminals that can't, if the app asked for it
+pub(crate) fn software_blink(
+    mut elapsed: Local<std::time::Duration>,
+    time: Res<Time>,
+    mut window: Query<&mut CrosstermWindow>,
+    mut stylemaps: ResMut<Assets<StyleMap>>,
+) {
+    let Ok(mut window) = window.get_single_mut() else {
+        return;
+    };
+    let Some(interval) = window.software_blink.filter(|_| !window.native_blink) else {
+        return;
+    };
+
+    *elapsed += time.delta();
+    if *elapsed < interval {
+        return;
+    }
+    *elapsed = std::time::Duration::ZERO;
+    window.blink_hidden = !window.blink_hidden;
+
+    // Touching the stylemaps gets everything that uses them redrawn
+    let blinking: Vec<_> = stylemaps
+        .iter()
+        .filter(|(_, stylemap)| stylemap.has_blink())
+        .map(|(id, _)| id)
+        .collect();
+    for id in blinking {
+        stylemaps.get_mut(id);
+    }
+}
+
+/// Switch terminal modes on or off when they're changed on the window
+pub(crate) fn update_terminal_modes(
+    mut mouse_captured: Local<Option<bool>>,
+    window: Query<&CrosstermWindow, Changed<CrosstermWindow>>,
+    mut commands: ResMut<TerminalCommands>,
+) {
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+
+    // The window turns on whatever was in the settings when it's created
+    let captured = mouse_captured.get_or_insert(window.mouse_capture());
+    if *captured != window.mouse_capture() {
+        *captured = window.mouse_capture();
+        if *captured {
+            commands.queue(crossterm::event::EnableMouseCapture);
+        } else {
+            commands.queue(crossterm::event::DisableMouseCapture);
+        }
+    }
+}
+
+/// Place the cursor and push everything queued this frame out to the terminal. This runs after
+/// `TerminalPostRender` so user output is included in the same write
+pub(crate) fn crossterm_flush(
+    window: Query<&CrosstermWindow>,
+    cursor: Res<Cursor>,
+    mut commands: ResMut<TerminalCommands>,
+    mut output: ResMut<FrameOutput>,
+    pipelined: Option<Res<PipelinedRendering>>,
+) {
+    let window = window.single();
+    let term = &mut output.0;
+
+    // User supplied commands go out after everything else this frame, but before the cursor is placed
+    if !commands.is_empty() {
+        term.extend_from_slice(commands.take().as_bytes());
     }
 
     // Draw the cursor at the right position, if needed
+    let view = window.viewport();
     if !cursor.hidden
+        && !window.is_too_small()
         && cursor.x >= 0
-        && cursor.x < window.width as i32
+        && cursor.x < view.width as i32
         && cursor.y >= 0
-        && cursor.y < window.height as i32
+        && cursor.y < view.height as i32
     {
         queue!(
             term,
-            crossterm::cursor::MoveTo(cursor.x as u16, cursor.y as u16),
+            crossterm::cursor::MoveTo(cursor.x as u16 + view.x, cursor.y as u16 + view.y),
             crossterm::cursor::Show
         )
         .unwrap();
     }
 
-    term.flush().unwrap();
+    // The pipelined render app writes the frame along with the screen
+    if term.is_empty() || pipelined.is_some() {
+        return;
+    }
+    if let Err(error) = window.backend.write_frame(term) {
+        warn!("Could not write the frame to the terminal: {error}");
+    }
+    term.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_cursor_only_moves_when_it_has_to() {
+        let mut terminal = TerminalState::default();
+        let mut term = Vec::new();
+        terminal.move_to(&mut term, 2, 1).unwrap();
+        assert_eq!(term, b"\x1b[2;3H");
+
+        term.clear();
+        terminal.move_to(&mut term, 2, 1).unwrap();
+        assert!(term.is_empty());
+
+        terminal.move_to(&mut term, 5, 1).unwrap();
+        assert_eq!(term, b"\x1b[3C");
+
+        // Going backwards or to another row needs the whole position
+        term.clear();
+        terminal.move_to(&mut term, 1, 1).unwrap();
+        assert_eq!(term, b"\x1b[2;2H");
+        term.clear();
+        terminal.move_to(&mut term, 4, 3).unwrap();
+        assert_eq!(term, b"\x1b[4;5H");
+    }
+
+    #[test]
+    fn printing_moves_the_cursor_along() {
+        let mut terminal = TerminalState {
+            cursor: Some((2, 1)),
+            ..default()
+        };
+        terminal.printed(10, "abc");
+        assert_eq!(terminal.cursor, Some((5, 1)));
+
+        // Unknown until the cursor is moved somewhere
+        terminal.printed(10, "世");
+        assert_eq!(terminal.cursor, None);
+        terminal.printed(10, "a");
+        assert_eq!(terminal.cursor, None);
+
+        // Terminals differ on where the last column leaves the cursor
+        terminal.cursor = Some((8, 0));
+        terminal.printed(10, "ab");
+        assert_eq!(terminal.cursor, None);
+    }
+
+    fn cell(grapheme: &str, foreground: Color, background: Color) -> Cell {
+        Cell {
+            grapheme: grapheme.to_string(),
+            style: Style::with_colors(Colors::new(foreground, background)),
+        }
+    }
+
+    #[test]
+    fn blending_with_what_is_below() {
+        let below = cell("b", Color::Red, Color::Blue);
+        let style = Style::with_colors(Colors::new(Color::Green, Color::Yellow));
+        let previous = Style::default();
+        let colors = |(grapheme, style): (&str, Style)| {
+            (
+                grapheme.to_string(),
+                style.colors.foreground,
+                style.colors.background,
+            )
+        };
+        let blended = |mode| colors(blend(mode, "a", &style, &previous, Some(&below)));
+
+        assert_eq!(
+            blended(BlendMode::Replace),
+            ("a".into(), Some(Color::Green), Some(Color::Yellow))
+        );
+        assert_eq!(
+            blended(BlendMode::Foreground),
+            ("a".into(), Some(Color::Green), Some(Color::Blue))
+        );
+        assert_eq!(
+            blended(BlendMode::Background),
+            ("b".into(), Some(Color::Red), Some(Color::Yellow))
+        );
+        let (grapheme, inverted) = blend(BlendMode::Invert, "a", &style, &previous, Some(&below));
+        assert_eq!(grapheme, "b");
+        assert_eq!(inverted.colors, below.style.colors);
+        assert!(inverted.attributes.has(Attribute::Reverse));
+
+        // With nothing below, the sprite is drawn as it is
+        for mode in [
+            BlendMode::Foreground,
+            BlendMode::Background,
+            BlendMode::Invert,
+        ] {
+            assert_eq!(
+                colors(blend(mode, "a", &style, &previous, None)),
+                ("a".into(), Some(Color::Green), Some(Color::Yellow))
+            );
+        }
+    }
+
+    #[test]
+    fn blending_twice_gives_the_same_result() {
+        let below = cell("b", Color::Red, Color::Blue);
+        let style = Style::with_colors(Colors::new(Color::Green, Color::Yellow));
+        let previous = Style::default();
+        for mode in [
+            BlendMode::Replace,
+            BlendMode::Foreground,
+            BlendMode::Background,
+            BlendMode::Invert,
+        ] {
+            let (grapheme, blended) = blend(mode, "a", &style, &previous, Some(&below));
+            let once = Cell {
+                grapheme: grapheme.to_string(),
+                style: blended,
+            };
+            let (grapheme, twice) = blend(mode, "a", &style, &previous, Some(&once));
+            assert_eq!(
+                (grapheme, twice),
+                (once.grapheme.as_str(), once.style),
+                "{mode:?}"
+            );
+        }
+    }
 }
@@ -0,0 +1,198 @@
+//! Drawing and laying out text.
+
+use bevy::prelude::*;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::components::{Position, Style, Visible};
+use crate::layout::Size;
+use crate::render::{CellBuffer, TerminalRender};
+
+/// Lines of text, drawn one row per line. Give the entity a [`Wrap`] to wrap long lines, e.g.
+/// `commands.spawn((TextBundle::new("It was a dark and stormy night..."), Wrap::new(40)))`
+#[derive(Component, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Text {
+    text: String,
+    style: Style,
+    // Where lines are wrapped, set from the entity's Wrap
+    wrap_width: Option<u16>,
+}
+
+impl Text {
+    pub fn new<T: ToString>(text: T) -> Text {
+        Text {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text<T: ToString>(&mut self, text: T) -> &mut Self {
+        self.text = text.to_string();
+        self
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn set_style(&mut self, style: Style) -> &mut Self {
+        self.style = style;
+        self
+    }
+
+    /// The rows the text is drawn on, after wrapping
+    pub fn lines(&self) -> Vec<String> {
+        match self.wrap_width {
+            Some(width) => wrap(&self.text, width as usize),
+            None => self.text.lines().map(str::to_string).collect(),
+        }
+    }
+}
+
+impl TerminalRender for Text {
+    fn size(&self) -> (u16, u16) {
+        let lines = self.lines();
+        let width = lines
+            .iter()
+            .map(|line| line.graphemes(true).count())
+            .max()
+            .unwrap_or(0);
+        (width as u16, lines.len() as u16)
+    }
+
+    fn render(&self, buffer: &mut CellBuffer) {
+        buffer.set_style(self.style);
+        for (y, line) in self.lines().iter().enumerate() {
+            buffer.print(0, y as u16, line, self.style);
+        }
+    }
+}
+
+#[derive(Bundle, Debug, Default)]
+pub struct TextBundle {
+    pub text: Text,
+    pub position: Position,
+    pub visible: Visible,
+}
+
+impl TextBundle {
+    pub fn new<T: ToString>(text: T) -> TextBundle {
+        TextBundle {
+            text: Text::new(text),
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_position(mut self, position: Position) -> Self {
+        self.position = position;
+        self
+    }
+
+    #[must_use]
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.text.style = style;
+        self
+    }
+}
+
+/// Wraps an entity's [`Text`] at word boundaries so no row is wider than `max_width`. If the entity has a [`Size`]
+/// too, the text wraps at the narrower of the two, so it rewraps as the terminal is resized
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wrap {
+    pub max_width: u16,
+}
+
+impl Wrap {
+    pub fn new(max_width: u16) -> Wrap {
+        Wrap { max_width }
+    }
+}
+
+/// How many columns tabs move the cursor to a multiple of, the same as the sprite loader's default
+pub const TAB_WIDTH: usize = 8;
+
+/// How many columns a grapheme takes up on the terminal: 2 for wide characters like CJK and most emoji, 0 for
+/// control characters and lone combining marks, and 1 for everything else
+pub fn grapheme_width(grapheme: &str) -> usize {
+    if grapheme.chars().any(char::is_control) {
+        return 0;
+    }
+    grapheme.width().min(2)
+}
+
+/// How many columns a line takes up on the terminal, with tabs moving to the next multiple of `tab_width`
+pub fn line_width(line: &str, tab_width: usize) -> usize {
+    let tab_width = tab_width.max(1);
+    line.graphemes(true).fold(0, |column, grapheme| {
+        if grapheme == "\t" {
+            column + tab_width - column % tab_width
+        } else {
+            column + grapheme_width(grapheme)
+        }
+    })
+}
+
+/// How much of the terminal `text` takes up, as (columns, rows). Tabs are [`TAB_WIDTH`] wide
+pub fn measure_text(text: &str) -> (u16, u16) {
+    let columns = text
+        .lines()
+        .map(|line| line_width(line, TAB_WIDTH))
+        .max()
+        .unwrap_or(0);
+    (columns as u16, text.lines().count() as u16)
+}
+
+/// Break `text` into lines at most `width` columns wide, as measured by [`line_width`]. Lines are broken at spaces
+/// where possible, and words too long to fit on a line of their own are split. Line breaks already in the text are
+/// kept
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut line_width = 0;
+        for word in paragraph.split(' ').filter(|word| !word.is_empty()) {
+            let word_width: usize = word.graphemes(true).map(grapheme_width).sum();
+            if line_width > 0 && line_width + 1 + word_width <= width {
+                line.push(' ');
+                line.push_str(word);
+                line_width += 1 + word_width;
+                continue;
+            }
+            if line_width > 0 {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            for grapheme in word.graphemes(true) {
+                let grapheme_width = grapheme_width(grapheme);
+                if line_width > 0 && line_width + grapheme_width > width {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                }
+                line.push_str(grapheme);
+                line_width += grapheme_width;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Hands each text the width to wrap at, which gets it drawn again if that changed
+pub(crate) fn update_text_wrap(mut texts: Query<(&mut Text, Option<&Wrap>, Option<&Size>)>) {
+    for (mut text, wrap, size) in &mut texts {
+        let width = match (wrap, size) {
+            (Some(wrap), Some(size)) => Some(wrap.max_width.min(size.resolved().0)),
+            (Some(wrap), None) => Some(wrap.max_width),
+            (None, _) => None,
+        };
+        if text.wrap_width != width {
+            text.wrap_width = width;
+        }
+    }
+}
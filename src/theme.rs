@@ -0,0 +1,224 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::components::{Color, Colors, Style};
+use crate::ForceRedraw;
+
+/// Named colors that stylemaps and widgets refer to by what they're for ("text.warning", "ui.border") rather than by
+/// the color itself. Replacing the resource (e.g. `*theme = Theme::light()`) restyles and redraws everything.
+///
+/// The built-in presets all define these names, apps are free to add their own:
+///
+/// | Name             | Used for                               |
+/// |------------------|----------------------------------------|
+/// | `text`           | Normal text                            |
+/// | `text.muted`     | Less important text, hints             |
+/// | `text.highlight` | Text that should stand out             |
+/// | `text.success`   | Good news                              |
+/// | `text.warning`   | Warnings                               |
+/// | `text.error`     | Errors                                 |
+/// | `ui.background`  | Background of panels and popups        |
+/// | `ui.border`      | Borders and separators                 |
+/// | `ui.selection`   | Background of selected items           |
+///
+/// A theme also has numbered color pairs, a foreground and background together, like curses has. Stylemaps can give
+/// single cells a pair with [`StyleMap::pairs`](crate::components::StyleMap::pairs), so a whole map of tiles can
+/// switch from day to night colors with the theme.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    name: String,
+    colors: HashMap<String, Color>,
+    pairs: HashMap<u16, Colors>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+impl Theme {
+    /// An empty theme. Anything that refers to it falls back to its own colors
+    pub fn new<T: ToString>(name: T) -> Theme {
+        Theme {
+            name: name.to_string(),
+            colors: HashMap::new(),
+            pairs: HashMap::new(),
+        }
+    }
+
+    /// For terminals with a dark background
+    pub fn dark() -> Theme {
+        Theme::new("dark")
+            .with("text", Color::White)
+            .with("text.muted", Color::DarkGrey)
+            .with("text.highlight", Color::Cyan)
+            .with("text.success", Color::Green)
+            .with("text.warning", Color::Yellow)
+            .with("text.error", Color::Red)
+            .with("ui.background", Color::Black)
+            .with("ui.border", Color::Grey)
+            .with("ui.selection", Color::DarkBlue)
+    }
+
+    /// For terminals with a light background
+    pub fn light() -> Theme {
+        Theme::new("light")
+            .with("text", Color::Black)
+            .with("text.muted", Color::DarkGrey)
+            .with("text.highlight", Color::DarkBlue)
+            .with("text.success", Color::DarkGreen)
+            .with("text.warning", Color::DarkYellow)
+            .with("text.error", Color::DarkRed)
+            .with("ui.background", Color::White)
+            .with("ui.border", Color::DarkGrey)
+            .with("ui.selection", Color::Grey)
+    }
+
+    /// Pure black and white with bright accents, for readability
+    pub fn high_contrast() -> Theme {
+        Theme::new("high_contrast")
+            .with("text", Color::White)
+            .with("text.muted", Color::White)
+            .with("text.highlight", Color::Yellow)
+            .with("text.success", Color::Green)
+            .with("text.warning", Color::Yellow)
+            .with("text.error", Color::Red)
+            .with("ui.background", Color::Black)
+            .with("ui.border", Color::White)
+            .with("ui.selection", Color::Blue)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get(&self, name: &str) -> Option<Color> {
+        self.colors.get(name).copied()
+    }
+
+    pub fn set<T: ToString>(&mut self, name: T, color: Color) -> &mut Self {
+        self.colors.insert(name.to_string(), color);
+        self
+    }
+
+    /// Like [`Theme::set`], for building themes
+    #[must_use]
+    pub fn with<T: ToString>(mut self, name: T, color: Color) -> Self {
+        self.set(name, color);
+        self
+    }
+
+    /// All the named colors in the theme
+    pub fn colors(&self) -> impl Iterator<Item = (&str, Color)> {
+        self.colors
+            .iter()
+            .map(|(name, color)| (name.as_str(), *color))
+    }
+
+    pub fn pair(&self, number: u16) -> Option<Colors> {
+        self.pairs.get(&number).copied()
+    }
+
+    /// Set the colors of pair `number`. Colors the pair leaves as `None` aren't changed where it's used
+    pub fn set_pair(&mut self, number: u16, colors: Colors) -> &mut Self {
+        self.pairs.insert(number, colors);
+        self
+    }
+
+    /// Like [`Theme::set_pair`], for building themes
+    #[must_use]
+    pub fn with_pair(mut self, number: u16, colors: Colors) -> Self {
+        self.set_pair(number, colors);
+        self
+    }
+
+    /// All the color pairs in the theme, by number
+    pub fn pairs(&self) -> impl Iterator<Item = (u16, Colors)> + '_ {
+        self.pairs.iter().map(|(number, colors)| (*number, *colors))
+    }
+
+    /// Replace the colors of `style` with pair `number`'s. A pair the theme doesn't have leaves the style as it was
+    pub fn apply_pair(&self, number: u16, style: Style) -> Style {
+        let mut style = style;
+        if let Some(colors) = self.pair(number) {
+            style.colors = colors.with_default(style.colors);
+        }
+        style
+    }
+
+    /// Replace the colors of `style` with the ones `names` refer to. Names the theme doesn't have leave the color
+    /// as it was. Named colors win over the pair's
+    pub fn apply(&self, names: &ThemeColors, style: Style) -> Style {
+        let mut style = match names.pair {
+            Some(number) => self.apply_pair(number, style),
+            None => style,
+        };
+        let lookup = |name: &Option<String>| name.as_deref().and_then(|name| self.get(name));
+        if let Some(foreground) = lookup(&names.foreground) {
+            style.colors.foreground = Some(foreground);
+        }
+        if let Some(background) = lookup(&names.background) {
+            style.colors.background = Some(background);
+        }
+        style
+    }
+}
+
+/// Names of [`Theme`] colors to use instead of fixed colors, e.g. in a [`StyleMap`](crate::components::StyleMap)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThemeColors {
+    #[serde(default)]
+    pub foreground: Option<String>,
+    #[serde(default)]
+    pub background: Option<String>,
+    /// A numbered color pair, see [`Theme::pair`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pair: Option<u16>,
+}
+
+impl ThemeColors {
+    pub fn new<F: ToString, B: ToString>(foreground: F, background: B) -> Self {
+        ThemeColors {
+            foreground: Some(foreground.to_string()),
+            background: Some(background.to_string()),
+            pair: None,
+        }
+    }
+
+    pub fn fg<T: ToString>(foreground: T) -> Self {
+        ThemeColors {
+            foreground: Some(foreground.to_string()),
+            background: None,
+            pair: None,
+        }
+    }
+
+    pub fn bg<T: ToString>(background: T) -> Self {
+        ThemeColors {
+            foreground: None,
+            background: Some(background.to_string()),
+            pair: None,
+        }
+    }
+
+    /// The colors of a numbered color pair, see [`Theme::pair`]
+    pub fn pair(number: u16) -> Self {
+        ThemeColors {
+            pair: Some(number),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.foreground.is_none() && self.background.is_none() && self.pair.is_none()
+    }
+}
+
+/// Everything has to be restyled when the theme changes
+pub(crate) fn redraw_on_theme_change(theme: Res<Theme>, mut redraw: EventWriter<ForceRedraw>) {
+    if theme.is_changed() && !theme.is_added() {
+        redraw.send(ForceRedraw);
+    }
+}
@@ -0,0 +1,131 @@
+//! Recording sessions in the ttyrec format, which tools like `ttyplay` and `ipbt` can play back.
+//!
+//! Wrap the backend in a [`TtyrecBackend`] to record everything written to the terminal:
+//!
+//! ```no_run
+//! # use bevy::prelude::*;
+//! # use bevy_crossterm::prelude::*;
+//! # use bevy_crossterm::ttyrec::TtyrecBackend;
+//! let file = std::fs::File::create("session.ttyrec").unwrap();
+//! App::new().insert_resource(SelectedBackend::new(TtyrecBackend::new(CrosstermBackend, file)));
+//! ```
+//!
+//! [`play`] replays a recording on the terminal, without needing an app.
+
+use std::io::{Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crossterm::event::Event;
+
+use crate::backend::TerminalBackend;
+
+/// A backend that records everything written to another backend. Each flush becomes one ttyrec frame
+pub struct TtyrecBackend<B, W> {
+    backend: B,
+    recording: W,
+    frame: Vec<u8>,
+}
+
+impl<B: TerminalBackend, W: Write + Send + Sync + 'static> TtyrecBackend<B, W> {
+    pub fn new(backend: B, recording: W) -> TtyrecBackend<B, W> {
+        TtyrecBackend {
+            backend,
+            recording,
+            frame: Vec::new(),
+        }
+    }
+}
+
+impl<B: TerminalBackend, W: Write + Send + Sync + 'static> TerminalBackend for TtyrecBackend<B, W> {
+    fn enable_raw_mode(&mut self) -> std::io::Result<()> {
+        self.backend.enable_raw_mode()
+    }
+
+    fn disable_raw_mode(&mut self) -> std::io::Result<()> {
+        self.backend.disable_raw_mode()
+    }
+
+    fn size(&self) -> std::io::Result<(u16, u16)> {
+        self.backend.size()
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.frame.extend_from_slice(bytes);
+        self.backend.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.backend.flush()?;
+        if self.frame.is_empty() {
+            return Ok(());
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        write_frame(&mut self.recording, time, &self.frame)?;
+        self.frame.clear();
+        self.recording.flush()
+    }
+
+    fn poll(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        self.backend.poll(timeout)
+    }
+
+    fn read(&mut self) -> std::io::Result<Event> {
+        self.backend.read()
+    }
+
+    fn supports_keyboard_enhancement(&mut self) -> bool {
+        self.backend.supports_keyboard_enhancement()
+    }
+
+    fn query_background(&mut self) -> Option<(u8, u8, u8)> {
+        self.backend.query_background()
+    }
+}
+
+/// Write a single frame. Each one has a header of the seconds and microseconds it was recorded at and the length of
+/// the data, as little endian 32 bit integers
+pub fn write_frame<W: Write>(writer: &mut W, time: Duration, data: &[u8]) -> std::io::Result<()> {
+    let length = u32::try_from(data.len())
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    writer.write_all(&(time.as_secs() as u32).to_le_bytes())?;
+    writer.write_all(&time.subsec_micros().to_le_bytes())?;
+    writer.write_all(&length.to_le_bytes())?;
+    writer.write_all(data)
+}
+
+/// Read every frame of a recording, with the time it was recorded at
+pub fn read_frames<R: Read>(mut reader: R) -> std::io::Result<Vec<(Duration, Vec<u8>)>> {
+    let mut frames = Vec::new();
+    let mut header = [0; 12];
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(frames),
+            Err(err) => return Err(err),
+        }
+        let field = |i: usize| u32::from_le_bytes(header[i * 4..i * 4 + 4].try_into().unwrap());
+        let time = Duration::new(field(0) as u64, field(1).saturating_mul(1000));
+        let mut data = vec![0; field(2) as usize];
+        reader.read_exact(&mut data)?;
+        frames.push((time, data));
+    }
+}
+
+/// Play a recording back on stdout, with the original timing scaled by `speed` (2.0 plays it twice as fast)
+pub fn play<R: Read>(reader: R, speed: f32) -> std::io::Result<()> {
+    let frames = read_frames(reader)?;
+    let mut stdout = std::io::stdout().lock();
+    let mut previous = None;
+    for (time, data) in frames {
+        if let Some(previous) = previous {
+            let delay = time.saturating_sub(previous);
+            std::thread::sleep(delay.div_f32(speed.max(f32::EPSILON)));
+        }
+        previous = Some(time);
+        stdout.write_all(&data)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,734 @@
+//! Ready made widgets for common bits of UI.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use crossterm::event::{KeyCode, KeyEventKind};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::camera::TerminalCamera;
+use crate::components::{ColoredSprite, Corner, Position, Sprite, Style, StyleMap, Visible};
+use crate::focus::{Focusable, FocusedEntity};
+use crate::layout::Size;
+use crate::render::{CellBuffer, TerminalRender};
+use crate::{CrosstermKeyEventWrapper, CrosstermWindow};
+
+/// Where toasts show up and how they move. Toasts that are already showing move over when this changes
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ToastSettings {
+    corner: Corner,
+    slide: Duration,
+}
+
+impl Default for ToastSettings {
+    fn default() -> Self {
+        ToastSettings {
+            corner: Corner::BottomRight,
+            slide: Duration::from_millis(200),
+        }
+    }
+}
+
+impl ToastSettings {
+    pub fn corner(&self) -> Corner {
+        self.corner
+    }
+
+    /// The corner toasts stack up in, the oldest closest to it. Defaults to the bottom right
+    pub fn set_corner(&mut self, corner: Corner) -> &mut Self {
+        self.corner = corner;
+        self
+    }
+
+    pub fn slide(&self) -> Duration {
+        self.slide
+    }
+
+    /// How long toasts take to slide in from the side of the screen, and back out again
+    pub fn set_slide(&mut self, slide: Duration) -> &mut Self {
+        self.slide = slide;
+        self
+    }
+}
+
+/// A short message that slides in, stays for a while, then slides out and despawns itself, e.g.
+/// `commands.spawn(Toast::show("Found a key", Duration::from_secs(2)))`. Toasts shown at the same time stack up, see
+/// [`ToastSettings`]
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct Toast {
+    lines: Vec<String>,
+    style: Style,
+    duration: Duration,
+    elapsed: Duration,
+}
+
+impl Toast {
+    /// A toast showing `text` for `duration`, not counting the time it takes to slide in and out
+    pub fn show<T: ToString>(text: T, duration: Duration) -> ToastBundle {
+        ToastBundle {
+            toast: Toast {
+                lines: text.to_string().lines().map(str::to_string).collect(),
+                style: Style::with_attrib(crossterm::style::Attribute::Reverse),
+                duration,
+                elapsed: Duration::ZERO,
+            },
+            position: Position::new(0, 0, i32::MAX - 1),
+            visible: Visible::default(),
+        }
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn set_style(&mut self, style: Style) -> &mut Self {
+        self.style = style;
+        self
+    }
+
+    // Width without the padding
+    fn text_width(&self) -> usize {
+        self.lines
+            .iter()
+            .map(|line| line.graphemes(true).count())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl TerminalRender for Toast {
+    fn size(&self) -> (u16, u16) {
+        (self.text_width() as u16 + 2, self.lines.len() as u16)
+    }
+
+    fn render(&self, buffer: &mut CellBuffer) {
+        buffer.set_style(self.style);
+        for (y, line) in self.lines.iter().enumerate() {
+            buffer.print(1, y as u16, line, self.style);
+        }
+    }
+}
+
+#[derive(Bundle, Debug)]
+pub struct ToastBundle {
+    pub toast: Toast,
+    pub position: Position,
+    pub visible: Visible,
+}
+
+impl ToastBundle {
+    #[must_use]
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.toast.style = style;
+        self
+    }
+}
+
+pub(crate) fn update_toasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<ToastSettings>,
+    mut toasts: Query<(Entity, &mut Toast, &mut Position)>,
+    camera: Query<&TerminalCamera>,
+    window: Query<&CrosstermWindow>,
+) {
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+    let view = window.viewport();
+    let camera = camera.get_single().copied().unwrap_or_default();
+    let slide = settings.slide;
+
+    // Oldest first, so they stay put as new ones are added
+    let mut showing = Vec::new();
+    for (entity, mut toast, _) in &mut toasts {
+        // The time isn't drawn, so don't let it count as a change
+        let toast = toast.bypass_change_detection();
+        toast.elapsed += time.delta();
+        if toast.elapsed >= slide * 2 + toast.duration {
+            commands.entity(entity).despawn();
+        } else {
+            showing.push((toast.elapsed, entity));
+        }
+    }
+    showing.sort_by_key(|(elapsed, _)| std::cmp::Reverse(*elapsed));
+
+    let mut offset = 0;
+    for (elapsed, entity) in showing {
+        let (_, toast, mut position) = toasts.get_mut(entity).unwrap();
+        let (width, height) = toast.size();
+
+        // How much of the toast is on screen while it slides in and out
+        let remaining = (slide * 2 + toast.duration).saturating_sub(elapsed);
+        let shown = if slide.is_zero() {
+            1.0
+        } else {
+            (elapsed.min(remaining).as_secs_f32() / slide.as_secs_f32()).min(1.0)
+        };
+        let hidden = width - (width as f32 * shown).round() as u16;
+
+        let (x, y) = settings
+            .corner
+            .place((view.width, view.height), (width, height));
+        let x = if settings.corner.is_left() {
+            x as i32 - hidden as i32
+        } else {
+            x as i32 + hidden as i32
+        };
+        let y = if settings.corner.is_top() {
+            y as i32 + offset
+        } else {
+            y as i32 - offset
+        };
+        offset += height as i32;
+
+        let (x, y) = (camera.x + x, camera.y + y);
+        if position.x != x || position.y != y {
+            position.x = x;
+            position.y = y;
+        }
+    }
+}
+
+/// The edge of the screen a [`StatusBar`] sits on
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Edge {
+    Top,
+    #[default]
+    Bottom,
+}
+
+/// A bar across the full width of the top or bottom of the screen, showing segments of text side by side. It keeps to
+/// its edge when the terminal is resized or the camera moves. Segments that don't fit are cut short with an
+/// ellipsis, or wrap onto more rows with [`StatusBar::set_wrap`]. Spawn it with [`StatusBarBundle`]
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct StatusBar {
+    edge: Edge,
+    segments: Vec<String>,
+    separator: String,
+    wrap: bool,
+    style: Style,
+    width: u16,
+}
+
+impl Default for StatusBar {
+    fn default() -> Self {
+        StatusBar::new(Edge::default())
+    }
+}
+
+impl StatusBar {
+    pub fn new(edge: Edge) -> StatusBar {
+        StatusBar {
+            edge,
+            segments: Vec::new(),
+            separator: " | ".to_string(),
+            wrap: false,
+            style: Style::with_attrib(crossterm::style::Attribute::Reverse),
+            width: 0,
+        }
+    }
+
+    pub fn edge(&self) -> Edge {
+        self.edge
+    }
+
+    pub fn set_edge(&mut self, edge: Edge) -> &mut Self {
+        self.edge = edge;
+        self
+    }
+
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    pub fn set_segments<I: IntoIterator<Item = T>, T: ToString>(
+        &mut self,
+        segments: I,
+    ) -> &mut Self {
+        self.segments = segments
+            .into_iter()
+            .map(|segment| segment.to_string())
+            .collect();
+        self
+    }
+
+    /// Change the text of one segment, adding empty segments before it if there aren't enough
+    pub fn set_segment<T: ToString>(&mut self, index: usize, text: T) -> &mut Self {
+        if self.segments.len() <= index {
+            self.segments.resize(index + 1, String::new());
+        }
+        self.segments[index] = text.to_string();
+        self
+    }
+
+    pub fn separator(&self) -> &str {
+        &self.separator
+    }
+
+    /// What goes between segments. Defaults to `" | "`
+    pub fn set_separator<T: ToString>(&mut self, separator: T) -> &mut Self {
+        self.separator = separator.to_string();
+        self
+    }
+
+    pub fn wrap(&self) -> bool {
+        self.wrap
+    }
+
+    /// Move segments that don't fit onto another row, instead of cutting them short. The bar grows away from its
+    /// edge. Off by default
+    pub fn set_wrap(&mut self, wrap: bool) -> &mut Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn set_style(&mut self, style: Style) -> &mut Self {
+        self.style = style;
+        self
+    }
+
+    // The text of each row, fitted to the width of the screen
+    fn rows(&self) -> Vec<String> {
+        let width = self.width as usize;
+        if !self.wrap {
+            return vec![truncate(&self.segments.join(&self.separator), width)];
+        }
+
+        let separator = self.separator.graphemes(true).count();
+        let mut rows = vec![String::new()];
+        for segment in &self.segments {
+            let row = rows.last_mut().unwrap();
+            let used = row.graphemes(true).count();
+            if used == 0 {
+                *row = truncate(segment, width);
+            } else if used + separator + segment.graphemes(true).count() <= width {
+                row.push_str(&self.separator);
+                row.push_str(segment);
+            } else {
+                rows.push(truncate(segment, width));
+            }
+        }
+        rows
+    }
+}
+
+// Cut `text` down to `width` graphemes, ending with an ellipsis if anything was cut
+fn truncate(text: &str, width: usize) -> String {
+    if text.graphemes(true).count() <= width {
+        return text.to_string();
+    }
+    let mut truncated: String = text.graphemes(true).take(width.saturating_sub(1)).collect();
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+impl TerminalRender for StatusBar {
+    fn size(&self) -> (u16, u16) {
+        (self.width, self.rows().len() as u16)
+    }
+
+    fn render(&self, buffer: &mut CellBuffer) {
+        buffer.set_style(self.style);
+        for (y, row) in self.rows().iter().enumerate() {
+            buffer.print(0, y as u16, row, self.style);
+        }
+    }
+}
+
+/// A status bar along an edge of the screen, e.g.
+/// `commands.spawn(StatusBarBundle::bottom(["HP 10", "Gold 25"]))`
+#[derive(Bundle, Debug, Default)]
+pub struct StatusBarBundle {
+    pub status_bar: StatusBar,
+    pub position: Position,
+    pub visible: Visible,
+}
+
+impl StatusBarBundle {
+    pub fn new<I: IntoIterator<Item = T>, T: ToString>(edge: Edge, segments: I) -> StatusBarBundle {
+        let mut status_bar = StatusBar::new(edge);
+        status_bar.set_segments(segments);
+        StatusBarBundle {
+            status_bar,
+            // Drawn over the game, but under toasts and debugging tools
+            position: Position::new(0, 0, i32::MAX - 2),
+            visible: Visible::default(),
+        }
+    }
+
+    pub fn top<I: IntoIterator<Item = T>, T: ToString>(segments: I) -> StatusBarBundle {
+        StatusBarBundle::new(Edge::Top, segments)
+    }
+
+    pub fn bottom<I: IntoIterator<Item = T>, T: ToString>(segments: I) -> StatusBarBundle {
+        StatusBarBundle::new(Edge::Bottom, segments)
+    }
+
+    #[must_use]
+    pub fn with_wrap(mut self, wrap: bool) -> Self {
+        self.status_bar.wrap = wrap;
+        self
+    }
+
+    #[must_use]
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.status_bar.style = style;
+        self
+    }
+}
+
+/// Stretches status bars to the width of the screen and keeps them on their edge
+pub(crate) fn update_status_bars(
+    mut status_bars: Query<(&mut StatusBar, &mut Position)>,
+    camera: Query<&TerminalCamera>,
+    window: Query<&CrosstermWindow>,
+) {
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+    let view = window.viewport();
+    let camera = camera.get_single().copied().unwrap_or_default();
+
+    for (mut status_bar, mut position) in &mut status_bars {
+        if status_bar.width != view.width {
+            status_bar.width = view.width;
+        }
+        let height = status_bar.size().1;
+        let y = match status_bar.edge {
+            Edge::Top => 0,
+            Edge::Bottom => view.height as i32 - height as i32,
+        };
+
+        let (x, y) = (camera.x, camera.y + y);
+        if position.x != x || position.y != y {
+            position.x = x;
+            position.y = y;
+        }
+    }
+}
+
+/// A panel of any size, drawn by stretching a small colored sprite as a nine-patch, see
+/// [`ColoredSprite::nine_patch`]. Give the entity a [`Size`] as well to size it relative to the screen instead of by
+/// [`NinePatch::size`]. Spawn it with [`NinePatchBundle`]
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct NinePatch {
+    pub source: Handle<ColoredSprite>,
+    /// The columns and rows around the edge of the source that are the panel's border, as (columns, rows)
+    pub border: (u16, u16),
+    /// As (width, height)
+    pub size: (u16, u16),
+}
+
+impl NinePatch {
+    /// A panel `width` by `height` with a border one cell wide
+    pub fn new(source: Handle<ColoredSprite>, width: u16, height: u16) -> NinePatch {
+        NinePatch {
+            source,
+            border: (1, 1),
+            size: (width, height),
+        }
+    }
+
+    #[must_use]
+    pub fn with_border(mut self, columns: u16, rows: u16) -> Self {
+        self.border = (columns, rows);
+        self
+    }
+}
+
+#[derive(Bundle, Debug)]
+pub struct NinePatchBundle {
+    pub nine_patch: NinePatch,
+    pub position: Position,
+    pub visible: Visible,
+}
+
+impl NinePatchBundle {
+    pub fn new(nine_patch: NinePatch, position: Position) -> NinePatchBundle {
+        NinePatchBundle {
+            nine_patch,
+            position,
+            visible: Visible::default(),
+        }
+    }
+}
+
+/// Draws nine-patches again when they change size or their source sprite changes
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_nine_patches(
+    mut commands: Commands,
+    mut asset_events: EventReader<AssetEvent<ColoredSprite>>,
+    colored_sprites: Res<Assets<ColoredSprite>>,
+    mut sprites: ResMut<Assets<Sprite>>,
+    mut stylemaps: ResMut<Assets<StyleMap>>,
+    patches: Query<(
+        Entity,
+        Ref<NinePatch>,
+        Option<Ref<Size>>,
+        Option<&Handle<Sprite>>,
+        Option<&Handle<StyleMap>>,
+    )>,
+) {
+    let changed_sources: HashSet<_> = asset_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Added { id }
+            | AssetEvent::LoadedWithDependencies { id }
+            | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (entity, patch, size, sprite, stylemap) in &patches {
+        if !patch.is_changed()
+            && !size.as_ref().is_some_and(|size| size.is_changed())
+            && !changed_sources.contains(&patch.source.id())
+        {
+            continue;
+        }
+        let Some(source) = colored_sprites.get(&patch.source) else {
+            continue;
+        };
+        let (width, height) = size.map_or(patch.size, |size| size.resolved());
+        let (new_sprite, new_stylemap) = source
+            .nine_patch(
+                (patch.border.0 as usize, patch.border.1 as usize),
+                width as usize,
+                height as usize,
+            )
+            .to_parts();
+
+        // Only touch the assets if something changed, since that gets the entity redrawn
+        match sprite {
+            Some(handle) => {
+                if sprites.get(handle).is_some_and(|old| *old != new_sprite) {
+                    *sprites.get_mut(handle).unwrap() = new_sprite;
+                }
+            }
+            None => {
+                commands.entity(entity).insert(sprites.add(new_sprite));
+            }
+        }
+        match stylemap {
+            Some(handle) => {
+                if stylemaps
+                    .get(handle)
+                    .is_some_and(|old| *old != new_stylemap)
+                {
+                    *stylemaps.get_mut(handle).unwrap() = new_stylemap;
+                }
+            }
+            None => {
+                commands.entity(entity).insert(stylemaps.add(new_stylemap));
+            }
+        }
+    }
+}
+
+/// A scrolling log of messages, the newest at the bottom. Long messages wrap, and the oldest are dropped once there
+/// are more than [`TextLog::capacity`]. It stays scrolled to the bottom as messages come in, unless it's been scrolled
+/// up to read back, and Page Up and Page Down scroll it. If the entity is [`Focusable`], it only scrolls while it has
+/// focus. Spawn it with [`TextLogBundle`]
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct TextLog {
+    messages: VecDeque<(String, Style)>,
+    capacity: usize,
+    size: (u16, u16),
+    // How many rows up from the bottom it's scrolled
+    scroll: usize,
+    style: Style,
+}
+
+impl TextLog {
+    /// An empty log `width` by `height`, that keeps the last 1000 messages
+    pub fn new(width: u16, height: u16) -> TextLog {
+        TextLog {
+            messages: VecDeque::new(),
+            capacity: 1000,
+            size: (width, height),
+            scroll: 0,
+            style: Style::default(),
+        }
+    }
+
+    /// Add a message at the bottom
+    pub fn push<T: ToString>(&mut self, message: T) -> &mut Self {
+        let style = self.style;
+        self.push_styled(message, style)
+    }
+
+    pub fn push_styled<T: ToString>(&mut self, message: T, style: Style) -> &mut Self {
+        let message = message.to_string();
+        // Keep what's on screen in place if it's scrolled up
+        if self.scroll > 0 {
+            self.scroll += self.wrap(&message).len();
+        }
+        self.messages.push_back((message, style));
+        while self.messages.len() > self.capacity {
+            self.messages.pop_front();
+        }
+        self.scroll = self.scroll.min(self.max_scroll());
+        self
+    }
+
+    pub fn clear(&mut self) -> &mut Self {
+        self.messages.clear();
+        self.scroll = 0;
+        self
+    }
+
+    /// The messages, oldest first
+    pub fn messages(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().map(|(message, _)| message.as_str())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How many messages are kept before the oldest are dropped
+    pub fn set_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.capacity = capacity;
+        while self.messages.len() > capacity {
+            self.messages.pop_front();
+        }
+        self.scroll = self.scroll.min(self.max_scroll());
+        self
+    }
+
+    /// As (width, height)
+    pub fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    pub fn set_size(&mut self, width: u16, height: u16) -> &mut Self {
+        self.size = (width, height);
+        self.scroll = self.scroll.min(self.max_scroll());
+        self
+    }
+
+    /// The style of messages pushed without one, and of the empty space
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn set_style(&mut self, style: Style) -> &mut Self {
+        self.style = style;
+        self
+    }
+
+    /// Scroll back through older messages
+    pub fn scroll_up(&mut self, rows: usize) -> &mut Self {
+        self.scroll = (self.scroll + rows).min(self.max_scroll());
+        self
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) -> &mut Self {
+        self.scroll = self.scroll.saturating_sub(rows);
+        self
+    }
+
+    pub fn scroll_to_bottom(&mut self) -> &mut Self {
+        self.scroll = 0;
+        self
+    }
+
+    /// Whether the newest message is showing, in which case new messages scroll the log
+    pub fn is_at_bottom(&self) -> bool {
+        self.scroll == 0
+    }
+
+    // Every message wrapped to the width, as rows
+    fn rows(&self) -> Vec<(String, Style)> {
+        self.messages
+            .iter()
+            .flat_map(|(message, style)| self.wrap(message).into_iter().map(|row| (row, *style)))
+            .collect()
+    }
+
+    // An empty message still takes up a row
+    fn wrap(&self, message: &str) -> Vec<String> {
+        let rows = crate::text::wrap(message, self.size.0 as usize);
+        if rows.is_empty() {
+            vec![String::new()]
+        } else {
+            rows
+        }
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.rows().len().saturating_sub(self.size.1 as usize)
+    }
+}
+
+impl TerminalRender for TextLog {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn render(&self, buffer: &mut CellBuffer) {
+        buffer.set_style(self.style);
+        let rows = self.rows();
+        let end = rows.len() - self.scroll.min(rows.len());
+        let start = end.saturating_sub(self.size.1 as usize);
+        for (y, (row, style)) in rows[start..end].iter().enumerate() {
+            buffer.print(0, y as u16, row, *style);
+        }
+    }
+}
+
+#[derive(Bundle, Debug)]
+pub struct TextLogBundle {
+    pub text_log: TextLog,
+    pub position: Position,
+    pub visible: Visible,
+}
+
+impl TextLogBundle {
+    pub fn new(width: u16, height: u16, position: Position) -> TextLogBundle {
+        TextLogBundle {
+            text_log: TextLog::new(width, height),
+            position,
+            visible: Visible::default(),
+        }
+    }
+}
+
+/// Scrolls text logs a page at a time with Page Up and Page Down
+pub(crate) fn scroll_text_logs(
+    mut key_events: EventReader<CrosstermKeyEventWrapper>,
+    focused: Res<FocusedEntity>,
+    mut logs: Query<(Entity, &mut TextLog, Option<&Focusable>)>,
+) {
+    for CrosstermKeyEventWrapper(key_event) in key_events.read() {
+        if key_event.kind == KeyEventKind::Release {
+            continue;
+        }
+        let up = match key_event.code {
+            KeyCode::PageUp => true,
+            KeyCode::PageDown => false,
+            _ => continue,
+        };
+        for (entity, mut log, focusable) in &mut logs {
+            if focusable.is_some() && !focused.is_focused(entity) {
+                continue;
+            }
+            // Keep a row from the last page on screen, so it's clear where the new one carries on from
+            let page = (log.size.1 as usize).saturating_sub(1).max(1);
+            if up {
+                log.scroll_up(page);
+            } else {
+                log.scroll_down(page);
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+use bevy_crossterm::prelude::*;
+use bevy_crossterm::CrosstermKeyEventWrapper;
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+#[derive(Component)]
+struct Player;
+
+fn startup_system(
+    mut commands: Commands,
+    mut sprites: ResMut<Assets<Sprite>>,
+    mut stylemaps: ResMut<Assets<StyleMap>>,
+) {
+    commands.spawn((
+        SpriteBundle {
+            sprite: sprites.add(Sprite::new("@")),
+            stylemap: stylemaps.add(StyleMap::default()),
+            position: Position::with_xy(1, 2),
+            ..Default::default()
+        },
+        Player,
+    ));
+}
+
+fn move_player(
+    mut keys: EventReader<CrosstermKeyEventWrapper>,
+    mut player: Query<&mut Position, With<Player>>,
+) {
+    for key in keys.read() {
+        if key.is_press() && key.char() == Some('l') {
+            player.single_mut().x += 1;
+        }
+    }
+}
+
+// Run a small app with a key press partway through, and return everything it wrote to the terminal
+fn run() -> Vec<u8> {
+    let backend = TestBackend::new(20, 5);
+    let headless = Headless::new(backend.clone()).with_frames(10).with_input(
+        3,
+        Event::Key(KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE)),
+    );
+
+    App::new()
+        .insert_resource(headless)
+        .add_plugins(CrosstermDefaultPlugins)
+        .add_systems(Startup, startup_system)
+        .add_systems(Update, move_player)
+        .run();
+    backend.output()
+}
+
+#[test]
+fn headless_runs_are_reproducible() {
+    let first = run();
+    let second = run();
+    assert_eq!(first, second);
+
+    let mut screen = Screen::new(20, 5);
+    screen.write(&first);
+    let player = screen.get(2, 2).expect("The player is on the screen");
+    assert_eq!(player.grapheme, "@");
+    assert_eq!(
+        screen.get(1, 2).map(|cell| cell.grapheme.as_str()),
+        Some(" ")
+    );
+}